@@ -1,5 +1,2 @@
-rm build/out.c
-cargo run $1
-rm build/out
-gcc build/out.c -I src/ -o build/out
+cargo run build $1 -o build/out
 ./build/out
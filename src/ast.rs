@@ -1,15 +1,93 @@
 use std::fmt;
 
+use crate::bigint::BigInt;
 use crate::symbols::Symbol;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ASTNode {
     Fn(String, Vec<Symbol>, NodeType, Vec<ASTNode>),
-    Call(String, Vec<ASTNode>),
-    Let(Symbol, Box<ASTNode>),
+    // A call with the line its name is on
+    Call(String, Vec<ASTNode>, Line),
+    Let(Symbol, Box<ASTNode>, Bind),
     Var(Symbol),
     Int(i64),
+    // Written with an "n" after the digits, like 10n
+    BigInt(BigInt),
+    // Written with a fraction or an exponent, like 1.0 or 1e3
+    Float(f64),
+    Char(char),
+    // "text", a list of chars with the first character at the head
+    String(String),
     Bool(bool),
+    // "(a, b)", with at least two elements
+    Tuple(Vec<ASTNode>),
+    // An element of a tuple, like "pair.0"
+    Index(Box<ASTNode>, usize),
+    // "struct Point { x: Int, y: Int }", at the top level
+    Struct(String, Vec<(String, NodeType)>),
+    // "Point { x: 1, y: 2 }", with the fields in the order they were declared
+    Record(String, Vec<(String, ASTNode)>),
+    // A field of a record, like "point.x"
+    Field(Box<ASTNode>, String),
+    // A copy of a record with some fields changed, like "point with { x: 3 }"
+    With(Box<ASTNode>, Vec<(String, ASTNode)>),
+}
+
+// The line a node was written on, where the back ends report errors. Calls
+// made by the compiler have line 0. Any two lines are equal, so nodes
+// compare the same wherever they were written.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Line(pub usize);
+
+impl Line {
+    pub fn known(self) -> Option<usize> {
+        (self.0 > 0).then_some(self.0)
+    }
+}
+
+impl PartialEq for Line {
+    fn eq(&self, _other: &Line) -> bool {
+        true
+    }
+}
+
+// What a let-binding binds. "=((a, b), value)" is parsed as a binding holding
+// the tuple, followed by "=(a, (a, b).0)" and "=(b, (a, b).1)", where the
+// first is printed as the original binding and the others are not printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Bind {
+    // A whole tuple pattern, which has no name in the code
+    pub tuple: bool,
+    // Part of the tuple pattern of an earlier binding
+    pub element: bool,
+}
+
+// A "test" block, which is type checked like a function but only run by
+// "flip test". "lines" holds the line each statement starts on.
+#[derive(Debug, Clone)]
+pub struct Test {
+    pub name: String,
+    pub line: usize,
+    pub body: Vec<ASTNode>,
+    pub lines: Vec<usize>,
+    // Lints turned off with #[allow(...)]
+    pub allow: Vec<String>,
+}
+
+// Where a function was defined, for diagnostics, like the fields of "Test"
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub line: usize,
+    pub lines: Vec<usize>,
+    pub allow: Vec<String>,
+}
+
+// A parsed file. "definitions" has an entry for each function and struct in "tree".
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub tree: Vec<ASTNode>,
+    pub definitions: Vec<Definition>,
+    pub tests: Vec<Test>,
 }
 
 impl ASTNode {
@@ -21,15 +99,400 @@ impl ASTNode {
             _ => String::new()
         }
     }
+
+    // How many times a variable is read in the node
+    pub fn count_uses(&self, name: &str) -> usize {
+        let mut counter = UseCounter { name, uses: 0 };
+        counter.visit_node(self);
+        counter.uses
+    }
+}
+
+// Prints nodes as Flip code, which "parser::build_ast" turns back into the
+// same nodes. Expressions go on one line, "flip fmt" can lay them out.
+// Literals are never negative, so negative numbers, which only passes make,
+// are printed as calls of "-" giving the same value.
+impl fmt::Display for ASTNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ASTNode::Fn(name, args, return_type, body) => {
+                let args: Vec<String> =
+                    args.iter().map(|arg| format!("{}: {}", arg.name, arg.symbol_type.source())).collect();
+                write!(f, "{name}({})", args.join(", "))?;
+
+                if *return_type != NodeType::None {
+                    write!(f, ": {}", return_type.source())?;
+                }
+                writeln!(f, " {{")?;
+
+                for node in body.iter().filter(|node| !matches!(node, ASTNode::Let(_, _, bind) if bind.element)) {
+                    writeln!(f, "    {node}")?;
+                }
+                write!(f, "}}")
+            }
+            ASTNode::Call(name, args, _) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{name}({})", args.join(", "))
+            }
+            ASTNode::Let(symbol, value, _) => write!(f, "=({}, {value})", symbol.name),
+            ASTNode::Var(symbol) => write!(f, "{}", symbol.name),
+            // Its magnitude is too large for an Int literal
+            ASTNode::Int(i64::MIN) => write!(f, "-(-({}), 1)", i64::MAX),
+            ASTNode::Int(v) => write_number(f, &v.to_string(), ""),
+            ASTNode::BigInt(v) => write_number(f, &v.to_string(), "n"),
+            // Debug always includes a "." or an exponent, so the literal stays a Float
+            ASTNode::Float(v) => write_number(f, &format!("{v:?}"), ""),
+            ASTNode::Char(v) => write!(f, "'{}'", escape(*v)),
+            ASTNode::String(v) => write!(f, "\"{}\"", v.chars().map(escape).collect::<String>()),
+            ASTNode::Bool(v) => write!(f, "{v}"),
+            ASTNode::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "({})", items.join(", "))
+            }
+            ASTNode::Index(tuple, index) => write!(f, "{tuple}.{index}"),
+            ASTNode::Struct(name, fields) => {
+                let fields: Vec<String> = fields.iter().map(|(field, t)| format!("{field}: {}", t.source())).collect();
+                write!(f, "struct {name} {{ {} }}", fields.join(", "))
+            }
+            ASTNode::Record(name, fields) => write!(f, "{name} {{ {} }}", field_values(fields)),
+            ASTNode::Field(record, field) => write!(f, "{record}.{field}"),
+            ASTNode::With(record, fields) => write!(f, "{record} with {{ {} }}", field_values(fields)),
+        }
+    }
+}
+
+fn write_number(f: &mut fmt::Formatter, number: &str, suffix: &str) -> fmt::Result {
+    match number.strip_prefix('-') {
+        Some(magnitude) => write!(f, "-({magnitude}{suffix})"),
+        None => write!(f, "{number}{suffix}"),
+    }
+}
+
+fn field_values(fields: &[(String, ASTNode)]) -> String {
+    let fields: Vec<String> = fields.iter().map(|(field, value)| format!("{field}: {value}")).collect();
+    fields.join(", ")
+}
+
+// The name of the binding holding a destructured tuple, see "Bind", which
+// prints as the pattern
+pub fn destructured_name(names: &[String]) -> String {
+    format!("({})", names.join(", "))
+}
+
+// The escape for a character in a Flip char or string literal
+pub fn escape(c: char) -> String {
+    match c {
+        '\n' => String::from("\\n"),
+        '\t' => String::from("\\t"),
+        '\r' => String::from("\\r"),
+        '\0' => String::from("\\0"),
+        '\\' | '\'' | '"' => format!("\\{c}"),
+        _ => c.to_string(),
+    }
+}
+
+// A whole program, with a blank line between functions
+pub fn to_source(tree: &[ASTNode]) -> String {
+    let nodes: Vec<String> = tree.iter().map(|node| format!("{node}\n")).collect();
+    nodes.join("\n")
+}
+
+// Passes over the tree implement one of these traits, overriding the methods
+// for the nodes they care about. Each method walks into the children of its
+// node by default, and overrides can call the matching "walk_" function to
+// keep doing so.
+
+// Reads a tree
+pub trait Visitor {
+    fn visit_node(&mut self, node: &ASTNode) {
+        walk_node(self, node)
+    }
+
+    fn visit_fn(&mut self, _name: &str, _args: &[Symbol], _return_type: &NodeType, body: &[ASTNode]) {
+        walk_body(self, body)
+    }
+
+    fn visit_call(&mut self, _name: &str, args: &[ASTNode]) {
+        walk_body(self, args)
+    }
+
+    fn visit_let(&mut self, _symbol: &Symbol, value: &ASTNode) {
+        self.visit_node(value)
+    }
+
+    fn visit_var(&mut self, _symbol: &Symbol) {}
+
+    fn visit_int(&mut self, _value: i64) {}
+
+    fn visit_big_int(&mut self, _value: &BigInt) {}
+
+    fn visit_float(&mut self, _value: f64) {}
+
+    fn visit_char(&mut self, _value: char) {}
+
+    fn visit_string(&mut self, _value: &str) {}
+
+    fn visit_bool(&mut self, _value: bool) {}
+
+    fn visit_tuple(&mut self, items: &[ASTNode]) {
+        walk_body(self, items)
+    }
+
+    fn visit_index(&mut self, tuple: &ASTNode, _index: usize) {
+        self.visit_node(tuple)
+    }
+
+    fn visit_struct(&mut self, _name: &str, _fields: &[(String, NodeType)]) {}
+
+    fn visit_record(&mut self, _name: &str, fields: &[(String, ASTNode)]) {
+        fields.iter().for_each(|(_, value)| self.visit_node(value))
+    }
+
+    fn visit_field(&mut self, record: &ASTNode, _field: &str) {
+        self.visit_node(record)
+    }
+
+    fn visit_with(&mut self, record: &ASTNode, fields: &[(String, ASTNode)]) {
+        self.visit_node(record);
+        fields.iter().for_each(|(_, value)| self.visit_node(value))
+    }
+}
+
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &ASTNode) {
+    match node {
+        ASTNode::Fn(name, args, return_type, body) => visitor.visit_fn(name, args, return_type, body),
+        ASTNode::Call(name, args, _) => visitor.visit_call(name, args),
+        ASTNode::Let(symbol, value, _) => visitor.visit_let(symbol, value),
+        ASTNode::Var(symbol) => visitor.visit_var(symbol),
+        ASTNode::Int(value) => visitor.visit_int(*value),
+        ASTNode::BigInt(value) => visitor.visit_big_int(value),
+        ASTNode::Float(value) => visitor.visit_float(*value),
+        ASTNode::Char(value) => visitor.visit_char(*value),
+        ASTNode::String(value) => visitor.visit_string(value),
+        ASTNode::Bool(value) => visitor.visit_bool(*value),
+        ASTNode::Tuple(items) => visitor.visit_tuple(items),
+        ASTNode::Index(tuple, index) => visitor.visit_index(tuple, *index),
+        ASTNode::Struct(name, fields) => visitor.visit_struct(name, fields),
+        ASTNode::Record(name, fields) => visitor.visit_record(name, fields),
+        ASTNode::Field(record, field) => visitor.visit_field(record, field),
+        ASTNode::With(record, fields) => visitor.visit_with(record, fields),
+    }
+}
+
+pub fn walk_body<V: Visitor + ?Sized>(visitor: &mut V, nodes: &[ASTNode]) {
+    for node in nodes {
+        visitor.visit_node(node);
+    }
+}
+
+struct UseCounter<'a> {
+    name: &'a str,
+    uses: usize,
+}
+
+impl Visitor for UseCounter<'_> {
+    fn visit_var(&mut self, symbol: &Symbol) {
+        self.uses += usize::from(symbol.name == self.name);
+    }
+}
+
+// Changes a tree in place. To replace a node with another kind of node,
+// override "visit_node".
+pub trait VisitorMut {
+    fn visit_node(&mut self, node: &mut ASTNode) {
+        walk_node_mut(self, node)
+    }
+
+    fn visit_fn(&mut self, _name: &mut String, _args: &mut Vec<Symbol>, _return_type: &mut NodeType, body: &mut Vec<ASTNode>) {
+        walk_body_mut(self, body)
+    }
+
+    fn visit_call(&mut self, _name: &mut String, args: &mut Vec<ASTNode>) {
+        walk_body_mut(self, args)
+    }
+
+    fn visit_let(&mut self, _symbol: &mut Symbol, value: &mut ASTNode) {
+        self.visit_node(value)
+    }
+
+    fn visit_var(&mut self, _symbol: &mut Symbol) {}
+
+    fn visit_int(&mut self, _value: &mut i64) {}
+
+    fn visit_big_int(&mut self, _value: &mut BigInt) {}
+
+    fn visit_float(&mut self, _value: &mut f64) {}
+
+    fn visit_char(&mut self, _value: &mut char) {}
+
+    fn visit_string(&mut self, _value: &mut String) {}
+
+    fn visit_bool(&mut self, _value: &mut bool) {}
+
+    fn visit_tuple(&mut self, items: &mut Vec<ASTNode>) {
+        walk_body_mut(self, items)
+    }
+
+    fn visit_index(&mut self, tuple: &mut ASTNode, _index: &mut usize) {
+        self.visit_node(tuple)
+    }
+
+    fn visit_struct(&mut self, _name: &mut String, _fields: &mut Vec<(String, NodeType)>) {}
+
+    fn visit_record(&mut self, _name: &mut String, fields: &mut Vec<(String, ASTNode)>) {
+        fields.iter_mut().for_each(|(_, value)| self.visit_node(value))
+    }
+
+    fn visit_field(&mut self, record: &mut ASTNode, _field: &mut String) {
+        self.visit_node(record)
+    }
+
+    fn visit_with(&mut self, record: &mut ASTNode, fields: &mut Vec<(String, ASTNode)>) {
+        self.visit_node(record);
+        fields.iter_mut().for_each(|(_, value)| self.visit_node(value))
+    }
+}
+
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut ASTNode) {
+    match node {
+        ASTNode::Fn(name, args, return_type, body) => visitor.visit_fn(name, args, return_type, body),
+        ASTNode::Call(name, args, _) => visitor.visit_call(name, args),
+        ASTNode::Let(symbol, value, _) => visitor.visit_let(symbol, value),
+        ASTNode::Var(symbol) => visitor.visit_var(symbol),
+        ASTNode::Int(value) => visitor.visit_int(value),
+        ASTNode::BigInt(value) => visitor.visit_big_int(value),
+        ASTNode::Float(value) => visitor.visit_float(value),
+        ASTNode::Char(value) => visitor.visit_char(value),
+        ASTNode::String(value) => visitor.visit_string(value),
+        ASTNode::Bool(value) => visitor.visit_bool(value),
+        ASTNode::Tuple(items) => visitor.visit_tuple(items),
+        ASTNode::Index(tuple, index) => visitor.visit_index(tuple, index),
+        ASTNode::Struct(name, fields) => visitor.visit_struct(name, fields),
+        ASTNode::Record(name, fields) => visitor.visit_record(name, fields),
+        ASTNode::Field(record, field) => visitor.visit_field(record, field),
+        ASTNode::With(record, fields) => visitor.visit_with(record, fields),
+    }
+}
+
+pub fn walk_body_mut<V: VisitorMut + ?Sized>(visitor: &mut V, nodes: &mut [ASTNode]) {
+    for node in nodes {
+        visitor.visit_node(node);
+    }
+}
+
+// Builds a new tree from an old one, node by node
+pub trait Fold {
+    fn fold_node(&mut self, node: ASTNode) -> ASTNode {
+        walk_fold_node(self, node)
+    }
+
+    fn fold_fn(&mut self, name: String, args: Vec<Symbol>, return_type: NodeType, body: Vec<ASTNode>) -> ASTNode {
+        ASTNode::Fn(name, args, return_type, walk_fold_body(self, body))
+    }
+
+    fn fold_call(&mut self, name: String, args: Vec<ASTNode>, line: Line) -> ASTNode {
+        ASTNode::Call(name, walk_fold_body(self, args), line)
+    }
+
+    fn fold_let(&mut self, symbol: Symbol, value: ASTNode, bind: Bind) -> ASTNode {
+        ASTNode::Let(symbol, Box::new(self.fold_node(value)), bind)
+    }
+
+    fn fold_var(&mut self, symbol: Symbol) -> ASTNode {
+        ASTNode::Var(symbol)
+    }
+
+    fn fold_int(&mut self, value: i64) -> ASTNode {
+        ASTNode::Int(value)
+    }
+
+    fn fold_big_int(&mut self, value: BigInt) -> ASTNode {
+        ASTNode::BigInt(value)
+    }
+
+    fn fold_float(&mut self, value: f64) -> ASTNode {
+        ASTNode::Float(value)
+    }
+
+    fn fold_char(&mut self, value: char) -> ASTNode {
+        ASTNode::Char(value)
+    }
+
+    fn fold_string(&mut self, value: String) -> ASTNode {
+        ASTNode::String(value)
+    }
+
+    fn fold_bool(&mut self, value: bool) -> ASTNode {
+        ASTNode::Bool(value)
+    }
+
+    fn fold_tuple(&mut self, items: Vec<ASTNode>) -> ASTNode {
+        ASTNode::Tuple(walk_fold_body(self, items))
+    }
+
+    fn fold_index(&mut self, tuple: ASTNode, index: usize) -> ASTNode {
+        ASTNode::Index(Box::new(self.fold_node(tuple)), index)
+    }
+
+    fn fold_struct(&mut self, name: String, fields: Vec<(String, NodeType)>) -> ASTNode {
+        ASTNode::Struct(name, fields)
+    }
+
+    fn fold_record(&mut self, name: String, fields: Vec<(String, ASTNode)>) -> ASTNode {
+        ASTNode::Record(name, walk_fold_fields(self, fields))
+    }
+
+    fn fold_field(&mut self, record: ASTNode, field: String) -> ASTNode {
+        ASTNode::Field(Box::new(self.fold_node(record)), field)
+    }
+
+    fn fold_with(&mut self, record: ASTNode, fields: Vec<(String, ASTNode)>) -> ASTNode {
+        ASTNode::With(Box::new(self.fold_node(record)), walk_fold_fields(self, fields))
+    }
+}
+
+pub fn walk_fold_node<F: Fold + ?Sized>(folder: &mut F, node: ASTNode) -> ASTNode {
+    match node {
+        ASTNode::Fn(name, args, return_type, body) => folder.fold_fn(name, args, return_type, body),
+        ASTNode::Call(name, args, line) => folder.fold_call(name, args, line),
+        ASTNode::Let(symbol, value, bind) => folder.fold_let(symbol, *value, bind),
+        ASTNode::Var(symbol) => folder.fold_var(symbol),
+        ASTNode::Int(value) => folder.fold_int(value),
+        ASTNode::BigInt(value) => folder.fold_big_int(value),
+        ASTNode::Float(value) => folder.fold_float(value),
+        ASTNode::Char(value) => folder.fold_char(value),
+        ASTNode::String(value) => folder.fold_string(value),
+        ASTNode::Bool(value) => folder.fold_bool(value),
+        ASTNode::Tuple(items) => folder.fold_tuple(items),
+        ASTNode::Index(tuple, index) => folder.fold_index(*tuple, index),
+        ASTNode::Struct(name, fields) => folder.fold_struct(name, fields),
+        ASTNode::Record(name, fields) => folder.fold_record(name, fields),
+        ASTNode::Field(record, field) => folder.fold_field(*record, field),
+        ASTNode::With(record, fields) => folder.fold_with(*record, fields),
+    }
+}
+
+pub fn walk_fold_body<F: Fold + ?Sized>(folder: &mut F, nodes: Vec<ASTNode>) -> Vec<ASTNode> {
+    nodes.into_iter().map(|node| folder.fold_node(node)).collect()
+}
+
+pub fn walk_fold_fields<F: Fold + ?Sized>(folder: &mut F, fields: Vec<(String, ASTNode)>) -> Vec<(String, ASTNode)> {
+    fields.into_iter().map(|(field, value)| (field, folder.fold_node(value))).collect()
 }
 
 // Types of function arguments and returns
 #[derive(Debug, PartialEq, Clone)]
 pub enum NodeType {
     Int,
+    BigInt,
+    Float,
+    Char,
     Bool,
     Fn(Box<NodeType>),
     List(Box<NodeType>),
+    Tuple(Vec<NodeType>),
+    // Declared with "struct", by name
+    Record(String),
     None,
     Generic(String)
 }
@@ -38,9 +501,17 @@ impl fmt::Display for NodeType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             NodeType::Int => write!(f, "Int"),
+            NodeType::BigInt => write!(f, "BigInt"),
+            NodeType::Float => write!(f, "Float"),
+            NodeType::Char => write!(f, "Char"),
             NodeType::Bool => write!(f, "Bool"),
             NodeType::Fn(return_type) => write!(f, "Fn({return_type})"),
             NodeType::List(inner) => write!(f, "List({inner})"),
+            NodeType::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "Tuple({})", items.join(", "))
+            }
+            NodeType::Record(name) => write!(f, "Record({name})"),
             NodeType::None => write!(f, "None"),
             NodeType::Generic(generic_name) => write!(f, "Generic({generic_name})"),
         }
@@ -52,6 +523,30 @@ impl<'a> NodeType {
         NodeType::Generic(name.to_string())
     }
 
+    // The type as it is written in Flip code
+    pub fn source(&self) -> String {
+        match self {
+            NodeType::Fn(_) => String::from("Fn"),
+            NodeType::List(inner) => format!("[{}]", inner.source()),
+            NodeType::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.source()).collect();
+                format!("({})", items.join(", "))
+            }
+            NodeType::Record(name) | NodeType::Generic(name) => name.clone(),
+            _ => self.to_string(),
+        }
+    }
+
+    // The names of the generics in a type, in order
+    pub fn generics(&self, names: &mut Vec<String>) {
+        match self {
+            NodeType::Fn(inner) | NodeType::List(inner) => inner.generics(names),
+            NodeType::Tuple(items) => items.iter().for_each(|item| item.generics(names)),
+            NodeType::Generic(g) if !names.contains(g) => names.push(g.clone()),
+            _ => {}
+        }
+    }
+
     pub fn unwrap_fn(&self) -> NodeType {
         match &self {
             NodeType::Fn(arg_type) => arg_type.unwrap_fn(),
@@ -0,0 +1,251 @@
+// Arbitrary-precision integers, the values of the BigInt type. The Rust back
+// end copies this file into the programs it generates, so it only uses std.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+// Each digit holds nine decimal digits, which keeps parsing and printing simple
+const BASE: u64 = 1_000_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    // Least significant first, without leading zeros, so zero has no digits
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(negative: bool, digits: Vec<u32>) -> BigInt {
+        let digits = trim(digits);
+        BigInt { negative: negative && !digits.is_empty(), digits }
+    }
+
+    pub fn from_i64(value: i64) -> BigInt {
+        let mut magnitude = value.unsigned_abs();
+        let mut digits = Vec::new();
+        while magnitude > 0 {
+            digits.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        BigInt::new(value < 0, digits)
+    }
+
+    // Decimal digits, with a "-" in front for negative numbers
+    pub fn parse(text: &str) -> Option<BigInt> {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let mut digits = Vec::new();
+        let mut end = text.len();
+        while end > 0 {
+            let start = end.saturating_sub(9);
+            digits.push(text[start..end].parse().unwrap());
+            end = start;
+        }
+
+        Some(BigInt::new(negative, digits))
+    }
+
+    // None if the number does not fit
+    pub fn to_i64(&self) -> Option<i64> {
+        let mut magnitude: i128 = 0;
+        for &digit in self.digits.iter().rev() {
+            magnitude = magnitude * BASE as i128 + digit as i128;
+            if magnitude > i64::MAX as i128 + 1 {
+                return None;
+            }
+        }
+
+        let value = if self.negative { -magnitude } else { magnitude };
+        if value > i64::MAX as i128 {
+            None
+        } else {
+            Some(value as i64)
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    // Division rounding towards zero, like Int, and the remainder, which has
+    // the sign of "self". None when dividing by zero.
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+
+        let (quotient, remainder) = div_rem_magnitudes(&self.digits, &other.digits);
+        Some((BigInt::new(self.negative != other.negative, quotient), BigInt::new(self.negative, remainder)))
+    }
+}
+
+fn trim(mut digits: Vec<u32>) -> Vec<u32> {
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    digits
+}
+
+// Both magnitudes must be trimmed
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut digits = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        digits.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        digits.push(carry as u32);
+    }
+
+    digits
+}
+
+// "a" must not be smaller than "b"
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut digits = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+
+    for (i, &digit) in a.iter().enumerate() {
+        let subtrahend = *b.get(i).unwrap_or(&0) as u64 + borrow;
+        if digit as u64 >= subtrahend {
+            digits.push((digit as u64 - subtrahend) as u32);
+            borrow = 0;
+        } else {
+            digits.push((digit as u64 + BASE - subtrahend) as u32);
+            borrow = 1;
+        }
+    }
+
+    trim(digits)
+}
+
+fn mul_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut digits = vec![0u32; a.len() + b.len()];
+
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let product = digits[i + j] as u64 + x as u64 * y as u64 + carry;
+            digits[i + j] = (product % BASE) as u32;
+            carry = product / BASE;
+        }
+        digits[i + b.len()] = carry as u32;
+    }
+
+    trim(digits)
+}
+
+// Long division, finding each digit of the quotient with a binary search
+fn div_rem_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+
+    for i in (0..a.len()).rev() {
+        remainder.insert(0, a[i]);
+        remainder = trim(remainder);
+
+        let (mut low, mut high) = (0, BASE - 1);
+        while low < high {
+            let middle = (low + high).div_ceil(2);
+            if compare_magnitudes(&mul_magnitudes(b, &[middle as u32]), &remainder) == Ordering::Greater {
+                high = middle - 1;
+            } else {
+                low = middle;
+            }
+        }
+
+        if low > 0 {
+            remainder = sub_magnitudes(&remainder, &mul_magnitudes(b, &[low as u32]));
+        }
+        quotient[i] = low as u32;
+    }
+
+    (quotient, remainder)
+}
+
+impl<'a> Add<&'a BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitudes(&self.digits, &other.digits));
+        }
+
+        match compare_magnitudes(&self.digits, &other.digits) {
+            Ordering::Less => BigInt::new(other.negative, sub_magnitudes(&other.digits, &self.digits)),
+            _ => BigInt::new(self.negative, sub_magnitudes(&self.digits, &other.digits)),
+        }
+    }
+}
+
+impl<'a> Sub<&'a BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl<'a> Mul<&'a BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(self.negative != other.negative, mul_magnitudes(&self.digits, &other.digits))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.digits, &other.digits),
+            (true, true) => compare_magnitudes(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some((last, rest)) = self.digits.split_last() else {
+            return write!(f, "0");
+        };
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{last}")?;
+        for digit in rest.iter().rev() {
+            write!(f, "{digit:09}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{ASTNode, Line, NodeType};
+use crate::bigint::BigInt;
+use crate::compiler;
+use crate::error::throw;
+use crate::overflow;
+use crate::symbols::{Symbol, SymbolTable};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Neg,
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    Not,
+    Len,
+    Head,
+    Tail,
+    Push,
+    IsNull,
+    Assert,
+    AssertEq,
+    ToBigInt,
+    ToInt,
+    ToFloat,
+    Floor,
+    Round,
+    Sqrt,
+    ToChar,
+    IsDigit,
+    IsAlpha,
+}
+
+impl Builtin {
+    // The number of values the builtin pops off the stack
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Neg
+            | Builtin::Not
+            | Builtin::Len
+            | Builtin::Head
+            | Builtin::Tail
+            | Builtin::IsNull
+            | Builtin::Assert
+            | Builtin::ToBigInt
+            | Builtin::ToInt
+            | Builtin::ToFloat
+            | Builtin::Floor
+            | Builtin::Round
+            | Builtin::Sqrt
+            | Builtin::ToChar
+            | Builtin::IsDigit
+            | Builtin::IsAlpha => 1,
+            _ => 2,
+        }
+    }
+
+    // The Flip function the builtin implements
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Add => "+",
+            Builtin::Sub | Builtin::Neg => "-",
+            Builtin::Mul => "*",
+            Builtin::Div => "/",
+            Builtin::Mod => "mod",
+            Builtin::Eq => "==",
+            Builtin::Ne => "!=",
+            Builtin::Gt => ">",
+            Builtin::Lt => "<",
+            Builtin::Ge => ">=",
+            Builtin::Le => "<=",
+            Builtin::Not => "not",
+            Builtin::Len => "len",
+            Builtin::Head => "head",
+            Builtin::Tail => "tail",
+            Builtin::Push => "push",
+            Builtin::IsNull => "is_null",
+            Builtin::Assert => "assert",
+            Builtin::AssertEq => "assert_eq",
+            Builtin::ToBigInt => "big",
+            Builtin::ToInt => "int",
+            Builtin::ToFloat => "to_float",
+            Builtin::Floor => "floor",
+            Builtin::Round => "round",
+            Builtin::Sqrt => "sqrt",
+            Builtin::ToChar => "to_char",
+            Builtin::IsDigit => "is_digit",
+            Builtin::IsAlpha => "is_alpha",
+        }
+    }
+
+    fn from_call(name: &str, arg_count: usize) -> Option<Builtin> {
+        let builtin = match (name, arg_count) {
+            ("+", 2) => Builtin::Add,
+            ("-", 2) => Builtin::Sub,
+            ("*", 2) => Builtin::Mul,
+            ("/", 2) => Builtin::Div,
+            ("mod", 2) => Builtin::Mod,
+            ("-", 1) => Builtin::Neg,
+            ("==", 2) => Builtin::Eq,
+            ("!=", 2) => Builtin::Ne,
+            (">", 2) => Builtin::Gt,
+            ("<", 2) => Builtin::Lt,
+            (">=", 2) => Builtin::Ge,
+            ("<=", 2) => Builtin::Le,
+            ("not", 1) => Builtin::Not,
+            ("len", 1) => Builtin::Len,
+            ("head", 1) => Builtin::Head,
+            ("tail", 1) => Builtin::Tail,
+            ("push", 2) => Builtin::Push,
+            ("is_null", 1) => Builtin::IsNull,
+            ("assert", 1) => Builtin::Assert,
+            ("assert_eq", 2) => Builtin::AssertEq,
+            ("wrapping_add", 2) => Builtin::Add,
+            ("wrapping_sub", 2) => Builtin::Sub,
+            ("wrapping_mul", 2) => Builtin::Mul,
+            ("wrapping_neg", 1) => Builtin::Neg,
+            ("big", 1) => Builtin::ToBigInt,
+            ("int", 1) => Builtin::ToInt,
+            ("to_float", 1) => Builtin::ToFloat,
+            ("floor", 1) => Builtin::Floor,
+            ("round", 1) => Builtin::Round,
+            ("sqrt", 1) => Builtin::Sqrt,
+            ("to_char", 1) => Builtin::ToChar,
+            ("is_digit", 1) => Builtin::IsDigit,
+            ("is_alpha", 1) => Builtin::IsAlpha,
+            _ => return None,
+        };
+
+        Some(builtin)
+    }
+
+    // Int arithmetic which stops on overflow, instead of wrapping around
+    fn from_checked_call(name: &str) -> Option<Builtin> {
+        match name {
+            "checked_add" => Some(Builtin::Add),
+            "checked_sub" => Some(Builtin::Sub),
+            "checked_mul" => Some(Builtin::Mul),
+            "checked_neg" => Some(Builtin::Neg),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    Int(i64),
+    // Pushes a BigInt constant of the program, by its index
+    BigInt(usize),
+    Float(f64),
+    Char(char),
+    // Pushes a string constant of the program as a list of chars, by its index
+    String(usize),
+    Bool(bool),
+    EmptyList,
+    // Pops the elements of a tuple, the last on top, and pushes the tuple
+    Tuple(usize),
+    // Replaces a tuple on top of the stack with one of its elements
+    Index(usize),
+    // Pops a value and a tuple, and pushes a copy of the tuple with the
+    // element changed to the value. Records are tuples of their fields.
+    With(usize),
+    // Pushes the value in a slot of the current frame
+    Load(usize),
+    // Pops a value into a slot of the current frame
+    Store(usize),
+    // Pushes an unevaluated thunk running a chunk in the current frame
+    Thunk(usize),
+    // Replaces a thunk on top of the stack with its value
+    Force,
+    // Calls a function chunk, popping its arguments
+    Call(usize, usize),
+    Builtin(Builtin),
+    // Like Builtin, stopping with an error of the program if the Int result overflows
+    Checked(Builtin, usize),
+    Jump(usize),
+    JumpIfFalse(usize),
+    Return,
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instr::Int(v) => write!(f, "int {v}"),
+            Instr::BigInt(index) => write!(f, "big_int ${index}"),
+            Instr::Float(v) => write!(f, "float {v:?}"),
+            Instr::Char(v) => write!(f, "char {v:?}"),
+            Instr::String(index) => write!(f, "string %{index}"),
+            Instr::Bool(v) => write!(f, "bool {v}"),
+            Instr::EmptyList => write!(f, "empty_list"),
+            Instr::Tuple(size) => write!(f, "tuple {size}"),
+            Instr::Index(index) => write!(f, "index {index}"),
+            Instr::With(index) => write!(f, "with {index}"),
+            Instr::Load(slot) => write!(f, "load {slot}"),
+            Instr::Store(slot) => write!(f, "store {slot}"),
+            Instr::Thunk(chunk) => write!(f, "thunk #{chunk}"),
+            Instr::Force => write!(f, "force"),
+            Instr::Call(chunk, arg_count) => write!(f, "call #{chunk} {arg_count}"),
+            Instr::Builtin(builtin) => write!(f, "builtin {builtin:?}"),
+            Instr::Checked(builtin, error) => write!(f, "checked {builtin:?} @{error}"),
+            Instr::Jump(target) => write!(f, "jump {target:04}"),
+            Instr::JumpIfFalse(target) => write!(f, "jump_if_false {target:04}"),
+            Instr::Return => write!(f, "return"),
+        }
+    }
+}
+
+// A function body, or the value of a let-binding. Let-bindings are run in
+// the frame of the function they belong to, so only functions have slots.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    // The function the chunk belongs to
+    pub name: String,
+    // Set if the chunk computes a let-binding
+    pub binding: Option<String>,
+    pub slots: usize,
+    pub code: Vec<Instr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub chunks: Vec<Chunk>,
+    pub main: Option<usize>,
+    pub big_ints: Vec<Rc<BigInt>>,
+    pub strings: Vec<String>,
+    // The messages of checked operations, with the line each is on if known
+    pub errors: Vec<(String, Option<usize>)>,
+}
+
+impl Program {
+    pub fn disassemble(&self) -> String {
+        let mut out = String::new();
+
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if let Some(binding) = &chunk.binding {
+                out.push_str(&format!("#{i} {} ={binding}\n", chunk.name));
+            } else {
+                out.push_str(&format!("#{i} {} (slots: {})\n", chunk.name, chunk.slots));
+            }
+            for (pc, instr) in chunk.code.iter().enumerate() {
+                out.push_str(&format!("    {pc:04} {instr}\n"));
+            }
+            out.push('\n');
+        }
+
+        for (i, value) in self.big_ints.iter().enumerate() {
+            out.push_str(&format!("${i} {value}\n"));
+        }
+        for (i, value) in self.strings.iter().enumerate() {
+            out.push_str(&format!("%{i} {value:?}\n"));
+        }
+        for (i, (error, line)) in self.errors.iter().enumerate() {
+            match line {
+                Some(line) => out.push_str(&format!("@{i} line {line}: {error}\n")),
+                None => out.push_str(&format!("@{i} {error}\n")),
+            }
+        }
+
+        out
+    }
+}
+
+struct Binding {
+    slot: usize,
+    lazy: bool,
+}
+
+struct ChunkCompiler<'a> {
+    symbols: &'a SymbolTable,
+    // The chunk of each overload of a function, by its argument types
+    functions: &'a HashMap<String, Vec<(Vec<NodeType>, usize)>>,
+    chunks: Vec<Chunk>,
+    big_ints: Vec<Rc<BigInt>>,
+    strings: Vec<String>,
+    errors: Vec<(String, Option<usize>)>,
+}
+
+pub fn compile_program(tree: &[ASTNode], symbols: &SymbolTable) -> Program {
+    let mut functions: HashMap<String, Vec<(Vec<NodeType>, usize)>> = HashMap::new();
+    let mut chunks = Vec::new();
+
+    for branch in tree {
+        if let ASTNode::Fn(name, args, _, _) = branch {
+            let arg_types = args.iter().map(|arg| arg.symbol_type.clone()).collect();
+            functions.entry(name.clone()).or_default().push((arg_types, chunks.len()));
+            chunks.push(Chunk { name: name.clone(), binding: None, slots: args.len(), code: Vec::new() });
+        }
+    }
+
+    let main = functions.get("main").and_then(|overloads| overloads.first()).map(|&(_, chunk)| chunk);
+    let mut compiler = ChunkCompiler { symbols, functions: &functions, chunks, big_ints: Vec::new(), strings: Vec::new(), errors: Vec::new() };
+
+    let mut index = 0;
+    for branch in tree {
+        if let ASTNode::Fn(_, args, _, body) = branch {
+            compiler.compile_fn(index, args, body);
+            index += 1;
+        }
+    }
+
+    Program { chunks: compiler.chunks, main, big_ints: compiler.big_ints, strings: compiler.strings, errors: compiler.errors }
+}
+
+impl ChunkCompiler<'_> {
+    // Resolves the overload the same way the type checker does, then finds its chunk
+    fn function_chunk(&self, name: &str, args: &[ASTNode]) -> Option<usize> {
+        let arg_types = self.symbols.get_arg_types(name, args);
+        let overloads = self.functions.get(name)?;
+        overloads.iter().find(|(types, _)| *types == arg_types).map(|&(_, chunk)| chunk)
+    }
+
+    fn compile_fn(&mut self, index: usize, args: &[Symbol], body: &[ASTNode]) {
+        let mut scope = HashMap::new();
+        for (slot, arg) in args.iter().enumerate() {
+            scope.insert(arg.name.clone(), Binding { slot, lazy: false });
+        }
+
+        let mut slots = args.len();
+        let mut code = Vec::new();
+
+        let (last, init) = body.split_last().unwrap();
+
+        for node in init {
+            if let ASTNode::Let(s, v, _) = node {
+                let thunk = self.compile_thunk(index, &s.name, v, &scope);
+                code.push(Instr::Thunk(thunk));
+                code.push(Instr::Store(slots));
+
+                scope.insert(s.name.clone(), Binding { slot: slots, lazy: true });
+                slots += 1;
+            }
+        }
+
+        self.compile_node(last, &scope, &mut code);
+        code.push(Instr::Return);
+
+        let chunk = &mut self.chunks[index];
+        chunk.slots = slots;
+        chunk.code = code;
+    }
+
+    fn compile_thunk(&mut self, function: usize, name: &str, value: &ASTNode, scope: &HashMap<String, Binding>) -> usize {
+        let mut code = Vec::new();
+        self.compile_node(value, scope, &mut code);
+        code.push(Instr::Return);
+
+        self.chunks.push(Chunk {
+            name: self.chunks[function].name.clone(),
+            binding: Some(name.to_string()),
+            slots: 0,
+            code,
+        });
+        self.chunks.len() - 1
+    }
+
+    fn compile_node(&mut self, node: &ASTNode, scope: &HashMap<String, Binding>, code: &mut Vec<Instr>) {
+        match node {
+            ASTNode::Int(v) => code.push(Instr::Int(*v)),
+            ASTNode::BigInt(v) => {
+                self.big_ints.push(Rc::new(v.clone()));
+                code.push(Instr::BigInt(self.big_ints.len() - 1));
+            }
+            ASTNode::Float(v) => code.push(Instr::Float(*v)),
+            ASTNode::Char(v) => code.push(Instr::Char(*v)),
+            ASTNode::String(v) => {
+                self.strings.push(v.clone());
+                code.push(Instr::String(self.strings.len() - 1));
+            }
+            ASTNode::Bool(v) => code.push(Instr::Bool(*v)),
+            ASTNode::Var(s) => {
+                if let Some(binding) = scope.get(&s.name) {
+                    code.push(Instr::Load(binding.slot));
+                    if binding.lazy {
+                        code.push(Instr::Force);
+                    }
+                } else {
+                    throw(&format!("Unknown variable \"{}\"", s.name));
+                }
+            }
+            ASTNode::Call(name, args, line) => {
+                if compiler::is_inline_call(name, args, self.symbols) {
+                    self.compile_builtin(name, args, *line, scope, code);
+                } else if let Some(chunk) = self.function_chunk(name, args) {
+                    for arg in args {
+                        self.compile_node(arg, scope, code);
+                    }
+                    code.push(Instr::Call(chunk, args.len()));
+                } else {
+                    throw(&format!("Could not find function \"{name}\""));
+                }
+            }
+            ASTNode::Tuple(items) => {
+                for item in items {
+                    self.compile_node(item, scope, code);
+                }
+                code.push(Instr::Tuple(items.len()));
+            }
+            ASTNode::Index(tuple, index) => {
+                self.compile_node(tuple, scope, code);
+                code.push(Instr::Index(*index));
+            }
+            ASTNode::Record(_, fields) => {
+                for (_, value) in fields {
+                    self.compile_node(value, scope, code);
+                }
+                code.push(Instr::Tuple(fields.len()));
+            }
+            ASTNode::Field(record, field) => {
+                self.compile_node(record, scope, code);
+                code.push(Instr::Index(self.field_index(record, field)));
+            }
+            ASTNode::With(record, fields) => {
+                self.compile_node(record, scope, code);
+                for (field, value) in fields {
+                    self.compile_node(value, scope, code);
+                    code.push(Instr::With(self.field_index(record, field)));
+                }
+            }
+            ASTNode::Let(..) => throw("Let-bindings can only appear in a function body"),
+            ASTNode::Fn(name, _, _, _) => throw(&format!("Function \"{name}\" must be defined at the top level")),
+            ASTNode::Struct(name, _) => throw(&format!("Struct \"{name}\" must be declared at the top level")),
+        }
+    }
+
+    fn field_index(&self, record: &ASTNode, field: &str) -> usize {
+        let record_type = self.symbols.get_node_type(record);
+        match self.symbols.field(&record_type, field) {
+            Some((index, _)) => index,
+            None => throw(&format!("{} has no field {field}", record_type.unwrap_fn().source())),
+        }
+    }
+
+    fn compile_builtin(&mut self, name: &str, args: &[ASTNode], line: Line, scope: &HashMap<String, Binding>, code: &mut Vec<Instr>) {
+        match name {
+            // Only the chosen branch is evaluated
+            "if" => {
+                self.compile_node(&args[0], scope, code);
+                let jump_to_else = code.len();
+                code.push(Instr::JumpIfFalse(0));
+                self.compile_node(&args[1], scope, code);
+                let jump_to_end = code.len();
+                code.push(Instr::Jump(0));
+                code[jump_to_else] = Instr::JumpIfFalse(code.len());
+                self.compile_node(&args[2], scope, code);
+                code[jump_to_end] = Instr::Jump(code.len());
+            }
+            // Short-circuiting, like in C
+            "and" | "or" => {
+                self.compile_node(&args[0], scope, code);
+                let jump_to_short = code.len();
+                code.push(Instr::JumpIfFalse(0));
+                if name == "and" {
+                    self.compile_node(&args[1], scope, code);
+                } else {
+                    code.push(Instr::Bool(true));
+                }
+                let jump_to_end = code.len();
+                code.push(Instr::Jump(0));
+                code[jump_to_short] = Instr::JumpIfFalse(code.len());
+                if name == "and" {
+                    code.push(Instr::Bool(false));
+                } else {
+                    self.compile_node(&args[1], scope, code);
+                }
+                code[jump_to_end] = Instr::Jump(code.len());
+            }
+            "[Int]" | "[Char]" | "[Float]" | "[BigInt]" => code.push(Instr::EmptyList),
+            "fst" | "snd" => {
+                self.compile_node(&args[0], scope, code);
+                code.push(Instr::Index(usize::from(name == "snd")));
+            }
+            _ => {
+                if let Some(builtin) = Builtin::from_checked_call(name) {
+                    for arg in args {
+                        self.compile_node(arg, scope, code);
+                    }
+                    let error = overflow::error_message(&ASTNode::Call(name.to_string(), args.to_vec(), line));
+                    self.errors.push((error, line.known()));
+                    code.push(Instr::Checked(builtin, self.errors.len() - 1));
+                } else if let Some(builtin) = Builtin::from_call(name, args.len()) {
+                    for arg in args {
+                        self.compile_node(arg, scope, code);
+                    }
+                    code.push(Instr::Builtin(builtin));
+                } else {
+                    throw(&format!("Built-in \"{name}\" is not supported by the bytecode compiler"));
+                }
+            }
+        }
+    }
+}
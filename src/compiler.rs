@@ -1,9 +1,13 @@
+use std::collections::{BTreeSet, HashMap};
 use std::fmt;
 
 use crate::symbols::{Symbol, SymbolTable};
-use crate::ast::{ASTNode, NodeType};
+use crate::ast::{ASTNode, Fold, Line, NodeType, Visitor};
+use crate::error::throw;
+use crate::overflow;
+use crate::runtime;
 
-type InlineFnBody<'a> = &'a (dyn Fn(Vec<ASTNode>, &'a mut SymbolTable) -> String);
+type InlineFnBody<'a> = &'a dyn Fn(Vec<ASTNode>, &mut Emitter) -> String;
 type InlineFn<'a> = (&'a str, Vec<NodeType>, NodeType, InlineFnBody<'a>);
 
 pub struct Buffer {
@@ -16,6 +20,12 @@ impl fmt::Display for Buffer {
     }
 }
 
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Buffer {
     pub fn new() -> Buffer {
         Buffer { content: String::new() }
@@ -29,56 +39,281 @@ impl Buffer {
         self.content.push_str(&format!("    {text}\n"));
     }
 
-    pub fn get<'a>(self) -> String {
+    pub fn get(self) -> String {
         self.content
     }
 }
 
-pub fn compile_expr<'a>(node: &ASTNode, symbols: &mut SymbolTable) -> Buffer {
+// Compiles expressions, noting what the C function they are emitted into has
+// to declare for them
+struct Emitter<'a> {
+    symbols: &'a mut SymbolTable,
+    // The code reads arguments or let-bindings from "frame"
+    uses_frame: bool,
+    // The code passes "flip_fn" to a runtime function which can fail
+    uses_fn_name: bool,
+    // The line of the built-in call being compiled
+    line: Option<usize>,
+    // The sizes of the tuples and records the code uses, and of those it
+    // copies with "with_tupleN", see "tuple_structs"
+    tuples: BTreeSet<usize>,
+    updated_tuples: BTreeSet<usize>,
+}
+
+impl Emitter<'_> {
+    // Called before compiling the code of each C function
+    fn start_function(&mut self) {
+        self.uses_frame = false;
+        self.uses_fn_name = false;
+    }
+
+    // The Flip name of the function being compiled, for runtime errors
+    fn fn_name(&mut self) -> String {
+        self.uses_fn_name = true;
+        String::from("flip_fn")
+    }
+}
+
+// Compiles a whole program to standalone C, including the parts of the runtime
+// it needs and a C "main" printing the result of the Flip "main"
+pub fn compile_program(tree: &[ASTNode], symbols: &mut SymbolTable) -> String {
+    let mut code = String::new();
+    let mut emitter = Emitter {
+        symbols,
+        uses_frame: false,
+        uses_fn_name: false,
+        line: None,
+        tuples: BTreeSet::new(),
+        updated_tuples: BTreeSet::new(),
+    };
+
+    for branch in tree {
+        code.push_str(&compile_expr(branch, &mut emitter).get());
+    }
+    let (mut sizes, updated) = (emitter.tuples, emitter.updated_tuples);
+
+    let print_main = match main_type(tree, symbols) {
+        Some(NodeType::BigInt) => "big_print(fn_main());",
+        Some(NodeType::Float) => "float_print(fn_main());",
+        Some(NodeType::Char) => "char_print(fn_main());",
+        Some(NodeType::List(item)) if *item == NodeType::Char => "string_print((list) fn_main());",
+        _ => "printf(\"%ld\\n\", fn_main());",
+    };
+
+    code.push_str(&format!("// C main\n\nint main() {{\n    {print_main}\n    return 0;\n}}\n"));
+
+    let mut compared = Comparisons { symbols, types: Vec::new() };
+    for branch in tree {
+        compared.visit_node(branch);
+    }
+    let equalities: String = compared.types.iter().map(|t| equality_definition(t, symbols)).collect();
+    sizes.extend(compared.types.iter().filter_map(|t| symbols.elements(t)).map(|items| items.len()));
+    let tuples = tuple_structs(&sizes, &updated);
+    code = format!("{tuples}{equalities}{code}");
+
+    format!("{}\n{code}", runtime::runtime_for(&code))
+}
+
+// Tuples are structs of longs on the heap, like lists. As every element is a
+// long, one struct is defined for each size of tuple the program uses.
+// Records are tuples of their fields, in the order they were declared, and
+// "with_tupleN" copies one with a field changed.
+fn tuple_structs(sizes: &BTreeSet<usize>, updated: &BTreeSet<usize>) -> String {
+    let mut out = String::new();
+    for &size in sizes {
+        let fields: Vec<String> = (0..size).map(|i| format!("f{i}")).collect();
+        let params: Vec<String> = fields.iter().map(|f| format!("long {f}")).collect();
+
+        out.push_str(&format!("// Tuples of {size}\n\nstruct tuple{size} {{\n"));
+        for param in params.iter() {
+            out.push_str(&format!("    {param};\n"));
+        }
+        out.push_str("};\n\n");
+
+        out.push_str(&format!("long make_tuple{size}({}) {{\n", params.join(", ")));
+        out.push_str(&format!("    struct tuple{size}* t = malloc(sizeof(struct tuple{size}));\n"));
+        for f in fields.iter() {
+            out.push_str(&format!("    t->{f} = {f};\n"));
+        }
+        out.push_str("    return (long) t;\n}\n\n");
+
+        if updated.contains(&size) {
+            out.push_str(&format!("long with_tuple{size}(long t, int field, long value) {{\n"));
+            out.push_str(&format!("    struct tuple{size}* copy = malloc(sizeof(struct tuple{size}));\n"));
+            out.push_str(&format!("    *copy = *(struct tuple{size}*) t;\n"));
+            out.push_str("    ((long*) copy)[field] = value;\n");
+            out.push_str("    return (long) copy;\n}\n\n");
+        }
+    }
+
+    out
+}
+
+// Tuples, records and lists compared with "==", "!=" or "assert_eq",
+// including those inside them, each of which gets an equality function
+struct Comparisons<'a> {
+    symbols: &'a SymbolTable,
+    types: Vec<NodeType>,
+}
+
+impl Comparisons<'_> {
+    // The types inside come first, as their functions are called by this one's
+    fn add(&mut self, t: NodeType) {
+        let items = match &t {
+            NodeType::List(item) => vec![item.unwrap_fn()],
+            _ => match self.symbols.elements(&t) {
+                Some(items) => items,
+                None => return,
+            },
+        };
+
+        for item in items {
+            self.add(item.unwrap_fn());
+        }
+        if !self.types.contains(&t) {
+            self.types.push(t);
+        }
+    }
+}
+
+impl Visitor for Comparisons<'_> {
+    fn visit_call(&mut self, name: &str, args: &[ASTNode]) {
+        if matches!(name, "==" | "!=" | "assert_eq") && args.len() == 2 {
+            self.add(self.symbols.get_node_type(&args[0]).unwrap_fn());
+        }
+        args.iter().for_each(|arg| self.visit_node(arg));
+    }
+}
+
+// Whether values of the type are compared by a generated function
+fn has_equality_fn(t: &NodeType) -> bool {
+    matches!(t, NodeType::Tuple(_) | NodeType::Record(_) | NodeType::List(_))
+}
+
+fn equality_fn(t: &NodeType) -> String {
+    format!("eq_{}", mangle(&t.source()))
+}
+
+// Compares two values of the type, held in the longs "a" and "b"
+fn equal_values(t: &NodeType, a: &str, b: &str) -> String {
+    match t.unwrap_fn() {
+        t if has_equality_fn(&t) => format!("{}({a}, {b})", equality_fn(&t)),
+        NodeType::BigInt => format!("(big_cmp({a}, {b}) == 0)"),
+        NodeType::Float => format!("(bits_float({a}) == bits_float({b}))"),
+        _ => format!("({a} == {b})"),
+    }
+}
+
+// Tuples and records are equal if their elements are, and lists if they have
+// the same length and items
+fn equality_definition(t: &NodeType, symbols: &SymbolTable) -> String {
+    let body = match t {
+        NodeType::List(item) => format!(
+            "    list x = (list) a;\n    list y = (list) b;\n    while (x != NULL && y != NULL) {{\n        if (!{}) {{\n            return 0;\n        }}\n        x = x->tail;\n        y = y->tail;\n    }}\n    return x == y;\n",
+            equal_values(item, "x->head", "y->head")
+        ),
+        _ => {
+            let Some(items) = symbols.elements(t) else {
+                unreachable!("only tuples, records and lists are collected");
+            };
+
+            let size = items.len();
+            let fields: Vec<String> = items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| equal_values(item, &format!("x->f{i}"), &format!("y->f{i}")))
+                .collect();
+
+            format!(
+                "    struct tuple{size}* x = (struct tuple{size}*) a;\n    struct tuple{size}* y = (struct tuple{size}*) b;\n    return {};\n",
+                fields.join(" && ")
+            )
+        }
+    };
+
+    format!("// {}\n\nlong {}(long a, long b) {{\n{body}}}\n\n", t.source(), equality_fn(t))
+}
+
+// The type of the value "main" returns, which decides how it is printed
+pub fn main_type(tree: &[ASTNode], symbols: &SymbolTable) -> Option<NodeType> {
+    tree.iter().find_map(|branch| match branch {
+        ASTNode::Fn(name, _, _, body) if name == "main" => Some(symbols.get_node_type(body.last().unwrap()).unwrap_fn()),
+        _ => None,
+    })
+}
+
+fn compile_expr(node: &ASTNode, emitter: &mut Emitter) -> Buffer {
     let mut buf = Buffer::new();
 
     match node {
-        ASTNode::Let(s, v) => {
-            buf.emit_instr(&format!("#undef {}", s.name));
-            buf.emit_instr(&format!("#define {} {}", s.name, compile_expr(v, symbols)));
-        }
+        ASTNode::Let(..) => throw("Let-bindings can only appear in a function body"),
         ASTNode::Fn(name, args, return_type, body) => {
+            let (args, body) = scope_bindings(args, body);
+            let args = &args;
+            let lets: Vec<(&Symbol, &ASTNode)> = body
+                .iter()
+                .filter_map(|node| match node {
+                    ASTNode::Let(s, v, _) => Some((s, v.as_ref())),
+                    _ => None,
+                })
+                .collect();
+
+            let c_name = mangle(name);
+
             buf.emit(&format!("// {name}\n\n"));
 
-            if name == "main" {
-                buf.emit(&format!("long fn_main("));
-                emit_fn_args(&mut buf, args);
-                buf.emit(") {\n");
-                emit_fn_body(&mut buf, symbols, body);
-            } else {
+            if name != "main" {
                 // Forward declaration
-                buf.emit(&format!("fn fn_{name}("));
+                buf.emit(&format!("fn fn_{c_name}("));
                 emit_fn_args(&mut buf, args);
                 buf.emit(");\n\n");
+            }
+
+            emit_frame(&mut buf, emitter, name, args, &lets);
 
+            if name == "main" {
+                buf.emit("long fn_main() {\n");
+            } else if let NodeType::List(_) = return_type.unwrap_fn() {
                 // "Real" function (called when evaluating)
-                if let NodeType::List(_) = return_type.unwrap_fn() {
-                    buf.emit(&format!("list eval_{name}(char* args) {{\n"));
+                buf.emit(&format!("list eval_{c_name}(char* args) {{\n"));
+            } else {
+                buf.emit(&format!("long eval_{c_name}(char* args) {{\n"));
+            }
+
+            emitter.start_function();
+            let result = compile_expr(body.last().unwrap(), emitter).get();
+            emit_fn_name(&mut buf, name, emitter);
+
+            if !args.is_empty() || !lets.is_empty() {
+                buf.emit_instr(&format!("struct frame_{c_name} frame[1];"));
+            }
+
+            for arg in args {
+                if let NodeType::List(_) = arg.symbol_type.unwrap_fn() {
+                    buf.emit_instr(&format!("frame->{} = value_cell((long) get_arg(args, list));", arg.name));
                 } else {
-                    buf.emit(&format!("long eval_{name}(char* args) {{\n"));
+                    buf.emit_instr(&format!("frame->{} = value_cell(get_arg(args, long));", arg.name));
                 }
+            }
 
-                for arg in args {
-                    if let NodeType::List(_) = arg.symbol_type.unwrap_fn() {
-                        buf.emit_instr(&format!("list {} = get_arg(args, list);", arg.name));
-                    } else {
-                        buf.emit_instr(&format!("long {} = get_arg(args, long);", arg.name));
-                    }
-                }
+            for (s, _) in lets.iter() {
+                buf.emit_instr(&format!("frame->{0} = lazy_cell(let_{c_name}_{0});", s.name));
+            }
 
-                emit_fn_body(&mut buf, symbols, body);
+            if name == "main" {
+                // "main" may return a string, which is a pointer
+                buf.emit_instr(&format!("return (long) {result};\n}}\n"));
+            } else {
+                buf.emit_instr(&format!("return {result};\n}}\n"));
+            }
 
+            if name != "main" {
                 // Lambda factory
-                buf.emit(&format!("fn fn_{name}("));
+                buf.emit(&format!("fn fn_{c_name}("));
                 emit_fn_args(&mut buf, args);
                 buf.emit(") {\n");
 
-                if args.len() > 0 {
+                if !args.is_empty() {
                     buf.emit_instr("int size = 0;");
                     for arg in args {
                         buf.emit_instr(&format!("size += sizeof({});", arg.name));
@@ -89,49 +324,119 @@ pub fn compile_expr<'a>(node: &ASTNode, symbols: &mut SymbolTable) -> Buffer {
                         buf.emit_instr(&format!("add_arg(args, {});", arg.name));
                     }
     
-                    buf.emit_instr(&format!("return lambda(eval_{name}, args - size);\n}}\n"));
+                    buf.emit_instr(&format!("return lambda(eval_{c_name}, args - size);\n}}\n"));
                 } else {
-                    buf.emit_instr(&format!("return lambda(eval_{name}, NULL);\n}}\n"));
+                    buf.emit_instr(&format!("return lambda(eval_{c_name}, NULL);\n}}\n"));
                 }
             }
         }
-        ASTNode::Call(name, args) => {
-            if let Some(body) = get_inline_fn_body(name, args, symbols) {
-                buf.emit(&body(args.to_vec(), symbols));
-            } else if symbols.check_types(name, args) {
-                let mut fn_call;
-
-                if let NodeType::List(_) = symbols.get_return_type(name, args).unwrap_fn() {
-                    fn_call = format!("(list) eval(fn_{name}(");
+        ASTNode::Call(name, args, line) => {
+            if let Some(body) = get_inline_fn_body(name, args, emitter.symbols) {
+                emitter.line = line.known();
+                buf.emit(&body(args.to_vec(), emitter));
+            } else if emitter.symbols.check_types(name, args) {
+                let compiled_args: Vec<String> = args.iter().map(|arg| compile_expr(arg, emitter).get()).collect();
+                let fn_call = format!("eval(fn_{}({}))", mangle(name), compiled_args.join(", "));
+
+                if let NodeType::List(_) = emitter.symbols.get_return_type(name, args).unwrap_fn() {
+                    buf.emit(&format!("(list) {fn_call}"));
                 } else {
-                    fn_call = format!("eval(fn_{name}(");
-                }
-
-                let max_index = args.len() - 1;
-                
-                for i in 0..max_index {
-                    fn_call.push_str(&format!("{}, ", compile_expr(&args[i], symbols)));
+                    buf.emit(&fn_call);
                 }
-
-                fn_call.push_str(&format!("{}))", compile_expr(&args[max_index], symbols)));
-
-                buf.emit(&fn_call);
             } else {
-                panic!("Could not find function \"{name}\" with args {args:?}");
+                throw(&emitter.symbols.overload_error(name, args));
+            }
+        }
+        ASTNode::Var(s) => {
+            // Arguments and let-bindings live in the frame of the enclosing function
+            emitter.uses_frame = true;
+            if let NodeType::List(_) = s.symbol_type.unwrap_fn() {
+                buf.emit(&format!("((list) force(&frame->{}, frame))", s.name));
+            } else {
+                buf.emit(&format!("force(&frame->{}, frame)", s.name));
             }
         }
-        ASTNode::Var(_) |
         ASTNode::Int(_) |
         ASTNode::Bool(_) => {
-            buf.emit(&format!("{}", node.imm_repr()));
+            buf.emit(&node.imm_repr());
+        }
+        ASTNode::BigInt(v) => {
+            buf.emit(&format!("big_parse(\"{v}\")"));
+        }
+        ASTNode::Float(v) => {
+            buf.emit(&format!("float_bits({v:?})"));
+        }
+        ASTNode::Char(v) => {
+            buf.emit(&(*v as u32).to_string());
+        }
+        // Built from an array of the code points, however long the string is
+        ASTNode::String(v) if v.is_empty() => {
+            buf.emit("((list) NULL)");
+        }
+        ASTNode::String(v) => {
+            let chars: Vec<String> = v.chars().map(|c| (c as u32).to_string()).collect();
+            buf.emit(&format!("list_from_array((long[]) {{{}}}, {})", chars.join(", "), chars.len()));
+        }
+        ASTNode::Tuple(items) => {
+            let items: Vec<String> = items.iter().map(|item| format!("(long) {}", compile_expr(item, emitter))).collect();
+            emitter.tuples.insert(items.len());
+            buf.emit(&format!("make_tuple{}({})", items.len(), items.join(", ")));
+        }
+        ASTNode::Index(tuple, index) => {
+            let NodeType::Tuple(items) = emitter.symbols.get_node_type(tuple).unwrap_fn() else {
+                throw(&format!("Can not take element {index} of a value which is not a tuple"));
+            };
+
+            buf.emit(&element(tuple, items.len(), *index, &items[*index], emitter));
+        }
+        // Declarations only give types, records are built as tuples
+        ASTNode::Struct(_, _) => {}
+        ASTNode::Record(_, fields) => {
+            let fields: Vec<String> = fields.iter().map(|(_, value)| format!("(long) {}", compile_expr(value, emitter))).collect();
+            emitter.tuples.insert(fields.len());
+            buf.emit(&format!("make_tuple{}({})", fields.len(), fields.join(", ")));
+        }
+        ASTNode::Field(record, field) => {
+            let record_type = emitter.symbols.get_node_type(record);
+            let (Some(items), Some((index, field_type))) = (emitter.symbols.elements(&record_type), emitter.symbols.field(&record_type, field)) else {
+                throw(&format!("{} has no field {field}", record_type.unwrap_fn().source()));
+            };
+
+            buf.emit(&element(record, items.len(), index, &field_type, emitter));
+        }
+        ASTNode::With(record, fields) => {
+            let record_type = emitter.symbols.get_node_type(record);
+            let size = emitter.symbols.elements(&record_type).map_or(0, |items| items.len());
+            emitter.tuples.insert(size);
+            emitter.updated_tuples.insert(size);
+
+            let mut code = compile_expr(record, emitter).get();
+            for (field, value) in fields {
+                let Some((index, _)) = emitter.symbols.field(&record_type, field) else {
+                    throw(&format!("{} has no field {field}", record_type.unwrap_fn().source()));
+                };
+                code = format!("with_tuple{size}({code}, {index}, (long) {})", compile_expr(value, emitter));
+            }
+            buf.emit(&code);
         }
     }
 
     buf
 }
 
-fn emit_fn_args(buf: &mut Buffer, args: &Vec<Symbol>) {
-    if args.len() > 0 {
+// Reads an element of a tuple, or a field of a record, with "size" elements
+fn element(tuple: &ASTNode, size: usize, index: usize, element_type: &NodeType, emitter: &mut Emitter) -> String {
+    emitter.tuples.insert(size);
+    let element = format!("((struct tuple{size}*) {})->f{index}", compile_expr(tuple, emitter));
+    if let NodeType::List(_) = element_type.unwrap_fn() {
+        format!("((list) {element})")
+    } else {
+        format!("({element})")
+    }
+}
+
+fn emit_fn_args(buf: &mut Buffer, args: &[Symbol]) {
+    if !args.is_empty() {
         let max_index = args.len() - 1;
         
         for i in 0..max_index {
@@ -150,18 +455,126 @@ fn emit_fn_args(buf: &mut Buffer, args: &Vec<Symbol>) {
     }
 }
 
-fn emit_fn_body(buf: &mut Buffer, symbols: &mut SymbolTable, body: &Vec<ASTNode>) {
-    let max_index = body.len() - 1;
-    for i in 0..max_index {
-        buf.emit(&compile_expr(&body[i], symbols).get());
+// Each call gets a frame of cells holding its arguments and let-bindings.
+// A let-binding is compiled to its own function, which is only run the first
+// time the binding is forced.
+fn emit_frame(buf: &mut Buffer, emitter: &mut Emitter, name: &str, args: &[Symbol], lets: &[(&Symbol, &ASTNode)]) {
+    if args.is_empty() && lets.is_empty() {
+        return;
+    }
+
+    buf.emit(&format!("struct frame_{} {{\n", mangle(name)));
+    for arg in args {
+        buf.emit_instr(&format!("cell {};", arg.name));
+    }
+    for (s, _) in lets {
+        buf.emit_instr(&format!("cell {};", s.name));
+    }
+    buf.emit("};\n\n");
+
+    let c_name = mangle(name);
+
+    for (s, v) in lets {
+        buf.emit(&format!("long let_{c_name}_{}(void* env) {{\n", s.name));
+        emitter.start_function();
+        let result = compile_expr(v, emitter).get();
+        emit_fn_name(buf, name, emitter);
+        if emitter.uses_frame {
+            buf.emit_instr(&format!("struct frame_{c_name}* frame = env;"));
+        }
+        buf.emit_instr(&format!("return (long) {result};\n}}\n"));
+    }
+}
+
+// Runtime functions which can fail take the Flip name of their caller
+fn emit_fn_name(buf: &mut Buffer, name: &str, emitter: &Emitter) {
+    if emitter.uses_fn_name {
+        buf.emit_instr(&format!("const char* flip_fn = {};", c_string(name)));
+    }
+}
+
+// Gives every argument and let-binding in a function body its own slot in the
+// frame, so that shadowed names refer to the right binding
+fn scope_bindings(args: &[Symbol], body: &[ASTNode]) -> (Vec<Symbol>, Vec<ASTNode>) {
+    fn rename(node: &ASTNode, scope: &HashMap<String, String>) -> ASTNode {
+        match node {
+            ASTNode::Var(s) => match scope.get(&s.name) {
+                Some(slot) => ASTNode::Var(Symbol::new_var(slot, s.symbol_type.clone())),
+                None => node.clone(),
+            },
+            ASTNode::Call(name, args, line) => ASTNode::Call(name.clone(), args.iter().map(|arg| rename(arg, scope)).collect(), *line),
+            ASTNode::Tuple(items) => ASTNode::Tuple(items.iter().map(|item| rename(item, scope)).collect()),
+            ASTNode::Index(tuple, index) => ASTNode::Index(Box::new(rename(tuple, scope)), *index),
+            ASTNode::Record(name, fields) => ASTNode::Record(name.clone(), rename_fields(fields, scope)),
+            ASTNode::Field(record, field) => ASTNode::Field(Box::new(rename(record, scope)), field.clone()),
+            ASTNode::With(record, fields) => ASTNode::With(Box::new(rename(record, scope)), rename_fields(fields, scope)),
+            _ => node.clone(),
+        }
+    }
+
+    fn rename_fields(fields: &[(String, ASTNode)], scope: &HashMap<String, String>) -> Vec<(String, ASTNode)> {
+        fields.iter().map(|(field, value)| (field.clone(), rename(value, scope))).collect()
+    }
+
+    let mut scope = HashMap::new();
+    let mut scoped_args = Vec::new();
+
+    for arg in args {
+        let slot = format!("v_{}", mangle(&arg.name));
+        scope.insert(arg.name.clone(), slot.clone());
+        scoped_args.push(Symbol::new_var(&slot, arg.symbol_type.clone()));
+    }
+
+    let mut slots = 0;
+    let mut scoped = Vec::new();
+
+    for node in body {
+        if let ASTNode::Let(s, v, bind) = node {
+            let value = rename(v, &scope);
+            let slot = format!("l{slots}_{}", mangle(&s.name));
+            slots += 1;
+
+            scope.insert(s.name.clone(), slot.clone());
+            scoped.push(ASTNode::Let(Symbol::new_var(&slot, s.symbol_type.clone()), Box::new(value), *bind));
+        } else {
+            scoped.push(rename(node, &scope));
+        }
+    }
+
+    (scoped_args, scoped)
+}
+
+// Flip identifiers may contain characters C does not allow, so they are
+// encoded before being emitted. ASCII letters and digits are kept, "_" becomes
+// "__" and any other byte becomes "_" followed by its value in hex. The
+// generated names are always prefixed ("fn_", "v_", "l0_" ...), so they never
+// clash with C keywords or the runtime in flip.h.
+pub fn mangle(name: &str) -> String {
+    let mut mangled = String::new();
+
+    for byte in name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => mangled.push(byte as char),
+            b'_' => mangled.push_str("__"),
+            _ => mangled.push_str(&format!("_{byte:02X}")),
+        }
     }
-    buf.emit_instr(&format!("return {};\n}}\n", compile_expr(&body[max_index], symbols).get()));
+
+    mangled
 }
 
-fn get_inline_fn_body<'a>(name: &String, args: &Vec<ASTNode>, symbols: &mut SymbolTable) -> Option<InlineFnBody<'a>> {
+fn c_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn get_inline_fn_body<'a>(name: &str, args: &[ASTNode], symbols: &SymbolTable) -> Option<InlineFnBody<'a>> {
+    if !symbols.calls_builtin(name, args) {
+        return None;
+    }
+
     for f in get_inlines() {
         let (fn_name, arg_types, _, body) = f;
-        if name == fn_name && symbols.compare_types(&args, &arg_types) {
+        if name == fn_name && symbols.compare_types(args, &arg_types) {
             return Some(body);
         }
     }
@@ -169,6 +582,11 @@ fn get_inline_fn_body<'a>(name: &String, args: &Vec<ASTNode>, symbols: &mut Symb
     None
 }
 
+// True if the call is compiled to a built-in rather than a user function
+pub fn is_inline_call(name: &str, args: &[ASTNode], symbols: &SymbolTable) -> bool {
+    get_inline_fn_body(name, args, symbols).is_some()
+}
+
 pub fn table_from_inlines() -> SymbolTable {
     let mut table = Vec::new();
 
@@ -181,66 +599,251 @@ pub fn table_from_inlines() -> SymbolTable {
 
 fn get_inlines<'a>() -> Vec<InlineFn<'a>> {
     vec![
-        ("+", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, symbols| binary_op("+", args, symbols)),
-        ("-", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, symbols| binary_op("-", args, symbols)),
-        ("*", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, symbols| binary_op("*", args, symbols)),
-        ("/", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, symbols| binary_op("/", args, symbols)),
-        ("mod", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, symbols| binary_op("%", args, symbols)),
-        ("-", vec![NodeType::Int], NodeType::Int, &|args, symbols| unary_op("-", args, symbols)),
-
-        ("==", vec![NodeType::gen("T"), NodeType::gen("T")], NodeType::Bool, &|args, symbols| binary_op("==", args, symbols)),
-        ("!=", vec![NodeType::gen("T"), NodeType::gen("T")], NodeType::Bool, &|args, symbols| binary_op("!=", args, symbols)),
-        (">", vec![NodeType::gen("T"), NodeType::gen("T")], NodeType::Bool, &|args, symbols| binary_op(">", args, symbols)),
-        ("<", vec![NodeType::gen("T"), NodeType::gen("T")], NodeType::Bool, &|args, symbols| binary_op("<", args, symbols)),
-        (">=", vec![NodeType::gen("T"), NodeType::gen("T")], NodeType::Bool, &|args, symbols| binary_op(">=", args, symbols)),
-        ("<=", vec![NodeType::gen("T"), NodeType::gen("T")], NodeType::Bool, &|args, symbols| binary_op("<=", args, symbols)),
-
-        ("and", vec![NodeType::Bool, NodeType::Bool], NodeType::Bool, &|args, symbols| binary_op("&&", args, symbols)),
-        ("or", vec![NodeType::Bool, NodeType::Bool], NodeType::Bool, &|args, symbols| binary_op("||", args, symbols)),
-        ("not", vec![NodeType::Bool], NodeType::Bool, &|args, symbols| unary_op("!", args, symbols)),
-
-        ("if", vec![NodeType::Bool, NodeType::gen("T"), NodeType::gen("T")], NodeType::gen("T"), &|args, symbols| {
+        ("+", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| binary_op("+", args, emitter)),
+        ("-", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| binary_op("-", args, emitter)),
+        ("*", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| binary_op("*", args, emitter)),
+        ("/", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| checked_op("divide", args, emitter)),
+        ("mod", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| checked_op("modulo", args, emitter)),
+        ("-", vec![NodeType::Int], NodeType::Int, &|args, emitter| unary_op("-", args, emitter)),
+
+        // Int arithmetic which stops the program on overflow, and arithmetic
+        // which wraps around, where "+" would be undefined in C
+        ("checked_add", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| overflow_checked_op("checked_add", args, emitter)),
+        ("checked_sub", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| overflow_checked_op("checked_sub", args, emitter)),
+        ("checked_mul", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| overflow_checked_op("checked_mul", args, emitter)),
+        ("checked_neg", vec![NodeType::Int], NodeType::Int, &|args, emitter| overflow_checked_op("checked_neg", args, emitter)),
+        ("wrapping_add", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| runtime_call("wrapping_add", args, emitter)),
+        ("wrapping_sub", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| runtime_call("wrapping_sub", args, emitter)),
+        ("wrapping_mul", vec![NodeType::Int, NodeType::Int], NodeType::Int, &|args, emitter| runtime_call("wrapping_mul", args, emitter)),
+        ("wrapping_neg", vec![NodeType::Int], NodeType::Int, &|args, emitter| runtime_call("wrapping_neg", args, emitter)),
+
+        // BigInts are pointers, so they are compared by the runtime, before
+        // the generic comparisons below can match
+        ("+", vec![NodeType::BigInt, NodeType::BigInt], NodeType::BigInt, &|args, emitter| runtime_call("big_add", args, emitter)),
+        ("-", vec![NodeType::BigInt, NodeType::BigInt], NodeType::BigInt, &|args, emitter| runtime_call("big_sub", args, emitter)),
+        ("*", vec![NodeType::BigInt, NodeType::BigInt], NodeType::BigInt, &|args, emitter| runtime_call("big_mul", args, emitter)),
+        ("/", vec![NodeType::BigInt, NodeType::BigInt], NodeType::BigInt, &|args, emitter| checked_op("big_divide", args, emitter)),
+        ("mod", vec![NodeType::BigInt, NodeType::BigInt], NodeType::BigInt, &|args, emitter| checked_op("big_modulo", args, emitter)),
+        ("-", vec![NodeType::BigInt], NodeType::BigInt, &|args, emitter| runtime_call("big_neg", args, emitter)),
+        ("big", vec![NodeType::Int], NodeType::BigInt, &|args, emitter| runtime_call("big_from_long", args, emitter)),
+        ("int", vec![NodeType::BigInt], NodeType::Int, &|args, emitter| checked_op("big_to_long", args, emitter)),
+
+        ("==", vec![NodeType::BigInt, NodeType::BigInt], NodeType::Bool, &|args, emitter| big_comparison("==", args, emitter)),
+        ("!=", vec![NodeType::BigInt, NodeType::BigInt], NodeType::Bool, &|args, emitter| big_comparison("!=", args, emitter)),
+        (">", vec![NodeType::BigInt, NodeType::BigInt], NodeType::Bool, &|args, emitter| big_comparison(">", args, emitter)),
+        ("<", vec![NodeType::BigInt, NodeType::BigInt], NodeType::Bool, &|args, emitter| big_comparison("<", args, emitter)),
+        (">=", vec![NodeType::BigInt, NodeType::BigInt], NodeType::Bool, &|args, emitter| big_comparison(">=", args, emitter)),
+        ("<=", vec![NodeType::BigInt, NodeType::BigInt], NodeType::Bool, &|args, emitter| big_comparison("<=", args, emitter)),
+
+        // Chars are code points, so the generic comparisons work on them
+        ("to_char", vec![NodeType::Int], NodeType::Char, &|args, emitter| checked_op("to_char", args, emitter)),
+        ("int", vec![NodeType::Char], NodeType::Int, &|args, emitter| compile_expr(&args[0], emitter).get()),
+        ("is_digit", vec![NodeType::Char], NodeType::Bool, &|args, emitter| runtime_call("char_is_digit", args, emitter)),
+        ("is_alpha", vec![NodeType::Char], NodeType::Bool, &|args, emitter| runtime_call("char_is_alpha", args, emitter)),
+
+        // Floats are passed as their bits, so they are compared as doubles
+        ("+", vec![NodeType::Float, NodeType::Float], NodeType::Float, &|args, emitter| runtime_call("float_add", args, emitter)),
+        ("-", vec![NodeType::Float, NodeType::Float], NodeType::Float, &|args, emitter| runtime_call("float_sub", args, emitter)),
+        ("*", vec![NodeType::Float, NodeType::Float], NodeType::Float, &|args, emitter| runtime_call("float_mul", args, emitter)),
+        ("/", vec![NodeType::Float, NodeType::Float], NodeType::Float, &|args, emitter| runtime_call("float_div", args, emitter)),
+        ("-", vec![NodeType::Float], NodeType::Float, &|args, emitter| runtime_call("float_neg", args, emitter)),
+        ("to_float", vec![NodeType::Int], NodeType::Float, &|args, emitter| runtime_call("float_from_long", args, emitter)),
+        ("floor", vec![NodeType::Float], NodeType::Int, &|args, emitter| checked_op("float_floor", args, emitter)),
+        ("round", vec![NodeType::Float], NodeType::Int, &|args, emitter| checked_op("float_round", args, emitter)),
+        ("sqrt", vec![NodeType::Float], NodeType::Float, &|args, emitter| runtime_call("float_sqrt", args, emitter)),
+
+        ("==", vec![NodeType::Float, NodeType::Float], NodeType::Bool, &|args, emitter| float_comparison("==", args, emitter)),
+        ("!=", vec![NodeType::Float, NodeType::Float], NodeType::Bool, &|args, emitter| float_comparison("!=", args, emitter)),
+        (">", vec![NodeType::Float, NodeType::Float], NodeType::Bool, &|args, emitter| float_comparison(">", args, emitter)),
+        ("<", vec![NodeType::Float, NodeType::Float], NodeType::Bool, &|args, emitter| float_comparison("<", args, emitter)),
+        (">=", vec![NodeType::Float, NodeType::Float], NodeType::Bool, &|args, emitter| float_comparison(">=", args, emitter)),
+        ("<=", vec![NodeType::Float, NodeType::Float], NodeType::Bool, &|args, emitter| float_comparison("<=", args, emitter)),
+
+        ("==", vec![NodeType::gen("T"), NodeType::gen("T")], NodeType::Bool, &|args, emitter| equality("==", args, emitter)),
+        ("!=", vec![NodeType::gen("T"), NodeType::gen("T")], NodeType::Bool, &|args, emitter| equality("!=", args, emitter)),
+        // Only values with one obvious order can be ordered, not lists, tuples or records
+        (">", vec![NodeType::Int, NodeType::Int], NodeType::Bool, &|args, emitter| binary_op(">", args, emitter)),
+        ("<", vec![NodeType::Int, NodeType::Int], NodeType::Bool, &|args, emitter| binary_op("<", args, emitter)),
+        (">=", vec![NodeType::Int, NodeType::Int], NodeType::Bool, &|args, emitter| binary_op(">=", args, emitter)),
+        ("<=", vec![NodeType::Int, NodeType::Int], NodeType::Bool, &|args, emitter| binary_op("<=", args, emitter)),
+        (">", vec![NodeType::Char, NodeType::Char], NodeType::Bool, &|args, emitter| binary_op(">", args, emitter)),
+        ("<", vec![NodeType::Char, NodeType::Char], NodeType::Bool, &|args, emitter| binary_op("<", args, emitter)),
+        (">=", vec![NodeType::Char, NodeType::Char], NodeType::Bool, &|args, emitter| binary_op(">=", args, emitter)),
+        ("<=", vec![NodeType::Char, NodeType::Char], NodeType::Bool, &|args, emitter| binary_op("<=", args, emitter)),
+        (">", vec![NodeType::Bool, NodeType::Bool], NodeType::Bool, &|args, emitter| binary_op(">", args, emitter)),
+        ("<", vec![NodeType::Bool, NodeType::Bool], NodeType::Bool, &|args, emitter| binary_op("<", args, emitter)),
+        (">=", vec![NodeType::Bool, NodeType::Bool], NodeType::Bool, &|args, emitter| binary_op(">=", args, emitter)),
+        ("<=", vec![NodeType::Bool, NodeType::Bool], NodeType::Bool, &|args, emitter| binary_op("<=", args, emitter)),
+
+        // Tuples are pointers to structs, see "tuple_structs"
+        ("fst", vec![NodeType::Tuple(vec![NodeType::gen("A"), NodeType::gen("B")])], NodeType::gen("A"), &|args, emitter| {
+            compile_expr(&ASTNode::Index(Box::new(args[0].clone()), 0), emitter).get()
+        }),
+        ("snd", vec![NodeType::Tuple(vec![NodeType::gen("A"), NodeType::gen("B")])], NodeType::gen("B"), &|args, emitter| {
+            compile_expr(&ASTNode::Index(Box::new(args[0].clone()), 1), emitter).get()
+        }),
+
+        ("and", vec![NodeType::Bool, NodeType::Bool], NodeType::Bool, &|args, emitter| binary_op("&&", args, emitter)),
+        ("or", vec![NodeType::Bool, NodeType::Bool], NodeType::Bool, &|args, emitter| binary_op("||", args, emitter)),
+        ("not", vec![NodeType::Bool], NodeType::Bool, &|args, emitter| unary_op("!", args, emitter)),
+
+        ("if", vec![NodeType::Bool, NodeType::gen("T"), NodeType::gen("T")], NodeType::gen("T"), &|args, emitter| {
             format!("({} ? {} : {})",
-                compile_expr(&args[0], symbols),
-                compile_expr(&args[1], symbols),
-                compile_expr(&args[2], symbols))
+                compile_expr(&args[0], emitter),
+                compile_expr(&args[1], emitter),
+                compile_expr(&args[2], emitter))
         }),
 
         ("[Int]", vec![], NodeType::List(Box::new(NodeType::Int)), &|_, _| {
-            format!("((list) NULL)")
+            String::from("((list) NULL)")
         }),
-        ("len", vec![NodeType::List(Box::new(NodeType::gen("T")))], NodeType::Int, &|args, symbols| {
-            format!("(len({}))",
-                compile_expr(&args[0], symbols))
+        ("[Char]", vec![], NodeType::List(Box::new(NodeType::Char)), &|_, _| {
+            String::from("((list) NULL)")
         }),
-        ("head", vec![NodeType::List(Box::new(NodeType::gen("T")))], NodeType::gen("T"), &|args, symbols| {
-            format!("(({})->head)",
-                compile_expr(&args[0], symbols))
+        ("[Float]", vec![], NodeType::List(Box::new(NodeType::Float)), &|_, _| {
+            String::from("((list) NULL)")
         }),
-        ("tail", vec![NodeType::List(Box::new(NodeType::gen("T")))], NodeType::List(Box::new(NodeType::gen("T"))), &|args, symbols| {
-            format!("(({})->tail)",
-                compile_expr(&args[0], symbols))
+        ("[BigInt]", vec![], NodeType::List(Box::new(NodeType::BigInt)), &|_, _| {
+            String::from("((list) NULL)")
         }),
+        ("len", vec![NodeType::List(Box::new(NodeType::gen("T")))], NodeType::Int, &|args, emitter| {
+            format!("(len({}))",
+                compile_expr(&args[0], emitter))
+        }),
+        ("head", vec![NodeType::List(Box::new(NodeType::gen("T")))], NodeType::gen("T"), &|args, emitter| checked_op("head", args, emitter)),
+        ("tail", vec![NodeType::List(Box::new(NodeType::gen("T")))], NodeType::List(Box::new(NodeType::gen("T"))), &|args, emitter| checked_op("tail", args, emitter)),
         // List concatenation
-        ("push", vec![NodeType::List(Box::new(NodeType::gen("T"))), NodeType::gen("T")], NodeType::List(Box::new(NodeType::gen("T"))), &|args, symbols| {
+        ("push", vec![NodeType::List(Box::new(NodeType::gen("T"))), NodeType::gen("T")], NodeType::List(Box::new(NodeType::gen("T"))), &|args, emitter| {
             format!("push({}, {})",
-                compile_expr(&args[0], symbols),
-                compile_expr(&args[1], symbols))
+                compile_expr(&args[0], emitter),
+                compile_expr(&args[1], emitter))
         }),
-        ("is_null", vec![NodeType::List(Box::new(NodeType::gen("T")))], NodeType::Bool, &|args, symbols| {
+        ("is_null", vec![NodeType::List(Box::new(NodeType::gen("T")))], NodeType::Bool, &|args, emitter| {
             format!("({} == NULL)",
-                compile_expr(&args[0], symbols))
+                compile_expr(&args[0], emitter))
+        }),
+
+        // Assertions, for test blocks, stop the program if they fail
+        ("assert", vec![NodeType::Bool], NodeType::Bool, &|args, emitter| checked_op("flip_assert", args, emitter)),
+        ("assert_eq", vec![NodeType::BigInt, NodeType::BigInt], NodeType::Bool, &|args, emitter| checked_op("big_assert_eq", args, emitter)),
+        ("assert_eq", vec![NodeType::Float, NodeType::Float], NodeType::Bool, &|args, emitter| checked_op("float_assert_eq", args, emitter)),
+        ("assert_eq", vec![NodeType::gen("T"), NodeType::gen("T")], NodeType::Bool, &|args, emitter| {
+            match emitter.symbols.get_node_type(&args[0]).unwrap_fn() {
+                t if has_equality_fn(&t) => format!("flip_assert_eq({}, 1, {})", equality_call(&t, args, emitter), emitter.fn_name()),
+                _ => checked_op("flip_assert_eq", args, emitter),
+            }
         })
     ]
 }
 
-fn unary_op<'a>(op: &'a str, args: Vec<ASTNode>, symbols: &mut SymbolTable) -> String {
+fn unary_op(op: &str, args: Vec<ASTNode>, emitter: &mut Emitter) -> String {
     format!("({op}({}))",
-        compile_expr(&args[0], symbols))
+        compile_expr(&args[0], emitter))
 }
 
-fn binary_op<'a>(op: &'a str, args: Vec<ASTNode>, symbols: &mut SymbolTable) -> String {
+fn binary_op(op: &str, args: Vec<ASTNode>, emitter: &mut Emitter) -> String {
     format!("({} {op} {})",
-        compile_expr(&args[0], symbols),
-        compile_expr(&args[1], symbols))
+        compile_expr(&args[0], emitter),
+        compile_expr(&args[1], emitter))
+}
+
+// Tuples, records and lists are equal if their elements are, so they are
+// compared by a function generated for their type, see "equality_definition"
+fn equality(op: &str, args: Vec<ASTNode>, emitter: &mut Emitter) -> String {
+    match emitter.symbols.get_node_type(&args[0]).unwrap_fn() {
+        t if has_equality_fn(&t) && op == "==" => format!("({})", equality_call(&t, args, emitter)),
+        t if has_equality_fn(&t) => format!("(!{})", equality_call(&t, args, emitter)),
+        _ => binary_op(op, args, emitter),
+    }
+}
+
+fn equality_call(t: &NodeType, args: Vec<ASTNode>, emitter: &mut Emitter) -> String {
+    format!("{}((long) {}, (long) {})",
+        equality_fn(t),
+        compile_expr(&args[0], emitter),
+        compile_expr(&args[1], emitter))
+}
+
+fn runtime_call(runtime_fn: &str, args: Vec<ASTNode>, emitter: &mut Emitter) -> String {
+    let args: Vec<String> = args.iter().map(|arg| compile_expr(arg, emitter).get()).collect();
+    format!("{runtime_fn}({})", args.join(", "))
+}
+
+fn big_comparison(op: &str, args: Vec<ASTNode>, emitter: &mut Emitter) -> String {
+    format!("(big_cmp({}, {}) {op} 0)",
+        compile_expr(&args[0], emitter),
+        compile_expr(&args[1], emitter))
+}
+
+fn float_comparison(op: &str, args: Vec<ASTNode>, emitter: &mut Emitter) -> String {
+    format!("(bits_float({}) {op} bits_float({}))",
+        compile_expr(&args[0], emitter),
+        compile_expr(&args[1], emitter))
+}
+
+fn overflow_checked_op(runtime_fn: &str, args: Vec<ASTNode>, emitter: &mut Emitter) -> String {
+    let call = Unscoped.fold_node(ASTNode::Call(runtime_fn.to_string(), args.clone(), Line::default()));
+    let error = c_string(&overflow::error_message(&call));
+    // 0 when the line is not known, as when the compiler made the call
+    let line = emitter.line.unwrap_or(0);
+    let args: Vec<String> = args.iter().map(|arg| compile_expr(arg, emitter).get()).collect();
+    format!("{runtime_fn}({}, {error}, {line}, {})", args.join(", "), emitter.fn_name())
+}
+
+// Gives variables back the names they have in the source, see "scope_bindings"
+struct Unscoped;
+
+impl Fold for Unscoped {
+    fn fold_var(&mut self, symbol: Symbol) -> ASTNode {
+        match demangle_slot(&symbol.name) {
+            Some(name) => ASTNode::Var(Symbol::new_var(&name, symbol.symbol_type)),
+            None => ASTNode::Var(symbol),
+        }
+    }
+}
+
+// Reverses "mangle", returning None if the name could not have come from it
+fn demangle(mangled: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut chars = mangled.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '_' => match chars.next()? {
+                '_' => bytes.push(b'_'),
+                high => {
+                    let low = chars.next()?;
+                    let hex: String = [high, low].iter().collect();
+                    bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+                }
+            },
+            c if c.is_ascii_alphanumeric() => bytes.push(c as u8),
+            _ => return None,
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+// Recovers the source name of an argument ("v_") or let-binding ("l0_",
+// "l1_" ...) slot
+fn demangle_slot(slot: &str) -> Option<String> {
+    if let Some(mangled) = slot.strip_prefix("v_") {
+        return demangle(mangled);
+    }
+
+    let (index, mangled) = slot.split_once('_')?;
+    if index.starts_with('l') && index.len() > 1 && index[1..].chars().all(|c| c.is_ascii_digit()) {
+        return demangle(mangled);
+    }
+
+    None
+}
+
+// Runtime functions which can fail are passed the name of the calling function,
+// so that errors are reported in terms of the Flip source
+fn checked_op(runtime_fn: &str, args: Vec<ASTNode>, emitter: &mut Emitter) -> String {
+    let mut args: Vec<String> = args.iter().map(|arg| compile_expr(arg, emitter).get()).collect();
+    args.push(emitter.fn_name());
+    format!("{runtime_fn}({})", args.join(", "))
 }
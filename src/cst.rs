@@ -0,0 +1,380 @@
+// A concrete syntax tree, which unlike the AST keeps comments and blank lines,
+// and is built before any symbols are resolved. It is what the formatter prints.
+
+use crate::error::{throw, throw_at};
+use crate::tokens::{Token, TokenType};
+
+// A node with the comments around it
+#[derive(Debug, Clone)]
+pub struct Commented<T> {
+    // Whole-line comments before the node, with "" for blank lines between them
+    pub comments: Vec<String>,
+    pub node: T,
+    // A comment after the node, on the line it ends
+    pub trailing: Option<String>,
+    // Whether there were blank lines before the node (or its comments)
+    pub blank_before: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    // Integers, booleans and names
+    Atom(String),
+    // Calls, including let-bindings "=(name, value)" and lists "[Int]()", and
+    // tuples "(a, b)", which have no name. The last field holds comments
+    // before the closing parenthesis.
+    Call(String, Vec<Commented<Expr>>, Vec<String>),
+    // An element of a tuple, like "pair.0", or a field of a record, like "point.x"
+    Field(Box<Expr>, String),
+    // A record, like "Point { x: 1, y: 2 }", whose fields are "Named". The
+    // last field holds comments before the closing brace, as for calls.
+    Record(String, Vec<Commented<Expr>>, Vec<String>),
+    // An updated record, like "point with { x: 3 }"
+    With(Box<Expr>, Vec<Commented<Expr>>, Vec<String>),
+    // "name: value", in the braces of a record
+    Named(String, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    pub param_type: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    // Lines like "#[allow(unused_param)]" before the function
+    pub attributes: Vec<String>,
+    // Set for "test" blocks, whose name is a string, quotes included
+    pub is_test: bool,
+    pub name: String,
+    pub params: Vec<Param>,
+    pub return_type: Option<String>,
+    // A comment after the opening brace
+    pub header_comment: Option<String>,
+    pub body: Vec<Commented<Expr>>,
+    // Comments before the closing brace
+    pub end_comments: Vec<String>,
+}
+
+// struct Name { field: Type, ... }
+#[derive(Debug, Clone)]
+pub struct Struct {
+    pub attributes: Vec<String>,
+    pub name: String,
+    pub fields: Vec<Commented<Param>>,
+    // Comments before the closing brace
+    pub end_comments: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Item {
+    Function(Function),
+    Struct(Struct),
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub items: Vec<Commented<Item>>,
+    // Comments after the last item
+    pub end_comments: Vec<String>,
+}
+
+// Tokens should come from "scanner::get_tokens_with_comments"
+pub fn parse(tokens: Vec<Token>) -> SourceFile {
+    let mut cursor = Cursor { tokens, next: 0, line: 0, pending: Vec::new() };
+    let mut items = Vec::new();
+
+    loop {
+        let (comments, blank_before) = cursor.leading();
+
+        if cursor.peek().is_none() {
+            return SourceFile { items, end_comments: comments };
+        }
+
+        let mut attributes = Vec::new();
+        while cursor.peek_is(TokenType::Attribute) {
+            attributes.push(cursor.consume().content);
+        }
+
+        let name = cursor.expect(TokenType::Literal).content;
+        let node = if name == "struct" && cursor.peek_is(TokenType::Literal) {
+            Item::Struct(Struct { attributes, ..consume_struct(&mut cursor) })
+        } else {
+            Item::Function(Function { attributes, ..consume_fn(name, &mut cursor) })
+        };
+        let trailing = cursor.trailing();
+        items.push(Commented { comments, node, trailing, blank_before });
+    }
+}
+
+struct Cursor {
+    tokens: Vec<Token>,
+    next: usize,
+    // Line of the last token consumed
+    line: usize,
+    // Comments in places they cannot be kept, moved to the next node
+    pending: Vec<String>,
+}
+
+impl Cursor {
+    // The next token which is not a comment
+    fn peek(&self) -> Option<&Token> {
+        self.tokens[self.next..].iter().find(|t| t.token_type != TokenType::Comment)
+    }
+
+    fn peek_is(&self, token_type: TokenType) -> bool {
+        self.peek().is_some_and(|t| t.token_type == token_type)
+    }
+
+    // "with" is only a keyword when it is followed by "{", like in the parser
+    fn peek_is_with(&self) -> bool {
+        let mut tokens = self.tokens[self.next..].iter().filter(|t| t.token_type != TokenType::Comment);
+        tokens.next().is_some_and(|t| t.token_type == TokenType::Literal && t.content == "with")
+            && tokens.next().is_some_and(|t| t.token_type == TokenType::LeftBrace)
+    }
+
+    fn consume(&mut self) -> Token {
+        while let Some(token) = self.tokens.get(self.next) {
+            self.next += 1;
+            self.line = token.line;
+
+            if token.token_type == TokenType::Comment {
+                self.pending.push(token.content.clone());
+            } else {
+                return token.clone();
+            }
+        }
+
+        throw("Expected another token, but reached end");
+    }
+
+    fn expect(&mut self, expected: TokenType) -> Token {
+        let token = self.consume();
+
+        if token.token_type == expected {
+            token
+        } else {
+            throw_at(&format!("Unexpected token {}, expected {expected:?}", token.content), token.line);
+        }
+    }
+
+    // Comments before the next node, and whether a blank line comes first
+    fn leading(&mut self) -> (Vec<String>, bool) {
+        let mut comments = std::mem::take(&mut self.pending);
+        let blank_before = self.line > 0 && self.tokens.get(self.next).is_some_and(|t| t.line > self.line + 1);
+
+        while let Some(token) = self.tokens.get(self.next).filter(|t| t.token_type == TokenType::Comment) {
+            if !comments.is_empty() && token.line > self.line + 1 {
+                comments.push(String::new());
+            }
+            comments.push(token.content.clone());
+            self.line = token.line;
+            self.next += 1;
+        }
+
+        let comments_end = self.line;
+        if !comments.is_empty() && self.tokens.get(self.next).is_some_and(|t| {
+            t.line > comments_end + 1 && !matches!(t.token_type, TokenType::RightBrace | TokenType::RightParen)
+        }) {
+            comments.push(String::new());
+        }
+
+        (comments, blank_before)
+    }
+
+    // A comment on the same line as the last token
+    fn trailing(&mut self) -> Option<String> {
+        match self.tokens.get(self.next) {
+            Some(token) if token.token_type == TokenType::Comment && token.line == self.line => {
+                self.next += 1;
+                Some(token.content.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+fn consume_struct(cursor: &mut Cursor) -> Struct {
+    let name = cursor.expect(TokenType::Literal).content;
+    cursor.expect(TokenType::LeftBrace);
+
+    let (fields, end_comments) = consume_list(cursor, TokenType::RightBrace, |cursor| {
+        let name = cursor.expect(TokenType::Literal).content;
+        cursor.expect(TokenType::Colon);
+        Param { name, param_type: consume_type(cursor) }
+    });
+
+    Struct { attributes: Vec::new(), name, fields, end_comments }
+}
+
+fn consume_fn(name: String, cursor: &mut Cursor) -> Function {
+    if name == "test" && cursor.peek_is(TokenType::String) {
+        let name = cursor.consume().content;
+        return consume_body(cursor, true, name, Vec::new(), None);
+    }
+
+    cursor.expect(TokenType::LeftParen);
+
+    let mut params = Vec::new();
+    while !cursor.peek_is(TokenType::RightParen) {
+        if !params.is_empty() {
+            cursor.expect(TokenType::Comma);
+        }
+        let name = cursor.expect(TokenType::Literal).content;
+        cursor.expect(TokenType::Colon);
+        let param_type = consume_type(cursor);
+        params.push(Param { name, param_type });
+    }
+    cursor.expect(TokenType::RightParen);
+
+    let return_type = if cursor.peek_is(TokenType::Colon) {
+        cursor.consume();
+        Some(consume_type(cursor))
+    } else {
+        None
+    };
+
+    consume_body(cursor, false, name, params, return_type)
+}
+
+// A type as one string, like "Int", "[Int]" or "(Int, [Bool])"
+fn consume_type(cursor: &mut Cursor) -> String {
+    let token = cursor.consume();
+
+    match token.token_type {
+        TokenType::LeftParen => {
+            let mut items = vec![consume_type(cursor)];
+            while cursor.peek_is(TokenType::Comma) {
+                cursor.consume();
+                items.push(consume_type(cursor));
+            }
+            cursor.expect(TokenType::RightParen);
+            format!("({})", items.join(", "))
+        }
+        // Lists of tuples, like "[(Int, Bool)]"
+        TokenType::Literal if token.content.chars().all(|c| c == '[') => {
+            let item = consume_type(cursor);
+            let close = cursor.expect(TokenType::Literal).content;
+            format!("{}{item}{close}", token.content)
+        }
+        TokenType::Literal => token.content,
+        _ => throw_at(&format!("Unexpected token {}, expected a type", token.content), token.line),
+    }
+}
+
+fn consume_body(
+    cursor: &mut Cursor,
+    is_test: bool,
+    name: String,
+    params: Vec<Param>,
+    return_type: Option<String>,
+) -> Function {
+    cursor.expect(TokenType::LeftBrace);
+    let header_comment = cursor.trailing();
+
+    let mut body = Vec::new();
+    loop {
+        let (comments, blank_before) = cursor.leading();
+
+        if cursor.peek_is(TokenType::RightBrace) {
+            cursor.consume();
+            return Function {
+                attributes: Vec::new(),
+                is_test,
+                name,
+                params,
+                return_type,
+                header_comment,
+                body,
+                end_comments: comments,
+            };
+        }
+
+        let node = consume_expr(cursor);
+        let trailing = cursor.trailing();
+        body.push(Commented { comments, node, trailing, blank_before });
+    }
+}
+
+fn consume_expr(cursor: &mut Cursor) -> Expr {
+    let token = cursor.consume();
+
+    let mut expr = match token.token_type {
+        TokenType::Integer | TokenType::Float | TokenType::Char | TokenType::String | TokenType::True | TokenType::False => Expr::Atom(token.content),
+        TokenType::Literal | TokenType::Let if cursor.peek_is(TokenType::LeftParen) => {
+            cursor.consume();
+            consume_args(token.content, cursor)
+        }
+        TokenType::Literal if cursor.peek_is(TokenType::LeftBrace) => {
+            let (fields, end_comments) = consume_field_values(cursor);
+            Expr::Record(token.content, fields, end_comments)
+        }
+        TokenType::Literal => Expr::Atom(token.content),
+        TokenType::LeftParen => consume_args(String::new(), cursor),
+        _ => throw_at(&format!("Invalid argument: {}", token.content), token.line),
+    };
+
+    loop {
+        if cursor.peek_is(TokenType::Dot) {
+            cursor.consume();
+            expr = Expr::Field(Box::new(expr), cursor.consume().content);
+        } else if cursor.peek_is_with() {
+            cursor.consume();
+            let (fields, end_comments) = consume_field_values(cursor);
+            expr = Expr::With(Box::new(expr), fields, end_comments);
+        } else {
+            return expr;
+        }
+    }
+}
+
+// The arguments after the opening parenthesis of a call or tuple
+fn consume_args(name: String, cursor: &mut Cursor) -> Expr {
+    let (args, end_comments) = consume_list(cursor, TokenType::RightParen, consume_expr);
+    Expr::Call(name, args, end_comments)
+}
+
+// "{ name: value, ... }" in a record
+fn consume_field_values(cursor: &mut Cursor) -> (Vec<Commented<Expr>>, Vec<String>) {
+    cursor.expect(TokenType::LeftBrace);
+
+    consume_list(cursor, TokenType::RightBrace, |cursor| {
+        let name = cursor.expect(TokenType::Literal).content;
+        cursor.expect(TokenType::Colon);
+        Expr::Named(name, Box::new(consume_expr(cursor)))
+    })
+}
+
+// Items separated by commas, up to and including "close", and the comments
+// before it
+fn consume_list<T>(cursor: &mut Cursor, close: TokenType, item: impl Fn(&mut Cursor) -> T) -> (Vec<Commented<T>>, Vec<String>) {
+    let mut items = Vec::new();
+
+    loop {
+        let (comments, _) = cursor.leading();
+
+        if cursor.peek_is(close.clone()) {
+            cursor.consume();
+            return (items, comments);
+        }
+
+        let node = item(cursor);
+
+        // Comments after an item usually follow its comma
+        let mut trailing = cursor.trailing();
+        let more = cursor.peek_is(TokenType::Comma);
+        if more {
+            cursor.consume();
+            trailing = trailing.or_else(|| cursor.trailing());
+        }
+
+        items.push(Commented { comments, node, trailing, blank_before: false });
+
+        if !more {
+            let (end_comments, _) = cursor.leading();
+            cursor.expect(close);
+            return (items, end_comments);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+// Text and JSON dumps of each compiler stage, for "flip dump"
+
+use crate::ast::{ASTNode, NodeType};
+use crate::json::Json;
+use crate::symbols::{Symbol, SymbolTable};
+use crate::tokens::Token;
+
+pub fn tokens(tokens: &[Token], json: bool) -> String {
+    if json {
+        let tokens = tokens
+            .iter()
+            .map(|t| {
+                Json::object(vec![
+                    ("type", Json::str(&format!("{:?}", t.token_type))),
+                    ("content", t.content.as_str().into()),
+                    ("line", t.line.into()),
+                    ("column", t.column.into()),
+                ])
+            })
+            .collect();
+        return format!("{}\n", Json::Array(tokens));
+    }
+
+    tokens
+        .iter()
+        .map(|t| format!("{:>4}:{:<4} {:<10} {}\n", t.line, t.column, format!("{:?}", t.token_type), t.content))
+        .collect()
+}
+
+// Every expression is printed with its type, resolved like the compiler does
+pub fn ast(tree: &[ASTNode], symbols: &SymbolTable, json: bool) -> String {
+    if json {
+        let tree = tree.iter().map(|node| node_json(node, symbols)).collect();
+        return format!("{}\n", Json::Array(tree));
+    }
+
+    let mut out = String::new();
+    for node in tree {
+        write_node(&mut out, node, symbols, 0);
+    }
+    out
+}
+
+pub fn symbols(symbols: &SymbolTable, json: bool) -> String {
+    if json {
+        let table = symbols
+            .iter()
+            .map(|s| {
+                let arg_types = match &s.arg_types {
+                    Some(arg_types) => Json::Array(arg_types.iter().map(type_json).collect()),
+                    None => Json::Null,
+                };
+                Json::object(vec![
+                    ("name", s.name.as_str().into()),
+                    ("type", type_json(&s.symbol_type)),
+                    ("arg_types", arg_types),
+                ])
+            })
+            .collect();
+        return format!("{}\n", Json::Array(table));
+    }
+
+    symbols.iter().map(|s| format!("{}\n", signature(s))).collect()
+}
+
+pub fn c(code: &str, json: bool) -> String {
+    if json {
+        format!("{}\n", Json::object(vec![("c", code.into())]))
+    } else {
+        code.to_string()
+    }
+}
+
+fn signature(symbol: &Symbol) -> String {
+    match &symbol.arg_types {
+        Some(arg_types) => {
+            let arg_types: Vec<String> = arg_types.iter().map(|t| t.to_string()).collect();
+            format!("{}({}): {}", symbol.name, arg_types.join(", "), symbol.symbol_type)
+        }
+        None => format!("{}: {}", symbol.name, symbol.symbol_type),
+    }
+}
+
+fn write_node(out: &mut String, node: &ASTNode, symbols: &SymbolTable, depth: usize) {
+    out.push_str(&"  ".repeat(depth));
+
+    match node {
+        ASTNode::Fn(name, args, return_type, body) => {
+            let args: Vec<String> = args.iter().map(signature).collect();
+            out.push_str(&format!("Fn {name}({}): {return_type}\n", args.join(", ")));
+            for node in body {
+                write_node(out, node, symbols, depth + 1);
+            }
+        }
+        ASTNode::Call(name, args, _) => {
+            out.push_str(&format!("Call {name} : {}\n", symbols.get_node_type(node)));
+            for arg in args {
+                write_node(out, arg, symbols, depth + 1);
+            }
+        }
+        ASTNode::Let(symbol, value, _) => {
+            out.push_str(&format!("Let {} : {}\n", symbol.name, symbol.symbol_type));
+            write_node(out, value, symbols, depth + 1);
+        }
+        ASTNode::Var(symbol) => out.push_str(&format!("Var {} : {}\n", symbol.name, symbol.symbol_type)),
+        ASTNode::Int(v) => out.push_str(&format!("Int {v}\n")),
+        ASTNode::BigInt(v) => out.push_str(&format!("BigInt {v}\n")),
+        ASTNode::Float(v) => out.push_str(&format!("Float {v:?}\n")),
+        ASTNode::Char(_) => out.push_str(&format!("Char {node}\n")),
+        ASTNode::String(_) => out.push_str(&format!("String {node}\n")),
+        ASTNode::Bool(v) => out.push_str(&format!("Bool {v}\n")),
+        ASTNode::Tuple(items) => {
+            out.push_str(&format!("Tuple : {}\n", symbols.get_node_type(node)));
+            for item in items {
+                write_node(out, item, symbols, depth + 1);
+            }
+        }
+        ASTNode::Index(tuple, index) => {
+            out.push_str(&format!("Index {index} : {}\n", symbols.get_node_type(node)));
+            write_node(out, tuple, symbols, depth + 1);
+        }
+        ASTNode::Struct(name, fields) => {
+            let fields: Vec<String> = fields.iter().map(|(field, t)| format!("{field}: {t}")).collect();
+            out.push_str(&format!("Struct {name}({})\n", fields.join(", ")));
+        }
+        ASTNode::Record(name, fields) => {
+            out.push_str(&format!("Record {name} : {}\n", symbols.get_node_type(node)));
+            write_fields(out, fields, symbols, depth + 1);
+        }
+        ASTNode::Field(record, field) => {
+            out.push_str(&format!("Field {field} : {}\n", symbols.get_node_type(node)));
+            write_node(out, record, symbols, depth + 1);
+        }
+        ASTNode::With(record, fields) => {
+            out.push_str(&format!("With : {}\n", symbols.get_node_type(node)));
+            write_node(out, record, symbols, depth + 1);
+            write_fields(out, fields, symbols, depth + 1);
+        }
+    }
+}
+
+// Each field name on its own line, with its value below
+fn write_fields(out: &mut String, fields: &[(String, ASTNode)], symbols: &SymbolTable, depth: usize) {
+    for (field, value) in fields {
+        out.push_str(&format!("{}{field}:\n", "  ".repeat(depth)));
+        write_node(out, value, symbols, depth + 1);
+    }
+}
+
+fn node_json(node: &ASTNode, symbols: &SymbolTable) -> Json {
+    match node {
+        ASTNode::Fn(name, args, return_type, body) => {
+            let args = args
+                .iter()
+                .map(|arg| Json::object(vec![("name", arg.name.as_str().into()), ("type", type_json(&arg.symbol_type))]))
+                .collect();
+            Json::object(vec![
+                ("kind", "Fn".into()),
+                ("name", name.as_str().into()),
+                ("args", Json::Array(args)),
+                ("return_type", type_json(return_type)),
+                ("body", Json::Array(body.iter().map(|node| node_json(node, symbols)).collect())),
+            ])
+        }
+        ASTNode::Call(name, args, _) => Json::object(vec![
+            ("kind", "Call".into()),
+            ("name", name.as_str().into()),
+            ("type", type_json(&symbols.get_node_type(node))),
+            ("args", Json::Array(args.iter().map(|arg| node_json(arg, symbols)).collect())),
+        ]),
+        ASTNode::Let(symbol, value, _) => Json::object(vec![
+            ("kind", "Let".into()),
+            ("name", symbol.name.as_str().into()),
+            ("type", type_json(&symbol.symbol_type)),
+            ("value", node_json(value, symbols)),
+        ]),
+        ASTNode::Var(symbol) => Json::object(vec![
+            ("kind", "Var".into()),
+            ("name", symbol.name.as_str().into()),
+            ("type", type_json(&symbol.symbol_type)),
+        ]),
+        ASTNode::Int(v) => Json::object(vec![("kind", "Int".into()), ("value", Json::Number(*v as f64))]),
+        // As a string, since JSON numbers are doubles
+        ASTNode::BigInt(v) => Json::object(vec![("kind", "BigInt".into()), ("value", Json::str(&v.to_string()))]),
+        ASTNode::Float(v) => Json::object(vec![("kind", "Float".into()), ("value", Json::Number(*v))]),
+        ASTNode::Char(v) => Json::object(vec![("kind", "Char".into()), ("value", Json::str(&v.to_string()))]),
+        ASTNode::String(v) => Json::object(vec![("kind", "String".into()), ("value", Json::str(v))]),
+        ASTNode::Bool(v) => Json::object(vec![("kind", "Bool".into()), ("value", Json::Bool(*v))]),
+        ASTNode::Tuple(items) => Json::object(vec![
+            ("kind", "Tuple".into()),
+            ("type", type_json(&symbols.get_node_type(node))),
+            ("items", Json::Array(items.iter().map(|item| node_json(item, symbols)).collect())),
+        ]),
+        ASTNode::Index(tuple, index) => Json::object(vec![
+            ("kind", "Index".into()),
+            ("index", Json::Number(*index as f64)),
+            ("type", type_json(&symbols.get_node_type(node))),
+            ("tuple", node_json(tuple, symbols)),
+        ]),
+        ASTNode::Struct(name, fields) => {
+            let fields = fields
+                .iter()
+                .map(|(field, t)| Json::object(vec![("name", field.as_str().into()), ("type", type_json(t))]))
+                .collect();
+            Json::object(vec![("kind", "Struct".into()), ("name", name.as_str().into()), ("fields", Json::Array(fields))])
+        }
+        ASTNode::Record(name, fields) => Json::object(vec![
+            ("kind", "Record".into()),
+            ("name", name.as_str().into()),
+            ("type", type_json(&symbols.get_node_type(node))),
+            ("fields", fields_json(fields, symbols)),
+        ]),
+        ASTNode::Field(record, field) => Json::object(vec![
+            ("kind", "Field".into()),
+            ("field", field.as_str().into()),
+            ("type", type_json(&symbols.get_node_type(node))),
+            ("record", node_json(record, symbols)),
+        ]),
+        ASTNode::With(record, fields) => Json::object(vec![
+            ("kind", "With".into()),
+            ("type", type_json(&symbols.get_node_type(node))),
+            ("record", node_json(record, symbols)),
+            ("fields", fields_json(fields, symbols)),
+        ]),
+    }
+}
+
+fn fields_json(fields: &[(String, ASTNode)], symbols: &SymbolTable) -> Json {
+    let fields = fields
+        .iter()
+        .map(|(field, value)| Json::object(vec![("name", field.as_str().into()), ("value", node_json(value, symbols))]))
+        .collect();
+    Json::Array(fields)
+}
+
+fn type_json(node_type: &NodeType) -> Json {
+    Json::String(node_type.to_string())
+}
@@ -1,11 +1,72 @@
+use std::cell::Cell;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
 use std::process;
 
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub message: String,
+    pub line: Option<usize>,
+    // The Flip function a runtime error happened in
+    pub function: Option<String>,
+}
+
+impl Error {
+    pub fn new(message: &str) -> Error {
+        Error { message: message.to_string(), line: None, function: None }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.function, self.line) {
+            (_, Some(line)) => write!(f, "Error, line {line}: {}", self.message),
+            (Some(function), None) => write!(f, "Error in {function}: {}", self.message),
+            (None, None) => write!(f, "Error: {}", self.message),
+        }
+    }
+}
+
+thread_local! {
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+}
+
 pub fn throw(error: &str) -> ! {
-    eprintln!("Error: {error}");
-    process::exit(1)
+    fail(Error::new(error))
 }
 
 pub fn throw_at(error: &str, line: usize) -> ! {
-    eprintln!("Error, line {line}: {error}");
+    fail(Error { message: error.to_string(), line: Some(line), function: None })
+}
+
+// Errors raised while a program is running, "function" is its Flip name
+pub fn throw_in(error: &str, function: &str) -> ! {
+    fail(Error { message: error.to_string(), line: None, function: Some(function.to_string()) })
+}
+
+// Runtime errors in code whose line is known, which is shown instead of the function
+pub fn throw_at_in(error: &str, line: usize, function: &str) -> ! {
+    fail(Error { message: error.to_string(), line: Some(line), function: Some(function.to_string()) })
+}
+
+// Errors end the process, unless they happen inside "catch"
+fn fail(error: Error) -> ! {
+    if CATCHING.get() > 0 {
+        panic::resume_unwind(Box::new(error));
+    }
+
+    eprintln!("{error}");
     process::exit(1)
 }
+
+// Runs "f", returning the first error it throws instead of exiting
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, Error> {
+    CATCHING.set(CATCHING.get() + 1);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.set(CATCHING.get() - 1);
+
+    result.map_err(|payload| match payload.downcast::<Error>() {
+        Ok(error) => *error,
+        Err(payload) => panic::resume_unwind(payload),
+    })
+}
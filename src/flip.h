@@ -1,8 +1,540 @@
 #pragma once
 
+// The runtime is split into sections, and the compiler only copies the ones a
+// program uses into its output. "@provides" lists the names a section defines,
+// and "@requires" the other sections it uses.
+
+#include <stdio.h>
 #include <stdlib.h>
 
-// Lists
+// @section errors
+// @provides flip_error flip_error_at
+
+// "fn" is the Flip name of the function the error happened in
+void flip_error(const char* error, const char* fn) {
+    fprintf(stderr, "Error in %s: %s\n", fn, error);
+    exit(1);
+}
+
+// For errors in code written on a known "line", with 0 when it is not known
+void flip_error_at(const char* error, long line, const char* fn) {
+    if (line == 0) {
+        flip_error(error, fn);
+    }
+    fprintf(stderr, "Error, line %ld: %s\n", line, error);
+    exit(1);
+}
+
+// @section arithmetic
+// @provides divide modulo
+// @requires errors
+
+long divide(long a, long b, const char* fn) {
+    if (b == 0) {
+        flip_error("division by zero", fn);
+    }
+    return a / b;
+}
+
+long modulo(long a, long b, const char* fn) {
+    if (b == 0) {
+        flip_error("modulo by zero", fn);
+    }
+    return a % b;
+}
+
+// @section overflow
+// @provides checked_add checked_sub checked_mul checked_neg
+// @requires errors
+
+// Arithmetic which stops with "error" if the result does not fit in a long,
+// reporting the "line" the operation is on
+long checked_add(long a, long b, const char* error, long line, const char* fn) {
+    long result;
+    if (__builtin_add_overflow(a, b, &result)) {
+        flip_error_at(error, line, fn);
+    }
+    return result;
+}
+
+long checked_sub(long a, long b, const char* error, long line, const char* fn) {
+    long result;
+    if (__builtin_sub_overflow(a, b, &result)) {
+        flip_error_at(error, line, fn);
+    }
+    return result;
+}
+
+long checked_mul(long a, long b, const char* error, long line, const char* fn) {
+    long result;
+    if (__builtin_mul_overflow(a, b, &result)) {
+        flip_error_at(error, line, fn);
+    }
+    return result;
+}
+
+long checked_neg(long a, const char* error, long line, const char* fn) {
+    return checked_sub(0, a, error, line, fn);
+}
+
+// @section wrapping
+// @provides wrapping_add wrapping_sub wrapping_mul wrapping_neg
+
+// Unsigned arithmetic wraps around instead of being undefined
+long wrapping_add(long a, long b) {
+    return (long) ((unsigned long) a + (unsigned long) b);
+}
+
+long wrapping_sub(long a, long b) {
+    return (long) ((unsigned long) a - (unsigned long) b);
+}
+
+long wrapping_mul(long a, long b) {
+    return (long) ((unsigned long) a * (unsigned long) b);
+}
+
+long wrapping_neg(long a) {
+    return (long) -(unsigned long) a;
+}
+
+// @section floats
+// @provides float_bits bits_float float_add float_sub float_mul float_div float_neg float_from_long float_floor float_round float_sqrt float_print float_assert_eq
+// @requires errors
+
+#include <math.h>
+#include <string.h>
+
+// A Float is passed around as the bits of a double, in a long like every
+// other value, so thunks, cells and lists hold them unchanged
+union {
+    long bits;
+    double value;
+} typedef float_word;
+
+long float_bits(double value) {
+    float_word word;
+    word.value = value;
+    return word.bits;
+}
+
+double bits_float(long bits) {
+    float_word word;
+    word.bits = bits;
+    return word.value;
+}
+
+long float_add(long a, long b) {
+    return float_bits(bits_float(a) + bits_float(b));
+}
+
+long float_sub(long a, long b) {
+    return float_bits(bits_float(a) - bits_float(b));
+}
+
+long float_mul(long a, long b) {
+    return float_bits(bits_float(a) * bits_float(b));
+}
+
+long float_div(long a, long b) {
+    return float_bits(bits_float(a) / bits_float(b));
+}
+
+long float_neg(long a) {
+    return float_bits(-bits_float(a));
+}
+
+long float_from_long(long a) {
+    return float_bits((double) a);
+}
+
+long float_to_long(double x, const char* fn) {
+    // Also false for NaN
+    if (!(x >= -9223372036854775808.0 && x < 9223372036854775808.0)) {
+        flip_error("Float does not fit in an Int", fn);
+    }
+    return (long) x;
+}
+
+long float_floor(long a, const char* fn) {
+    return float_to_long(floor(bits_float(a)), fn);
+}
+
+long float_round(long a, const char* fn) {
+    return float_to_long(round(bits_float(a)), fn);
+}
+
+long float_sqrt(long a) {
+    return float_bits(sqrt(bits_float(a)));
+}
+
+long float_assert_eq(long a, long b, const char* fn) {
+    if (bits_float(a) != bits_float(b)) {
+        flip_error("assert_eq failed", fn);
+    }
+    return 1;
+}
+
+// The fewest digits which read back as the same double, in scientific
+// notation only for very large and small numbers, like 0.1, 2.0 or 1e+20
+void float_print(long a) {
+    double x = bits_float(a);
+    char digits[32];
+    int precision = 1;
+
+    if (isnan(x)) {
+        printf("NaN\n");
+        return;
+    }
+    if (isinf(x)) {
+        printf(x > 0 ? "inf\n" : "-inf\n");
+        return;
+    }
+
+    for (; precision < 17; precision++) {
+        snprintf(digits, sizeof(digits), "%.*e", precision - 1, x);
+        if (strtod(digits, NULL) == x) {
+            break;
+        }
+    }
+    snprintf(digits, sizeof(digits), "%.*e", precision - 1, x);
+
+    int exponent = atoi(strchr(digits, 'e') + 1);
+    if (exponent < -4 || exponent >= 16) {
+        printf("%s\n", digits);
+    } else {
+        int decimals = precision - 1 - exponent;
+        printf("%.*f%s\n", decimals > 0 ? decimals : 0, x, decimals > 0 ? "" : ".0");
+    }
+}
+
+// @section bigints
+// @provides bigint big_parse big_from_long big_to_long big_add big_sub big_mul big_neg big_divide big_modulo big_cmp big_print big_assert_eq
+// @requires errors
+
+// Arbitrary-precision integers are passed around as a long, like every other
+// value. Each digit holds nine decimal digits, least significant first.
+struct {
+    char negative;
+    long size;
+    unsigned int digits[];
+} typedef bigint;
+
+#define BIG_BASE 1000000000ul
+
+bigint* big_alloc(long size) {
+    bigint* n = (bigint*) malloc(sizeof(bigint) + size * sizeof(unsigned int));
+    n->negative = 0;
+    n->size = size;
+    return n;
+}
+
+// Drops leading zeros, zero is never negative
+long big_trim(bigint* n) {
+    while (n->size > 0 && n->digits[n->size - 1] == 0) {
+        n->size--;
+    }
+    if (n->size == 0) {
+        n->negative = 0;
+    }
+    return (long) n;
+}
+
+long big_from_long(long value) {
+    bigint* n = big_alloc(3);
+    unsigned long magnitude = value < 0 ? -(unsigned long) value : (unsigned long) value;
+    for (int i = 0; i < 3; i++) {
+        n->digits[i] = magnitude % BIG_BASE;
+        magnitude /= BIG_BASE;
+    }
+    n->negative = value < 0;
+    return big_trim(n);
+}
+
+// Decimal digits, with a "-" in front for negative numbers
+long big_parse(const char* text) {
+    char negative = *text == '-';
+    if (negative) {
+        text++;
+    }
+
+    long length = 0;
+    while (text[length] != '\0') {
+        length++;
+    }
+
+    bigint* n = big_alloc((length + 8) / 9);
+    for (long i = 0; i < n->size; i++) {
+        long end = length - 9 * i;
+        unsigned int digit = 0;
+        for (long j = end > 9 ? end - 9 : 0; j < end; j++) {
+            digit = digit * 10 + (text[j] - '0');
+        }
+        n->digits[i] = digit;
+    }
+    n->negative = negative;
+    return big_trim(n);
+}
+
+long big_to_long(long x, const char* fn) {
+    bigint* n = (bigint*) x;
+    unsigned long limit = 9223372036854775808ul;
+    unsigned long magnitude = 0;
+
+    for (long i = n->size - 1; i >= 0; i--) {
+        if (magnitude > (limit - n->digits[i]) / BIG_BASE) {
+            flip_error("BigInt is too large for an Int", fn);
+        }
+        magnitude = magnitude * BIG_BASE + n->digits[i];
+    }
+
+    if (n->negative) {
+        return (long) (0 - magnitude);
+    }
+    if (magnitude == limit) {
+        flip_error("BigInt is too large for an Int", fn);
+    }
+    return (long) magnitude;
+}
+
+int big_compare_magnitudes(bigint* a, bigint* b) {
+    if (a->size != b->size) {
+        return a->size < b->size ? -1 : 1;
+    }
+    for (long i = a->size - 1; i >= 0; i--) {
+        if (a->digits[i] != b->digits[i]) {
+            return a->digits[i] < b->digits[i] ? -1 : 1;
+        }
+    }
+    return 0;
+}
+
+bigint* big_add_magnitudes(bigint* a, bigint* b) {
+    long size = a->size > b->size ? a->size : b->size;
+    bigint* n = big_alloc(size + 1);
+    unsigned long carry = 0;
+
+    for (long i = 0; i < size; i++) {
+        unsigned long sum = carry;
+        sum += i < a->size ? a->digits[i] : 0;
+        sum += i < b->size ? b->digits[i] : 0;
+        n->digits[i] = sum % BIG_BASE;
+        carry = sum / BIG_BASE;
+    }
+    n->digits[size] = carry;
+    return n;
+}
+
+// "a" must not be smaller than "b"
+bigint* big_sub_magnitudes(bigint* a, bigint* b) {
+    bigint* n = big_alloc(a->size);
+    unsigned long borrow = 0;
+
+    for (long i = 0; i < a->size; i++) {
+        unsigned long subtrahend = (i < b->size ? b->digits[i] : 0) + borrow;
+        if (a->digits[i] >= subtrahend) {
+            n->digits[i] = a->digits[i] - subtrahend;
+            borrow = 0;
+        } else {
+            n->digits[i] = a->digits[i] + BIG_BASE - subtrahend;
+            borrow = 1;
+        }
+    }
+    return n;
+}
+
+long big_add(long x, long y) {
+    bigint* a = (bigint*) x;
+    bigint* b = (bigint*) y;
+    bigint* n;
+
+    if (a->negative == b->negative) {
+        n = big_add_magnitudes(a, b);
+        n->negative = a->negative;
+    } else if (big_compare_magnitudes(a, b) >= 0) {
+        n = big_sub_magnitudes(a, b);
+        n->negative = a->negative;
+    } else {
+        n = big_sub_magnitudes(b, a);
+        n->negative = b->negative;
+    }
+    return big_trim(n);
+}
+
+long big_neg(long x) {
+    bigint* a = (bigint*) x;
+    bigint* n = big_alloc(a->size);
+    for (long i = 0; i < a->size; i++) {
+        n->digits[i] = a->digits[i];
+    }
+    n->negative = !a->negative;
+    return big_trim(n);
+}
+
+long big_sub(long x, long y) {
+    return big_add(x, big_neg(y));
+}
+
+long big_mul(long x, long y) {
+    bigint* a = (bigint*) x;
+    bigint* b = (bigint*) y;
+    bigint* n = big_alloc(a->size + b->size);
+
+    for (long i = 0; i < n->size; i++) {
+        n->digits[i] = 0;
+    }
+    for (long i = 0; i < a->size; i++) {
+        unsigned long carry = 0;
+        for (long j = 0; j < b->size; j++) {
+            unsigned long product = n->digits[i + j] + (unsigned long) a->digits[i] * b->digits[j] + carry;
+            n->digits[i + j] = product % BIG_BASE;
+            carry = product / BIG_BASE;
+        }
+        n->digits[i + b->size] = carry;
+    }
+    n->negative = a->negative != b->negative;
+    return big_trim(n);
+}
+
+// Long division, finding each digit of the quotient with a binary search.
+// The quotient rounds towards zero and the remainder has the sign of "a".
+void big_div_rem(bigint* a, bigint* b, bigint** quotient, bigint** remainder, const char* error, const char* fn) {
+    if (b->size == 0) {
+        flip_error(error, fn);
+    }
+
+    bigint* q = big_alloc(a->size);
+    bigint* r = big_alloc(b->size + 1);
+    bigint* product = big_alloc(b->size + 1);
+    r->size = 0;
+
+    for (long i = a->size - 1; i >= 0; i--) {
+        for (long j = r->size; j > 0; j--) {
+            r->digits[j] = r->digits[j - 1];
+        }
+        r->digits[0] = a->digits[i];
+        r->size++;
+        big_trim(r);
+
+        unsigned long low = 0;
+        unsigned long high = BIG_BASE - 1;
+        while (low < high) {
+            unsigned long middle = (low + high + 1) / 2;
+            unsigned long carry = 0;
+            product->size = b->size + 1;
+            for (long j = 0; j < b->size; j++) {
+                unsigned long digit = b->digits[j] * middle + carry;
+                product->digits[j] = digit % BIG_BASE;
+                carry = digit / BIG_BASE;
+            }
+            product->digits[b->size] = carry;
+            big_trim(product);
+
+            if (big_compare_magnitudes(product, r) > 0) {
+                high = middle - 1;
+            } else {
+                low = middle;
+            }
+        }
+
+        q->digits[i] = low;
+        if (low > 0) {
+            unsigned long carry = 0;
+            unsigned long borrow = 0;
+            for (long j = 0; j < r->size; j++) {
+                unsigned long digit = (j < b->size ? b->digits[j] * low : 0) + carry;
+                carry = digit / BIG_BASE;
+                unsigned long subtrahend = digit % BIG_BASE + borrow;
+                if (r->digits[j] >= subtrahend) {
+                    r->digits[j] -= subtrahend;
+                    borrow = 0;
+                } else {
+                    r->digits[j] = r->digits[j] + BIG_BASE - subtrahend;
+                    borrow = 1;
+                }
+            }
+            big_trim(r);
+        }
+    }
+
+    q->negative = a->negative != b->negative;
+    r->negative = a->negative;
+    big_trim(q);
+    big_trim(r);
+    *quotient = q;
+    *remainder = r;
+}
+
+long big_divide(long a, long b, const char* fn) {
+    bigint* quotient;
+    bigint* remainder;
+    big_div_rem((bigint*) a, (bigint*) b, &quotient, &remainder, "division by zero", fn);
+    return (long) quotient;
+}
+
+long big_modulo(long a, long b, const char* fn) {
+    bigint* quotient;
+    bigint* remainder;
+    big_div_rem((bigint*) a, (bigint*) b, &quotient, &remainder, "modulo by zero", fn);
+    return (long) remainder;
+}
+
+// -1, 0 or 1
+long big_cmp(long x, long y) {
+    bigint* a = (bigint*) x;
+    bigint* b = (bigint*) y;
+
+    if (a->negative != b->negative) {
+        return a->negative ? -1 : 1;
+    }
+    int order = big_compare_magnitudes(a, b);
+    return a->negative ? -order : order;
+}
+
+void big_print(long x) {
+    bigint* n = (bigint*) x;
+
+    if (n->size == 0) {
+        printf("0\n");
+        return;
+    }
+    if (n->negative) {
+        printf("-");
+    }
+    printf("%u", n->digits[n->size - 1]);
+    for (long i = n->size - 2; i >= 0; i--) {
+        printf("%09u", n->digits[i]);
+    }
+    printf("\n");
+}
+
+long big_assert_eq(long a, long b, const char* fn) {
+    if (big_cmp(a, b) != 0) {
+        flip_error("assert_eq failed", fn);
+    }
+    return 1;
+}
+
+// @section assertions
+// @provides flip_assert flip_assert_eq
+// @requires errors
+
+long flip_assert(long ok, const char* fn) {
+    if (!ok) {
+        flip_error("assertion failed", fn);
+    }
+    return 1;
+}
+
+long flip_assert_eq(long a, long b, const char* fn) {
+    if (a != b) {
+        flip_error("assert_eq failed", fn);
+    }
+    return 1;
+}
+
+// @section lists
+// @provides list_node push len list head tail list_from_array
+// @requires errors
 
 struct list_node_t {
     char empty;
@@ -14,6 +546,7 @@ list_node* push(list_node* list, long value) {
     list_node* next_list = (list_node*) malloc(sizeof(list_node));
     next_list->head = value;
     next_list->tail = list;
+    return next_list;
 }
 
 long len(list_node* list) {
@@ -27,7 +560,83 @@ long len(list_node* list) {
 
 typedef list_node* list;
 
-// Lambdas
+long head(list list, const char* fn) {
+    if (list == NULL) {
+        flip_error("head of an empty list", fn);
+    }
+    return list->head;
+}
+
+list tail(list list, const char* fn) {
+    if (list == NULL) {
+        flip_error("tail of an empty list", fn);
+    }
+    return list->tail;
+}
+
+// The list of "size" items, with the first at the head, as for string literals
+list list_from_array(const long* items, long size) {
+    list result = NULL;
+    for (long i = size - 1; i >= 0; i--) {
+        result = push(result, items[i]);
+    }
+    return result;
+}
+
+// @section chars
+// @provides to_char char_is_digit char_is_alpha char_print string_print
+// @requires errors lists
+
+// A Char is its Unicode code point
+long to_char(long code, const char* fn) {
+    if (code < 0 || code > 0x10FFFF || (code >= 0xD800 && code <= 0xDFFF)) {
+        flip_error("Int is not a valid Char", fn);
+    }
+    return code;
+}
+
+long char_is_digit(long c) {
+    return c >= '0' && c <= '9';
+}
+
+long char_is_alpha(long c) {
+    return (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z');
+}
+
+// Writes a code point as UTF-8
+void put_char(long c) {
+    if (c < 0x80) {
+        putchar(c);
+    } else if (c < 0x800) {
+        putchar(0xC0 | (c >> 6));
+        putchar(0x80 | (c & 0x3F));
+    } else if (c < 0x10000) {
+        putchar(0xE0 | (c >> 12));
+        putchar(0x80 | ((c >> 6) & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    } else {
+        putchar(0xF0 | (c >> 18));
+        putchar(0x80 | ((c >> 12) & 0x3F));
+        putchar(0x80 | ((c >> 6) & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    }
+}
+
+void char_print(long c) {
+    put_char(c);
+    putchar('\n');
+}
+
+// Lists of chars are strings, printed from the head
+void string_print(list s) {
+    for (; s != NULL; s = s->tail) {
+        put_char(s->head);
+    }
+    putchar('\n');
+}
+
+// @section lambdas
+// @provides lambda_t lambda get_arg add_arg eval fn
 
 struct {
     char evaluated;
@@ -51,7 +660,7 @@ lambda_t* lambda(void* fn, void* args) {
 }
 
 #define get_arg(args, type) \
-    *((type*) ((args += sizeof(type)) - sizeof(type)));
+    *((type*) ((args += sizeof(type)) - sizeof(type)))
 
 #define add_arg(args, arg) {\
     *((typeof(arg)*) args) = arg;\
@@ -69,3 +678,37 @@ long eval(lambda_t* x) {
 }
 
 typedef lambda_t* fn;
+
+// @section frames
+// @provides cell value_cell lazy_cell force
+
+// A cell holds an argument or let-binding of a running function. Let-bindings
+// start out unforced, and are evaluated at most once, the first time they are
+// needed.
+struct {
+    char forced;
+    long value;
+    long (*eval)(void*);
+} typedef cell;
+
+cell value_cell(long value) {
+    return (cell) {
+        .forced = 1,
+        .value = value
+    };
+}
+
+cell lazy_cell(long (*eval)(void*)) {
+    return (cell) {
+        .forced = 0,
+        .eval = eval
+    };
+}
+
+long force(cell* c, void* frame) {
+    if (!c->forced) {
+        c->value = c->eval(frame);
+        c->forced = 1;
+    }
+    return c->value;
+}
@@ -0,0 +1,262 @@
+use crate::cst::{self, Commented, Expr, Function, Item, SourceFile, Struct};
+use crate::scanner;
+
+const INDENT: usize = 4;
+const WIDTH: usize = 80;
+
+// Prints code in the canonical style:
+// - one blank line between functions, and at most one between statements
+// - calls on one line if they fit in WIDTH columns and hold no comments
+// - otherwise the first argument follows the "(", the rest go on their own
+//   lines, and the ")" goes on a line of its own
+// - "if" calls in a statement, or in a call which did not fit, are always
+//   split like this, so their branches line up
+// - structs and records on one line if they fit and hold no comments, or
+//   else with each field on its own line
+pub fn format(code: &str) -> String {
+    let file = cst::parse(scanner::get_tokens_with_comments(code));
+    let mut out = String::new();
+
+    write_file(&mut out, &file);
+    out
+}
+
+fn write_file(out: &mut String, file: &SourceFile) {
+    for (i, item) in file.items.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        write_comments(out, &item.comments, 0);
+        match &item.node {
+            Item::Function(function) => write_fn(out, function),
+            Item::Struct(declaration) => write_struct(out, declaration),
+        }
+        write_trailing(out, &item.trailing);
+        out.push('\n');
+    }
+
+    if !file.end_comments.is_empty() {
+        if !file.items.is_empty() {
+            out.push('\n');
+        }
+        write_comments(out, &file.end_comments, 0);
+    }
+}
+
+fn write_fn(out: &mut String, function: &Function) {
+    for attribute in function.attributes.iter() {
+        out.push_str(attribute);
+        out.push('\n');
+    }
+
+    if function.is_test {
+        out.push_str(&format!("test {}", function.name));
+    } else {
+        let params: Vec<String> = function.params.iter().map(|p| format!("{}: {}", p.name, p.param_type)).collect();
+        out.push_str(&format!("{}({})", function.name, params.join(", ")));
+    }
+
+    if let Some(return_type) = &function.return_type {
+        out.push_str(&format!(": {return_type}"));
+    }
+    out.push_str(" {");
+    write_trailing(out, &function.header_comment);
+    out.push('\n');
+
+    for (i, statement) in function.body.iter().enumerate() {
+        if i > 0 && statement.blank_before {
+            out.push('\n');
+        }
+        write_comments(out, &statement.comments, INDENT);
+        out.push_str(&" ".repeat(INDENT));
+        write_expr(out, &statement.node, INDENT, INDENT, true);
+        write_trailing(out, &statement.trailing);
+        out.push('\n');
+    }
+
+    write_comments(out, &function.end_comments, INDENT);
+    out.push('}');
+}
+
+fn write_struct(out: &mut String, declaration: &Struct) {
+    for attribute in declaration.attributes.iter() {
+        out.push_str(attribute);
+        out.push('\n');
+    }
+
+    let fields: Vec<String> = declaration.fields.iter().map(|f| format!("{}: {}", f.node.name, f.node.param_type)).collect();
+    let flat = format!("struct {} {}", declaration.name, braced(&fields));
+    if declaration.end_comments.is_empty() && !declaration.fields.iter().any(has_comments) && flat.len() <= WIDTH {
+        out.push_str(&flat);
+        return;
+    }
+
+    out.push_str(&format!("struct {} {{\n", declaration.name));
+    for (i, (field, text)) in declaration.fields.iter().zip(fields).enumerate() {
+        write_comments(out, &field.comments, INDENT);
+        out.push_str(&" ".repeat(INDENT));
+        out.push_str(&text);
+        if i + 1 < declaration.fields.len() {
+            out.push(',');
+        }
+        write_trailing(out, &field.trailing);
+        out.push('\n');
+    }
+    write_comments(out, &declaration.end_comments, INDENT);
+    out.push('}');
+}
+
+// "column" is where the expression starts, and "indent" that of its line
+fn write_expr(out: &mut String, expr: &Expr, indent: usize, column: usize, split_if: bool) {
+    if let Some(flat) = flat(expr) {
+        if !(split_if && is_if(expr)) && column + flat.len() <= WIDTH {
+            out.push_str(&flat);
+            return;
+        }
+    }
+
+    let (name, args, end_comments) = match expr {
+        Expr::Call(name, args, end_comments) => (name, args, end_comments),
+        Expr::Field(tuple, field) => {
+            write_expr(out, tuple, indent, column, split_if);
+            out.push('.');
+            out.push_str(field);
+            return;
+        }
+        Expr::Record(name, fields, end_comments) => {
+            out.push_str(name);
+            write_fields(out, fields, end_comments, indent);
+            return;
+        }
+        Expr::With(record, fields, end_comments) => {
+            write_expr(out, record, indent, column, split_if);
+            out.push_str(" with");
+            write_fields(out, fields, end_comments, indent);
+            return;
+        }
+        Expr::Named(name, value) => {
+            out.push_str(&format!("{name}: "));
+            write_expr(out, value, indent, column + name.len() + 2, true);
+            return;
+        }
+        Expr::Atom(_) => unreachable!("atoms always fit on one line"),
+    };
+
+    out.push_str(name);
+    out.push('(');
+
+    let inner = indent + INDENT;
+    for (i, arg) in args.iter().enumerate() {
+        let first_column = column + name.len() + 1;
+        let inline = i == 0
+            && arg.comments.is_empty()
+            && !is_if(&arg.node)
+            && flat(&arg.node).is_some_and(|flat| first_column + flat.len() <= WIDTH);
+
+        if inline {
+            write_expr(out, &arg.node, indent, first_column, true);
+        } else {
+            out.push('\n');
+            write_comments(out, &arg.comments, inner);
+            out.push_str(&" ".repeat(inner));
+            write_expr(out, &arg.node, inner, inner, true);
+        }
+
+        if i + 1 < args.len() {
+            out.push(',');
+        }
+        write_trailing(out, &arg.trailing);
+    }
+
+    out.push('\n');
+    write_comments(out, end_comments, inner);
+    out.push_str(&" ".repeat(indent));
+    out.push(')');
+}
+
+// The fields of a record which did not fit on one line, each on its own
+fn write_fields(out: &mut String, fields: &[Commented<Expr>], end_comments: &[String], indent: usize) {
+    out.push_str(" {");
+
+    let inner = indent + INDENT;
+    for (i, field) in fields.iter().enumerate() {
+        out.push('\n');
+        write_comments(out, &field.comments, inner);
+        out.push_str(&" ".repeat(inner));
+        write_expr(out, &field.node, inner, inner, true);
+
+        if i + 1 < fields.len() {
+            out.push(',');
+        }
+        write_trailing(out, &field.trailing);
+    }
+
+    out.push('\n');
+    write_comments(out, end_comments, inner);
+    out.push_str(&" ".repeat(indent));
+    out.push('}');
+}
+
+// "{ a, b }", or "{}" without fields
+fn braced(fields: &[String]) -> String {
+    if fields.is_empty() {
+        String::from("{}")
+    } else {
+        format!("{{ {} }}", fields.join(", "))
+    }
+}
+
+// An expression on one line, unless it holds comments
+fn flat(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Atom(atom) => Some(atom.clone()),
+        Expr::Call(name, args, end_comments) => {
+            if !end_comments.is_empty() || args.iter().any(has_comments) {
+                return None;
+            }
+
+            let args: Option<Vec<String>> = args.iter().map(|arg| flat(&arg.node)).collect();
+            Some(format!("{name}({})", args?.join(", ")))
+        }
+        Expr::Field(tuple, field) => Some(format!("{}.{field}", flat(tuple)?)),
+        Expr::Record(name, fields, end_comments) => Some(format!("{name} {}", flat_fields(fields, end_comments)?)),
+        Expr::With(record, fields, end_comments) => {
+            Some(format!("{} with {}", flat(record)?, flat_fields(fields, end_comments)?))
+        }
+        Expr::Named(name, value) => Some(format!("{name}: {}", flat(value)?)),
+    }
+}
+
+fn flat_fields(fields: &[Commented<Expr>], end_comments: &[String]) -> Option<String> {
+    if !end_comments.is_empty() || fields.iter().any(has_comments) {
+        return None;
+    }
+
+    let fields: Option<Vec<String>> = fields.iter().map(|field| flat(&field.node)).collect();
+    Some(braced(&fields?))
+}
+
+fn has_comments<T>(arg: &Commented<T>) -> bool {
+    !arg.comments.is_empty() || arg.trailing.is_some()
+}
+
+fn is_if(expr: &Expr) -> bool {
+    matches!(expr, Expr::Call(name, _, _) if name == "if")
+}
+
+fn write_comments(out: &mut String, comments: &[String], indent: usize) {
+    for comment in comments {
+        if !comment.is_empty() {
+            out.push_str(&" ".repeat(indent));
+            out.push_str(comment);
+        }
+        out.push('\n');
+    }
+}
+
+fn write_trailing(out: &mut String, comment: &Option<String>) {
+    if let Some(comment) = comment {
+        out.push(' ');
+        out.push_str(comment);
+    }
+}
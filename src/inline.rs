@@ -0,0 +1,248 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{walk_body, walk_fold_body, walk_node, ASTNode, Fold, Line, Visitor};
+use crate::compiler;
+use crate::passes::Pass;
+use crate::symbols::{Symbol, SymbolTable};
+
+// Functions with at most this many nodes in their body are inlined by default
+pub const DEFAULT_THRESHOLD: usize = 12;
+
+struct Candidate {
+    params: Vec<Symbol>,
+    body: Vec<ASTNode>,
+}
+
+// Replaces calls to small, non-recursive functions with their bodies.
+// A threshold of 0 disables inlining.
+pub struct Inline {
+    pub threshold: usize,
+}
+
+impl Pass for Inline {
+    fn name(&self) -> &str {
+        "inline"
+    }
+
+    fn run(&mut self, tree: Vec<ASTNode>, symbols: &SymbolTable) -> Vec<ASTNode> {
+        inline_calls(tree, symbols, self.threshold)
+    }
+}
+
+pub fn inline_calls(tree: Vec<ASTNode>, symbols: &SymbolTable, threshold: usize) -> Vec<ASTNode> {
+    if threshold == 0 {
+        return tree;
+    }
+
+    let candidates = find_candidates(&tree, symbols, threshold);
+    walk_fold_body(&mut Inliner { candidates: &candidates, symbols }, tree)
+}
+
+fn find_candidates(tree: &[ASTNode], symbols: &SymbolTable, threshold: usize) -> HashMap<String, Candidate> {
+    let mut definitions: HashMap<&str, Vec<&ASTNode>> = HashMap::new();
+    for branch in tree {
+        if let ASTNode::Fn(name, _, _, _) = branch {
+            definitions.entry(name).or_default().push(branch);
+        }
+    }
+
+    let mut calls = HashMap::new();
+    for (name, fns) in definitions.iter() {
+        let mut collector = CallCollector { symbols, calls: HashSet::new() };
+        for f in fns {
+            if let ASTNode::Fn(_, _, _, body) = f {
+                walk_body(&mut collector, body);
+            }
+        }
+        calls.insert(name.to_string(), collector.calls);
+    }
+
+    let mut candidates = HashMap::new();
+
+    for (name, fns) in definitions {
+        // Overloaded user functions are left alone, a name must map to one body
+        if name == "main" || fns.len() != 1 || is_recursive(name, &calls) {
+            continue;
+        }
+
+        if let ASTNode::Fn(_, params, _, body) = fns[0] {
+            let mut size = NodeSize(0);
+            walk_body(&mut size, body);
+            if size.0 <= threshold && has_shareable_lets(body) {
+                candidates.insert(name.to_string(), Candidate {
+                    params: params.clone(),
+                    body: body.clone(),
+                });
+            }
+        }
+    }
+
+    candidates
+}
+
+struct Inliner<'a> {
+    candidates: &'a HashMap<String, Candidate>,
+    symbols: &'a SymbolTable,
+}
+
+impl Fold for Inliner<'_> {
+    fn fold_call(&mut self, name: String, args: Vec<ASTNode>, line: Line) -> ASTNode {
+        let args = walk_fold_body(self, args);
+
+        if let Some(candidate) = self.candidates.get(&name) {
+            if !compiler::is_inline_call(&name, &args, self.symbols) {
+                if let Some(expanded) = expand(candidate, &args) {
+                    // The callee may itself call other candidates
+                    return self.fold_node(expanded);
+                }
+            }
+        }
+
+        ASTNode::Call(name, args, line)
+    }
+}
+
+// Substitutes the arguments and let-bindings of a candidate into its final expression.
+// Gives up if an argument which would be computed at call time gets duplicated.
+fn expand(candidate: &Candidate, args: &[ASTNode]) -> Option<ASTNode> {
+    let (last, init) = candidate.body.split_last()?;
+
+    let mut env = HashMap::new();
+    for param in candidate.params.iter() {
+        env.insert(param.name.clone(), ASTNode::Var(param.clone()));
+    }
+    for node in init {
+        if let ASTNode::Let(s, v, _) = node {
+            let value = substitute(v, &env);
+            env.insert(s.name.clone(), value);
+        }
+    }
+
+    let shape = substitute(last, &env);
+
+    let mut env = HashMap::new();
+    for (param, arg) in candidate.params.iter().zip(args) {
+        if !evaluated_alike(arg, &shape, &param.name) {
+            return None;
+        }
+        env.insert(param.name.clone(), arg.clone());
+    }
+
+    Some(substitute(&shape, &env))
+}
+
+fn substitute(node: &ASTNode, env: &HashMap<String, ASTNode>) -> ASTNode {
+    Substitute(env).fold_node(node.clone())
+}
+
+struct Substitute<'a>(&'a HashMap<String, ASTNode>);
+
+impl Fold for Substitute<'_> {
+    fn fold_var(&mut self, symbol: Symbol) -> ASTNode {
+        self.0.get(&symbol.name).cloned().unwrap_or(ASTNode::Var(symbol))
+    }
+}
+
+// Let-bindings are shared between their uses, so a binding used more than once
+// can only be substituted if it is cheap to recompute
+fn has_shareable_lets(body: &[ASTNode]) -> bool {
+    for (i, node) in body.iter().enumerate() {
+        if let ASTNode::Let(s, v, _) = node {
+            let rest = &body[i + 1..];
+
+            let shadowed = rest.iter().any(|n| matches!(n, ASTNode::Let(t, _, _) if t.name == s.name));
+            let uses: usize = rest.iter().map(|n| n.count_uses(&s.name)).sum();
+
+            if shadowed || (uses > 1 && !is_atomic(v)) {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+// Arguments are evaluated before a call, so the inlined body must still
+// evaluate an argument which can fail, like "head(xs)" or a let-binding,
+// exactly once. Literals can't fail, and forcing a variable twice is the same
+// as once.
+fn evaluated_alike(arg: &ASTNode, shape: &ASTNode, param: &str) -> bool {
+    let strict = StrictUses { name: param, uses: 0 }.count(shape);
+    match arg {
+        ASTNode::Int(_) | ASTNode::BigInt(_) | ASTNode::Float(_) | ASTNode::Char(_) | ASTNode::String(_) | ASTNode::Bool(_) => true,
+        ASTNode::Var(_) => strict > 0,
+        _ => strict == 1 && shape.count_uses(param) == 1,
+    }
+}
+
+// Counts the reads of a variable which happen whenever the node is evaluated,
+// leaving out the branches of "if" and the second operand of "and" and "or"
+struct StrictUses<'a> {
+    name: &'a str,
+    uses: usize,
+}
+
+impl StrictUses<'_> {
+    fn count(mut self, node: &ASTNode) -> usize {
+        self.visit_node(node);
+        self.uses
+    }
+}
+
+impl Visitor for StrictUses<'_> {
+    fn visit_call(&mut self, name: &str, args: &[ASTNode]) {
+        match (name, args) {
+            ("if", [condition, _, _]) | ("and" | "or", [condition, _]) => self.visit_node(condition),
+            _ => walk_body(self, args),
+        }
+    }
+
+    fn visit_var(&mut self, symbol: &Symbol) {
+        self.uses += usize::from(symbol.name == self.name);
+    }
+}
+
+fn is_atomic(node: &ASTNode) -> bool {
+    matches!(node, ASTNode::Var(_) | ASTNode::Int(_) | ASTNode::BigInt(_) | ASTNode::Float(_) | ASTNode::Char(_) | ASTNode::Bool(_))
+}
+
+// Counts every node it visits
+struct NodeSize(usize);
+
+impl Visitor for NodeSize {
+    fn visit_node(&mut self, node: &ASTNode) {
+        self.0 += 1;
+        walk_node(self, node)
+    }
+}
+
+// The user functions called from the visited nodes
+struct CallCollector<'a> {
+    symbols: &'a SymbolTable,
+    calls: HashSet<String>,
+}
+
+impl Visitor for CallCollector<'_> {
+    fn visit_call(&mut self, name: &str, args: &[ASTNode]) {
+        if !compiler::is_inline_call(name, args, self.symbols) {
+            self.calls.insert(name.to_string());
+        }
+        walk_body(self, args)
+    }
+}
+
+fn is_recursive(name: &str, calls: &HashMap<String, HashSet<String>>) -> bool {
+    let mut seen = HashSet::new();
+    let mut stack: Vec<&String> = calls.get(name).into_iter().flatten().collect();
+
+    while let Some(callee) = stack.pop() {
+        if callee == name {
+            return true;
+        }
+        if seen.insert(callee) {
+            stack.extend(calls.get(callee).into_iter().flatten());
+        }
+    }
+
+    false
+}
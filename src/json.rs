@@ -0,0 +1,246 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// A minimal JSON value, enough for the language server and tooling output
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    pub fn str(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    // Follows a path of object keys, e.g. ["params", "textDocument", "uri"]
+    pub fn path(&self, keys: &[&str]) -> Option<&Json> {
+        keys.iter().try_fold(self, |json, key| json.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut chars = text.chars().peekable();
+        let json = parse_value(&mut chars)?;
+        skip_whitespace(&mut chars);
+        if chars.next().is_some() {
+            return None;
+        }
+        Some(json)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::str(s)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) => {
+                if n.fract() == 0.0 && n.abs() < 1e15 {
+                    write!(f, "{}", *n as i64)
+                } else {
+                    write!(f, "{n}")
+                }
+            }
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while let Some(c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else {
+            break;
+        }
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Option<Json> {
+    skip_whitespace(chars);
+
+    match chars.peek()? {
+        '{' => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Some(Json::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                if chars.next()? != ':' {
+                    return None;
+                }
+                fields.push((key, parse_value(chars)?));
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    '}' => return Some(Json::Object(fields)),
+                    _ => return None,
+                }
+            }
+        }
+        '[' => {
+            chars.next();
+            let mut items = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Some(Json::Array(items));
+            }
+            loop {
+                items.push(parse_value(chars)?);
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    ']' => return Some(Json::Array(items)),
+                    _ => return None,
+                }
+            }
+        }
+        '"' => parse_string(chars).map(Json::String),
+        't' => parse_word(chars, "true", Json::Bool(true)),
+        'f' => parse_word(chars, "false", Json::Bool(false)),
+        'n' => parse_word(chars, "null", Json::Null),
+        _ => {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_digit() || "+-.eE".contains(c) {
+                    number.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            number.parse().ok().map(Json::Number)
+        }
+    }
+}
+
+fn parse_word(chars: &mut Peekable<Chars>, word: &str, value: Json) -> Option<Json> {
+    for expected in word.chars() {
+        if chars.next()? != expected {
+            return None;
+        }
+    }
+    Some(value)
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    if chars.next()? != '"' {
+        return None;
+    }
+
+    let mut s = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => match chars.next()? {
+                'n' => s.push('\n'),
+                'r' => s.push('\r'),
+                't' => s.push('\t'),
+                'b' => s.push('\u{8}'),
+                'f' => s.push('\u{c}'),
+                'u' => {
+                    let hex: String = (0..4).filter_map(|_| chars.next()).collect();
+                    let mut code = u32::from_str_radix(&hex, 16).ok()?;
+                    // Surrogate pairs
+                    if (0xD800..0xDC00).contains(&code) {
+                        if chars.next()? != '\\' || chars.next()? != 'u' {
+                            return None;
+                        }
+                        let hex: String = (0..4).filter_map(|_| chars.next()).collect();
+                        let low = u32::from_str_radix(&hex, 16).ok()?;
+                        code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                    }
+                    s.push(char::from_u32(code)?);
+                }
+                c => s.push(c),
+            },
+            c => s.push(c),
+        }
+    }
+}
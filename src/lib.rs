@@ -1,7 +1,23 @@
 pub mod ast;
+pub mod bigint;
+pub mod bytecode;
 pub mod compiler;
+pub mod cst;
+pub mod dump;
 pub mod error;
+pub mod formatter;
+pub mod inline;
+pub mod json;
+pub mod lint;
+pub mod lsp;
+pub mod overflow;
 pub mod parser;
+pub mod passes;
+pub mod repl;
+pub mod runtime;
+pub mod rust_backend;
 pub mod scanner;
 pub mod symbols;
+pub mod test_runner;
 pub mod tokens;
+pub mod vm;
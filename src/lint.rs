@@ -0,0 +1,178 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::{walk_body, ASTNode, Line, NodeType, Program, Visitor};
+use crate::compiler;
+use crate::symbols::SymbolTable;
+
+// Every lint, by the name "#[allow(...)]" takes
+pub const LINTS: [&str; 5] = ["unused_binding", "unused_param", "unused_function", "identical_branches", "self_comparison"];
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub lint: &'static str,
+    pub message: String,
+    pub line: usize,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Warning, line {}: {} [{}]", self.line, self.message, self.lint)
+    }
+}
+
+// Finds code which is probably a mistake, in the tree before any passes ran
+pub fn check(program: &Program, symbols: &SymbolTable) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let unused = unused_functions(program);
+
+    for (node, definition) in program.tree.iter().zip(&program.definitions) {
+        let ASTNode::Fn(name, params, _, body) = node else {
+            continue;
+        };
+        let mut found = Vec::new();
+
+        if unused.contains(name.as_str()) {
+            found.push(warning("unused_function", format!("Function \"{name}\" is never used"), definition.line));
+        }
+
+        for param in params {
+            if uses_from(body, 0, &param.name) == 0 {
+                found.push(warning("unused_param", format!("Parameter \"{}\" is never used", param.name), definition.line));
+            }
+        }
+
+        check_body(body, &definition.lines, symbols, &mut found);
+        warnings.extend(found.into_iter().filter(|w| !definition.allow.iter().any(|a| a == w.lint)));
+    }
+
+    for test in program.tests.iter() {
+        let mut found = Vec::new();
+        check_body(&test.body, &test.lines, symbols, &mut found);
+        warnings.extend(found.into_iter().filter(|w| !test.allow.iter().any(|a| a == w.lint)));
+    }
+
+    warnings.sort_by_key(|w| w.line);
+    warnings
+}
+
+fn warning(lint: &'static str, message: String, line: usize) -> Warning {
+    Warning { lint, message, line }
+}
+
+fn check_body(body: &[ASTNode], lines: &[usize], symbols: &SymbolTable, found: &mut Vec<Warning>) {
+    for (i, (node, &line)) in body.iter().zip(lines).enumerate() {
+        if let ASTNode::Let(s, _, _) = node {
+            if uses_from(body, i + 1, &s.name) == 0 {
+                found.push(warning("unused_binding", format!("Binding \"{}\" is never used", s.name), line));
+            }
+        }
+
+        let mut calls = CallLints { symbols, found: Vec::new() };
+        calls.visit_node(node);
+        found.extend(calls.found.into_iter().map(|(lint, message)| warning(lint, message, line)));
+    }
+}
+
+// Uses of a name in the statements from "start", up to a binding which shadows it
+fn uses_from(body: &[ASTNode], start: usize, name: &str) -> usize {
+    let mut uses = 0;
+
+    for node in body[start..].iter() {
+        uses += node.count_uses(name);
+        if matches!(node, ASTNode::Let(s, _, _) if s.name == name) {
+            break;
+        }
+    }
+
+    uses
+}
+
+// Calls to built-ins which can be replaced by one of their arguments
+struct CallLints<'a> {
+    symbols: &'a SymbolTable,
+    found: Vec<(&'static str, String)>,
+}
+
+impl Visitor for CallLints<'_> {
+    fn visit_call(&mut self, name: &str, args: &[ASTNode]) {
+        if compiler::is_inline_call(name, args, self.symbols) {
+            match (name, args) {
+                ("if", [_, a, b]) if a == b => {
+                    self.found.push(("identical_branches", String::from("Both branches of \"if\" are the same")));
+                }
+                // Every back end compares lists, tuples and records by value, so the
+                // same expression twice gives equal values. NaN is not equal to
+                // itself though, so values holding Floats can not be caught.
+                ("==" | "!=" | "<" | ">" | "<=" | ">=", [a, b]) if a == b && !may_hold_float(&self.symbols.get_node_type(a), self.symbols, &mut Vec::new()) => {
+                    let always = matches!(name, "==" | "<=" | ">=");
+                    let call = ASTNode::Call(name.to_string(), args.to_vec(), Line::default());
+                    self.found.push(("self_comparison", format!("\"{call}\" compares a value with itself, so it is always {always}")));
+                }
+                _ => {}
+            }
+        }
+
+        walk_body(self, args)
+    }
+}
+
+// Whether a value of the type can be or contain a Float. Generics might be
+// one, and "seen" stops at records already looked into.
+fn may_hold_float(t: &NodeType, symbols: &SymbolTable, seen: &mut Vec<String>) -> bool {
+    match t.unwrap_fn() {
+        NodeType::Float | NodeType::Generic(_) => true,
+        NodeType::List(item) => may_hold_float(&item, symbols, seen),
+        NodeType::Record(name) if seen.contains(&name) => false,
+        NodeType::Record(name) => {
+            seen.push(name.clone());
+            let fields = symbols.elements(&NodeType::Record(name)).unwrap_or_default();
+            fields.iter().any(|field| may_hold_float(field, symbols, seen))
+        }
+        NodeType::Tuple(items) => items.iter().any(|item| may_hold_float(item, symbols, seen)),
+        _ => false,
+    }
+}
+
+// Names of the functions which neither "main" nor a test calls, directly or
+// not. Nothing is reported for files without a "main".
+fn unused_functions(program: &Program) -> HashSet<&str> {
+    let mut calls: HashMap<&str, HashSet<String>> = HashMap::new();
+    for node in program.tree.iter() {
+        if let ASTNode::Fn(name, _, _, body) = node {
+            let mut collector = CallNames(HashSet::new());
+            walk_body(&mut collector, body);
+            calls.entry(name).or_default().extend(collector.0);
+        }
+    }
+
+    if !calls.contains_key("main") {
+        return HashSet::new();
+    }
+
+    let mut roots = CallNames(HashSet::from([String::from("main")]));
+    for test in program.tests.iter() {
+        walk_body(&mut roots, &test.body);
+    }
+
+    let mut reached = HashSet::new();
+    let mut stack: Vec<String> = roots.0.into_iter().collect();
+    while let Some(name) = stack.pop() {
+        if let Some(callees) = calls.get(name.as_str()) {
+            if reached.insert(name) {
+                stack.extend(callees.iter().cloned());
+            }
+        }
+    }
+
+    calls.into_keys().filter(|name| !reached.contains(*name)).collect()
+}
+
+struct CallNames(HashSet<String>);
+
+impl Visitor for CallNames {
+    fn visit_call(&mut self, name: &str, args: &[ASTNode]) {
+        self.0.insert(name.to_string());
+        walk_body(self, args)
+    }
+}
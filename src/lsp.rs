@@ -0,0 +1,564 @@
+use std::collections::HashMap;
+use std::io::{self, prelude::*};
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+
+use crate::ast::{ASTNode, NodeType, Test};
+use crate::error::{self, Error};
+use crate::json::Json;
+use crate::lint::{self, Warning};
+use crate::symbols::{Symbol, SymbolTable};
+use crate::tokens::{Token, TokenType};
+use crate::{compiler, parser, scanner};
+
+// A line and column in a document, both starting at 1 like tokens
+type Position = (usize, usize);
+
+// A parameter or let-binding, visible from "scope" to the end of its function
+struct Binding {
+    token: Token,
+    scope: Position,
+    node_type: Option<NodeType>,
+}
+
+struct Function {
+    // The name, or for test blocks the string naming them
+    token: Token,
+    // The first "params" bindings are parameters, the rest let-bindings
+    params: usize,
+    bindings: Vec<Binding>,
+    return_type: Option<NodeType>,
+    end: Position,
+}
+
+impl Function {
+    fn signature(&self) -> String {
+        let params: Vec<String> = self.bindings[..self.params].iter().map(describe).collect();
+        match &self.return_type {
+            Some(return_type) => format!("{}({}): {}", self.token.content, params.join(", "), return_type.source()),
+            None => format!("{}({})", self.token.content, params.join(", ")),
+        }
+    }
+}
+
+enum Target<'a> {
+    Function(&'a str),
+    Binding(&'a Binding),
+}
+
+struct Document {
+    tokens: Vec<Token>,
+    functions: Vec<Function>,
+    // Built-ins and every function parsed before any error
+    symbols: SymbolTable,
+    error: Option<Error>,
+    warnings: Vec<Warning>,
+}
+
+impl Document {
+    fn new(text: &str) -> Document {
+        let tokens = scanner::get_tokens(text);
+        let mut functions = outline(&tokens);
+        let mut symbols = compiler::table_from_inlines();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            error::catch(|| parser::build_program(tokens.clone(), &mut symbols))
+        }));
+
+        let mut warnings = Vec::new();
+        let error = match result {
+            Ok(Ok(program)) => {
+                add_types(&mut functions, &program.tree, &program.tests);
+                warnings = lint::check(&program, &symbols);
+                None
+            }
+            Ok(Err(e)) => Some(e),
+            Err(_) => Some(Error::new("Internal compiler error")),
+        };
+
+        Document { tokens, functions, symbols, error, warnings }
+    }
+
+    fn token_at(&self, (line, column): Position) -> Option<&Token> {
+        self.tokens.iter().find(|t| {
+            t.token_type == TokenType::Literal
+                && t.line == line
+                && t.column <= column
+                && column <= t.column + t.content.chars().count()
+        })
+    }
+
+    fn function_at(&self, at: Position) -> Option<&Function> {
+        self.functions.iter().find(|f| position(&f.token) <= at && at <= f.end)
+    }
+
+    // Bindings are looked up like the parser does, so they shadow functions
+    // with the same name unless the name is followed by "("
+    fn resolve<'a>(&'a self, token: &'a Token) -> Option<Target<'a>> {
+        let name = token.content.as_str();
+        let at = position(token);
+
+        let is_function = self.symbols.iter().any(|s| s.name == name && s.arg_types.is_some())
+            || self.functions.iter().any(|f| f.token.content == name);
+        let is_call = self
+            .tokens
+            .iter()
+            .position(|t| position(t) == at)
+            .and_then(|i| self.tokens.get(i + 1))
+            .is_some_and(|t| t.token_type == TokenType::LeftParen);
+
+        if is_function && is_call {
+            return Some(Target::Function(name));
+        }
+
+        let binding = self.function_at(at).and_then(|f| {
+            f.bindings.iter().rev().find(|b| b.token.content == name && (b.scope <= at || position(&b.token) == at))
+        });
+
+        match binding {
+            Some(b) => Some(Target::Binding(b)),
+            None if is_function => Some(Target::Function(name)),
+            None => None,
+        }
+    }
+
+    // Every overload of a function, with parameter names for user functions
+    fn signatures(&self, name: &str) -> Vec<String> {
+        let mut signatures: Vec<String> =
+            self.functions.iter().filter(|f| f.token.content == name).map(Function::signature).collect();
+
+        if signatures.is_empty() {
+            for s in self.symbols.iter().filter(|s| s.name == name) {
+                if let Some(arg_types) = &s.arg_types {
+                    let arg_types: Vec<String> = arg_types.iter().map(NodeType::source).collect();
+                    signatures.push(format!("{name}({}): {}", arg_types.join(", "), s.symbol_type.unwrap_fn().source()));
+                }
+            }
+        }
+
+        signatures
+    }
+}
+
+// Finds functions and their bindings from the tokens alone, so positions are
+// known even if the document does not parse
+fn outline(tokens: &[Token]) -> Vec<Function> {
+    let mut functions = Vec::new();
+    let mut i = 0;
+
+    while i + 1 < tokens.len() {
+        let is_test = tokens[i].content == "test" && tokens[i + 1].token_type == TokenType::String;
+
+        if tokens[i].token_type != TokenType::Literal || !(is_test || tokens[i + 1].token_type == TokenType::LeftParen) {
+            i += 1;
+            continue;
+        }
+
+        let token = if is_test { tokens[i + 1].clone() } else { tokens[i].clone() };
+        let start = position(&token);
+        let mut bindings = Vec::new();
+        i += 2;
+
+        // Parameters, "name: Type", where tuple types have parentheses too
+        let mut parens = 0;
+        while !is_test && i < tokens.len() && !(parens == 0 && tokens[i].token_type == TokenType::RightParen) {
+            match tokens[i].token_type {
+                TokenType::LeftParen => parens += 1,
+                TokenType::RightParen => parens -= 1,
+                TokenType::Literal if tokens.get(i + 1).is_some_and(|t| t.token_type == TokenType::Colon) => {
+                    bindings.push(Binding { token: tokens[i].clone(), scope: start, node_type: None });
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        let params = bindings.len();
+
+        // The body, up to its closing brace
+        let mut depth = 0;
+        while i < tokens.len() {
+            match tokens[i].token_type {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                TokenType::Let if depth > 0 => {
+                    let scope = end_of_let(tokens, i + 1);
+                    for name in let_names(tokens, i + 2) {
+                        bindings.push(Binding { token: name.clone(), scope, node_type: None });
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        let end = tokens.get(i).map(after).unwrap_or((usize::MAX, 0));
+        functions.push(Function { token, params, bindings, return_type: None, end });
+        i += 1;
+    }
+
+    functions
+}
+
+// The names a let-binding defines, more than one for "=((a, b), value)"
+fn let_names(tokens: &[Token], start: usize) -> Vec<&Token> {
+    let mut names = Vec::new();
+    let mut depth = 0;
+
+    for token in tokens.iter().skip(start) {
+        match token.token_type {
+            TokenType::Literal => names.push(token),
+            TokenType::LeftParen => depth += 1,
+            TokenType::RightParen => depth -= 1,
+            TokenType::Comma => {}
+            _ => break,
+        }
+        if depth <= 0 {
+            break;
+        }
+    }
+
+    names
+}
+
+// A let-binding is visible after the parenthesis closing it
+fn end_of_let(tokens: &[Token], open: usize) -> Position {
+    let mut depth = 0;
+
+    for token in tokens[open..].iter() {
+        match token.token_type {
+            TokenType::LeftParen => depth += 1,
+            TokenType::RightParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return after(token);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    (usize::MAX, 0)
+}
+
+// The parser only returns a tree when the whole document parses, so the
+// functions and tests line up with the outline
+fn add_types(functions: &mut [Function], tree: &[ASTNode], tests: &[Test]) {
+    let mut nodes = tree.iter().filter(|node| matches!(node, ASTNode::Fn(..)));
+    let mut tests = tests.iter();
+
+    for function in functions.iter_mut() {
+        let (args, body): (&[Symbol], &[ASTNode]) = if function.token.token_type == TokenType::String {
+            match tests.next() {
+                Some(test) => (&[], &test.body),
+                None => break,
+            }
+        } else {
+            match nodes.next() {
+                Some(ASTNode::Fn(_, args, return_type, body)) => {
+                    function.return_type = Some(return_type.unwrap_fn());
+                    (args, body)
+                }
+                _ => break,
+            }
+        };
+
+        // Skipping the bindings holding destructured tuples, which have no name in the code
+        let lets = body.iter().filter_map(|node| match node {
+            ASTNode::Let(symbol, _, bind) if !bind.tuple => Some(symbol),
+            _ => None,
+        });
+
+        for (binding, symbol) in function.bindings.iter_mut().zip(args.iter().chain(lets)) {
+            binding.node_type = Some(symbol.symbol_type.unwrap_fn());
+        }
+    }
+}
+
+fn position(token: &Token) -> Position {
+    (token.line, token.column)
+}
+
+fn after(token: &Token) -> Position {
+    (token.line, token.column + token.content.chars().count())
+}
+
+fn describe(binding: &Binding) -> String {
+    match &binding.node_type {
+        Some(node_type) => format!("{}: {}", binding.token.content, node_type.source()),
+        None => binding.token.content.clone(),
+    }
+}
+
+// LSP positions start at 0
+fn range((line, column): Position, length: usize) -> Json {
+    let start = Json::object(vec![("line", (line - 1).into()), ("character", (column - 1).into())]);
+    let end = Json::object(vec![("line", (line - 1).into()), ("character", (column - 1 + length).into())]);
+    Json::object(vec![("start", start), ("end", end)])
+}
+
+fn token_range(token: &Token) -> Json {
+    range(position(token), token.content.chars().count())
+}
+
+fn location(uri: &str, token: &Token) -> Json {
+    Json::object(vec![("uri", uri.into()), ("range", token_range(token))])
+}
+
+struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl Server {
+    fn handle(&mut self, message: &Json) {
+        let Some(method) = message.get("method").and_then(Json::as_str) else {
+            return;
+        };
+        let params = message.get("params").unwrap_or(&Json::Null);
+
+        let result = match method {
+            "initialize" => Some(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Json::Null)
+            }
+            "exit" => process::exit(if self.shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" => {
+                if let (Some(uri), Some(text)) = (
+                    params.path(&["textDocument", "uri"]).and_then(Json::as_str),
+                    params.path(&["textDocument", "text"]).and_then(Json::as_str),
+                ) {
+                    self.update(uri, text);
+                }
+                None
+            }
+            "textDocument/didChange" => {
+                // Documents are always synced in full, so the last change is all of it
+                let text = match params.get("contentChanges") {
+                    Some(Json::Array(changes)) => changes.last().and_then(|c| c.get("text")).and_then(Json::as_str),
+                    _ => None,
+                };
+                if let (Some(uri), Some(text)) = (params.path(&["textDocument", "uri"]).and_then(Json::as_str), text) {
+                    self.update(uri, text);
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params.path(&["textDocument", "uri"]).and_then(Json::as_str) {
+                    self.documents.remove(uri);
+                    publish_diagnostics(uri, Vec::new());
+                }
+                None
+            }
+            "textDocument/hover" => Some(self.hover(params).unwrap_or(Json::Null)),
+            "textDocument/definition" => Some(self.definition(params).unwrap_or(Json::Null)),
+            "textDocument/completion" => Some(self.completion(params).unwrap_or(Json::Array(Vec::new()))),
+            _ => None,
+        };
+
+        // Requests have an id and always get a response, notifications never do
+        if let Some(id) = message.get("id") {
+            match result {
+                Some(result) => send(Json::object(vec![
+                    ("jsonrpc", "2.0".into()),
+                    ("id", id.clone()),
+                    ("result", result),
+                ])),
+                None => send_error(id.clone(), -32601, &format!("Unknown method {method}")),
+            }
+        }
+    }
+
+    fn update(&mut self, uri: &str, text: &str) {
+        let document = Document::new(text);
+
+        let mut diagnostics = Vec::new();
+        if let Some(error) = &document.error {
+            // Errors without a line are about the end of the document
+            let line = error.line.unwrap_or(text.lines().count().max(1));
+            let length = text.lines().nth(line - 1).map_or(0, |l| l.chars().count());
+
+            diagnostics.push(Json::object(vec![
+                ("range", range((line, 1), length)),
+                ("severity", 1.into()),
+                ("source", "flip".into()),
+                ("message", error.message.as_str().into()),
+            ]));
+        }
+
+        for warning in document.warnings.iter() {
+            let length = text.lines().nth(warning.line - 1).map_or(0, |l| l.chars().count());
+
+            diagnostics.push(Json::object(vec![
+                ("range", range((warning.line, 1), length)),
+                ("severity", 2.into()),
+                ("source", "flip".into()),
+                ("code", warning.lint.into()),
+                ("message", warning.message.as_str().into()),
+            ]));
+        }
+
+        publish_diagnostics(uri, diagnostics);
+        self.documents.insert(uri.to_string(), document);
+    }
+
+    // The document and position a request is about
+    fn locate<'a>(&'a self, params: &'a Json) -> Option<(&'a str, &'a Document, Position)> {
+        let uri = params.path(&["textDocument", "uri"])?.as_str()?;
+        let line = params.path(&["position", "line"])?.as_f64()? as usize;
+        let character = params.path(&["position", "character"])?.as_f64()? as usize;
+
+        Some((uri, self.documents.get(uri)?, (line + 1, character + 1)))
+    }
+
+    fn hover(&self, params: &Json) -> Option<Json> {
+        let (_, document, at) = self.locate(params)?;
+        let token = document.token_at(at)?;
+
+        let text = match document.resolve(token)? {
+            Target::Function(name) => document.signatures(name).join("\n"),
+            Target::Binding(binding) => describe(binding),
+        };
+
+        Some(Json::object(vec![
+            (
+                "contents",
+                Json::object(vec![("kind", "markdown".into()), ("value", Json::String(format!("```flip\n{text}\n```")))]),
+            ),
+            ("range", token_range(token)),
+        ]))
+    }
+
+    fn definition(&self, params: &Json) -> Option<Json> {
+        let (uri, document, at) = self.locate(params)?;
+        let token = document.token_at(at)?;
+
+        match document.resolve(token)? {
+            Target::Function(name) => {
+                let locations: Vec<Json> = document
+                    .functions
+                    .iter()
+                    .filter(|f| f.token.content == name)
+                    .map(|f| location(uri, &f.token))
+                    .collect();
+
+                // Built-ins have nowhere to go
+                (!locations.is_empty()).then_some(Json::Array(locations))
+            }
+            Target::Binding(binding) => Some(location(uri, &binding.token)),
+        }
+    }
+
+    fn completion(&self, params: &Json) -> Option<Json> {
+        let (_, document, at) = self.locate(params)?;
+        let mut items = Vec::new();
+        let mut seen = Vec::new();
+
+        if let Some(function) = document.function_at(at) {
+            for binding in function.bindings.iter().rev().filter(|b| b.scope <= at) {
+                if !seen.contains(&binding.token.content) {
+                    seen.push(binding.token.content.clone());
+                    items.push(completion_item(&binding.token.content, 6, &describe(binding)));
+                }
+            }
+        }
+
+        for symbol in document.symbols.iter().filter(|s| s.arg_types.is_some()) {
+            if !seen.contains(&symbol.name) {
+                seen.push(symbol.name.clone());
+                let detail = document.signatures(&symbol.name).join("\n");
+                items.push(completion_item(&symbol.name, 3, &detail));
+            }
+        }
+
+        Some(Json::Array(items))
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        (
+            "capabilities",
+            Json::object(vec![
+                // Full document sync
+                ("textDocumentSync", 1.into()),
+                ("hoverProvider", Json::Bool(true)),
+                ("definitionProvider", Json::Bool(true)),
+                ("completionProvider", Json::object(Vec::new())),
+            ]),
+        ),
+        ("serverInfo", Json::object(vec![("name", "flip".into())])),
+    ])
+}
+
+// "kind" is an LSP CompletionItemKind, 3 for functions and 6 for variables
+fn completion_item(label: &str, kind: usize, detail: &str) -> Json {
+    Json::object(vec![("label", label.into()), ("kind", kind.into()), ("detail", detail.into())])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) {
+    send(Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        ("params", Json::object(vec![("uri", uri.into()), ("diagnostics", Json::Array(diagnostics))])),
+    ]));
+}
+
+fn send_error(id: Json, code: i64, message: &str) {
+    send(Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        ("error", Json::object(vec![("code", Json::Number(code as f64)), ("message", message.into())])),
+    ]));
+}
+
+fn send(message: Json) {
+    let body = message.to_string();
+    let mut stdout = io::stdout().lock();
+    let _ = write!(stdout, "Content-Length: {}\r\n\r\n{body}", body.len());
+    let _ = stdout.flush();
+}
+
+// Reads the body of the next message, or None once the input is closed
+fn read_message(input: &mut impl BufRead) -> Option<String> {
+    let mut length = 0;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok()?;
+            }
+        }
+    }
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).ok()?;
+    Some(String::from_utf8_lossy(&body).into_owned())
+}
+
+// Serves requests over stdin and stdout until the client exits
+pub fn run() {
+    let mut server = Server { documents: HashMap::new(), shutdown: false };
+    let mut input = io::stdin().lock();
+
+    while let Some(body) = read_message(&mut input) {
+        match Json::parse(&body) {
+            Some(message) => server.handle(&message),
+            None => send_error(Json::Null, -32700, "Could not parse message"),
+        }
+    }
+}
@@ -1,54 +1,384 @@
 use std::env;
-use std::fs::{self, File};
-use std::io::prelude::*;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+use std::process;
 
-use flip::{compiler, parser, scanner};
+use flip::ast::{ASTNode, NodeType};
+use flip::error::throw;
+use flip::inline::Inline;
+use flip::overflow::CheckOverflow;
+use flip::passes::PassManager;
+use flip::symbols::SymbolTable;
+use flip::{bytecode, compiler, dump, formatter, inline, lint, lsp, parser, repl, rust_backend, scanner, test_runner, vm};
+
+const USAGE: &str = "\
+Usage: [options] [path]        Compile to build/out.c
+       build [options] [path]  Compile to an executable (build/out), C or Rust
+       run [options] [path]    Run with the bytecode VM
+       disasm [options] [path] Print the bytecode
+       test [path]             Run the test blocks in a file
+       dump <stage> [path]     Print the tokens, ast, symbols or c of a program
+       repl                    Start an interactive session
+       lsp                     Start a language server on stdin and stdout
+       fmt [--check] [paths]   Format files in place, or stdin to stdout
+
+Options:
+    --inline-threshold N  Inline functions with at most N nodes (0 disables)
+    --target c|rust       Language to compile to (default c)
+    --emit c|exe          Stop after writing C, or build an executable (default exe)
+    -o <output>           Where to write the output, \"-\" for stdout
+    -O<level>             Passed to the C compiler, which is $CC or cc
+    --deny-warnings       Stop if there are any warnings
+    --checked             Stop with an error when Int arithmetic overflows
+    --check               With fmt, list unformatted files instead of changing them
+    --json                With dump, print JSON
+    --after <pass>        With dump ast, show the tree after a pass, e.g. inline";
+
+enum Command {
+    Build,
+    Run,
+    Disasm,
+    Test,
+    Dump(Stage),
+    Repl,
+    Lsp,
+    Fmt,
+}
+
+enum Stage {
+    Tokens,
+    Ast,
+    Symbols,
+    C,
+}
+
+#[derive(PartialEq)]
+enum Target {
+    C,
+    Rust,
+}
+
+#[derive(PartialEq)]
+enum Emit {
+    C,
+    Exe,
+}
+
+struct Options {
+    inline_threshold: usize,
+    target: Target,
+    emit: Emit,
+    output: Option<PathBuf>,
+    cflags: Vec<String>,
+    deny_warnings: bool,
+    checked: bool,
+}
 
 pub fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut command = None;
+    let mut paths = Vec::new();
+    let mut check = false;
+    let mut json = false;
+    let mut after = None;
+    let mut options = Options {
+        inline_threshold: inline::DEFAULT_THRESHOLD,
+        target: Target::C,
+        emit: Emit::Exe,
+        output: None,
+        cflags: Vec::new(),
+        deny_warnings: false,
+        checked: false,
+    };
+    let mut emit = None;
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--inline-threshold" => {
+                if let Some(n) = args.next().and_then(|n| n.parse().ok()) {
+                    options.inline_threshold = n;
+                } else {
+                    usage();
+                }
+            }
+            "--target" => match args.next().as_deref() {
+                Some("c") => options.target = Target::C,
+                Some("rust") => options.target = Target::Rust,
+                _ => usage(),
+            },
+            "--emit" => match args.next().as_deref() {
+                Some("c") => emit = Some(Emit::C),
+                Some("exe") => emit = Some(Emit::Exe),
+                _ => usage(),
+            },
+            "-o" => match args.next() {
+                Some(output) => options.output = Some(PathBuf::from(output)),
+                None => usage(),
+            },
+            "--deny-warnings" => options.deny_warnings = true,
+            "--checked" => options.checked = true,
+            "--check" => check = true,
+            "--json" => json = true,
+            "--after" => match args.next() {
+                Some(pass) => after = Some(pass),
+                None => usage(),
+            },
+            _ if arg.starts_with("-O") => options.cflags.push(arg),
+            "build" if command.is_none() && paths.is_empty() => command = Some(Command::Build),
+            "run" if command.is_none() && paths.is_empty() => command = Some(Command::Run),
+            "disasm" if command.is_none() && paths.is_empty() => command = Some(Command::Disasm),
+            "test" if command.is_none() && paths.is_empty() => command = Some(Command::Test),
+            "dump" if command.is_none() && paths.is_empty() => {
+                let stage = match args.next().as_deref() {
+                    Some("tokens") => Stage::Tokens,
+                    Some("ast") => Stage::Ast,
+                    Some("symbols") => Stage::Symbols,
+                    Some("c") => Stage::C,
+                    _ => usage(),
+                };
+                command = Some(Command::Dump(stage));
+            }
+            "repl" if command.is_none() && paths.is_empty() => command = Some(Command::Repl),
+            "lsp" if command.is_none() && paths.is_empty() => command = Some(Command::Lsp),
+            "fmt" if command.is_none() && paths.is_empty() => command = Some(Command::Fmt),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    match command {
+        Some(Command::Repl) => return repl::run(),
+        Some(Command::Lsp) => return lsp::run(),
+        Some(Command::Fmt) => return format(&paths, check),
+        _ => {}
+    }
 
-    if args.len() == 2 {
-        let path = PathBuf::from(&args[1]);
-        let code = fs::read_to_string(&path).expect("Could not read file");
+    let [path] = paths.as_slice() else {
+        usage();
+    };
 
-        if let Err(e) = compile(code) {
-            eprintln!("{e}");
+    // Without a command, only C is written, as the compiler always did
+    options.emit = match emit {
+        Some(emit) => emit,
+        None if command.is_none() => Emit::C,
+        None => Emit::Exe,
+    };
+
+    let code = fs::read_to_string(path).expect("Could not read file");
+
+    match command {
+        Some(Command::Test) => return test(&code),
+        Some(Command::Dump(Stage::Tokens)) => return print!("{}", dump::tokens(&scanner::get_tokens(&code), json)),
+        _ => {}
+    }
+
+    let (tree, mut symbols) = front_end(&code, &options);
+
+    // The tree is shown as it was parsed, unless a pass to stop after is given
+    if let Some(Command::Dump(Stage::Ast)) = command {
+        let tree = match after {
+            Some(pass) => passes(&options).run_until(tree, &symbols, &pass),
+            None => tree,
         };
-    } else {
-        println!("Usage: [path]");
+        return print!("{}", dump::ast(&tree, &symbols, json));
+    }
+
+    let ast = passes(&options).run(tree, &symbols);
+
+    match command.unwrap_or(Command::Build) {
+        Command::Run => {
+            let program = bytecode::compile_program(&ast, &symbols);
+            let value = vm::run(&program);
+
+            // Strings are lists of chars, printed as text like the C build does
+            match (compiler::main_type(&ast, &symbols), value.as_string()) {
+                (Some(NodeType::List(item)), Some(text)) if *item == NodeType::Char => println!("{text}"),
+                _ => println!("{value}"),
+            }
+        }
+        Command::Disasm => {
+            let program = bytecode::compile_program(&ast, &symbols);
+            print!("{}", program.disassemble());
+        }
+        Command::Dump(stage) => match stage {
+            Stage::Symbols => print!("{}", dump::symbols(&symbols, json)),
+            _ => print!("{}", dump::c(&compiler::compile_program(&ast, &mut symbols), json)),
+        },
+        _ => build(ast, &mut symbols, &options),
+    }
+}
+
+fn usage() -> ! {
+    println!("{USAGE}");
+    process::exit(1)
+}
+
+// Warnings go to stderr
+fn front_end(code: &str, options: &Options) -> (Vec<ASTNode>, SymbolTable) {
+    let mut symbols = compiler::table_from_inlines();
+    let tokens = scanner::get_tokens(code);
+
+    let program = parser::build_program(tokens, &mut symbols);
+
+    let warnings = lint::check(&program, &symbols);
+    for warning in warnings.iter() {
+        eprintln!("{warning}");
+    }
+    if options.deny_warnings && !warnings.is_empty() {
+        throw("Stopping, as warnings are denied");
     }
+
+    (program.tree, symbols)
 }
 
-fn compile(code: String) -> std::io::Result<()> {
+// Inlining, and with --checked the overflow checks, run on the parsed program
+fn passes(options: &Options) -> PassManager {
+    let mut passes = PassManager::new();
+    if options.checked {
+        passes = passes.with(CheckOverflow);
+    }
+    passes.with(Inline { threshold: options.inline_threshold })
+}
+
+// Prints a line for each test, exiting with 1 if any failed
+fn test(code: &str) {
     let mut symbols = compiler::table_from_inlines();
-    let tokens = scanner::get_tokens(&code);
+    let program = parser::build_program(scanner::get_tokens(code), &mut symbols);
+    let results = test_runner::run_tests(&program.tree, &program.tests, &symbols);
+    let mut failed = 0;
+
+    for result in results.iter() {
+        let Some(error) = &result.error else {
+            println!("test {} ... ok", result.name);
+            continue;
+        };
 
-    let ast = parser::build_ast(tokens, &mut symbols);
+        failed += 1;
+        println!("test {} ... FAILED", result.name);
 
-    let mut file = File::create("build/out.c")?;
+        let line = error.line.map(|line| format!("line {line}: ")).unwrap_or_default();
+        match &error.function {
+            Some(function) if *function != result.name => println!("    {line}{} (in {function})", error.message),
+            _ => println!("    {line}{}", error.message),
+        }
+    }
 
-    file.write_all(format!("{INCLUDES}\n\n").as_bytes())?;
+    println!("\n{} passed, {failed} failed", results.len() - failed);
 
-    for branch in ast {
-        let buf = flip::compiler::compile_expr(&branch, &mut symbols);
-        file.write_all(buf.get().as_bytes())?;
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+// Without paths, formats stdin to stdout
+fn format(paths: &[PathBuf], check: bool) {
+    if paths.is_empty() {
+        let mut code = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut code) {
+            throw(&format!("Could not read stdin: {e}"));
+        }
+
+        let formatted = formatter::format(&code);
+        if !check {
+            print!("{formatted}");
+        } else if formatted != code {
+            process::exit(1);
+        }
+        return;
     }
 
-    file.write_all(C_MAIN.as_bytes())?;
+    let mut unformatted = false;
 
-    Ok(())
+    for path in paths {
+        let code = match fs::read_to_string(path) {
+            Ok(code) => code,
+            Err(e) => throw(&format!("Could not read {}: {e}", path.display())),
+        };
+
+        let formatted = formatter::format(&code);
+        if formatted == code {
+            continue;
+        }
+
+        if check {
+            println!("{} is not formatted", path.display());
+            unformatted = true;
+        } else if let Err(e) = fs::write(path, formatted) {
+            throw(&format!("Could not write {}: {e}", path.display()));
+        }
+    }
+
+    if unformatted {
+        process::exit(1);
+    }
+}
+
+fn build(ast: Vec<ASTNode>, symbols: &mut SymbolTable, options: &Options) {
+    let result = match (&options.target, &options.emit) {
+        (Target::Rust, _) => {
+            let output = options.output.clone().unwrap_or(PathBuf::from("build/out.rs"));
+            write_output(&output, &rust_backend::compile_program(&ast, symbols))
+        }
+        (Target::C, Emit::C) => {
+            let output = options.output.clone().unwrap_or(PathBuf::from("build/out.c"));
+            write_output(&output, &compiler::compile_program(&ast, symbols))
+        }
+        (Target::C, Emit::Exe) => {
+            let output = options.output.clone().unwrap_or(PathBuf::from("build/out"));
+            if output.as_os_str() == "-" {
+                throw("Cannot write an executable to stdout, use \"--emit c\"");
+            }
+            compile_exe(&ast, symbols, &output, &options.cflags)
+        }
+    };
+
+    if let Err(e) = result {
+        throw(&e.to_string());
+    }
+}
+
+// "-" is stdout, otherwise any missing directories are created
+fn write_output(output: &Path, content: &str) -> io::Result<()> {
+    if output.as_os_str() == "-" {
+        return io::stdout().write_all(content.as_bytes());
+    }
+
+    create_parent_dir(output)?;
+    fs::write(output, content).map_err(|e| io::Error::new(e.kind(), format!("Could not write {}: {e}", output.display())))
+}
+
+fn create_parent_dir(output: &Path) -> io::Result<()> {
+    match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir)
+            .map_err(|e| io::Error::new(e.kind(), format!("Could not create {}: {e}", dir.display()))),
+        _ => Ok(()),
+    }
 }
 
-const INCLUDES: &str = "\
-#include <stdio.h>
-#include \"flip.h\"";
+fn compile_exe(ast: &[ASTNode], symbols: &mut SymbolTable, output: &Path, cflags: &[String]) -> io::Result<()> {
+    let dir = env::temp_dir().join(format!("flip-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+
+    let source = dir.join("out.c");
+    fs::write(&source, compiler::compile_program(ast, symbols))?;
+    create_parent_dir(output)?;
 
-const C_MAIN: &str = "\
-// C main
+    let cc = env::var("CC").unwrap_or(String::from("cc"));
+    let status = process::Command::new(&cc)
+        .arg(&source)
+        .arg("-o")
+        .arg(output)
+        .args(cflags)
+        // The runtime's Float functions use libm
+        .arg("-lm")
+        .status();
 
-int main() {
-    printf(\"%ld\\n\", fn_main());
-    return 0;
+    fs::remove_dir_all(&dir)?;
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => throw(&format!("C compiler \"{cc}\" failed ({status})")),
+        Err(e) => throw(&format!("Could not run the C compiler \"{cc}\" ({e}), set $CC to choose another")),
+    }
 }
-";
@@ -0,0 +1,66 @@
+use crate::ast::{walk_fold_body, ASTNode, Fold, Line, NodeType};
+use crate::compiler;
+use crate::passes::Pass;
+use crate::symbols::SymbolTable;
+
+// Makes Int arithmetic stop the program with an error when it overflows,
+// instead of wrapping around, or being undefined in C. The checked operations
+// keep the line of the call, so the error can say where they are.
+pub struct CheckOverflow;
+
+impl Pass for CheckOverflow {
+    fn name(&self) -> &str {
+        "check_overflow"
+    }
+
+    fn run(&mut self, tree: Vec<ASTNode>, symbols: &SymbolTable) -> Vec<ASTNode> {
+        walk_fold_body(&mut Checker { symbols }, tree)
+    }
+}
+
+// The error for a checked operation, naming it as it was written, e.g.
+// "integer overflow in +(n, 1)"
+pub fn error_message(call: &ASTNode) -> String {
+    format!("integer overflow in {}", Operators.fold_node(call.clone()))
+}
+
+struct Checker<'a> {
+    symbols: &'a SymbolTable,
+}
+
+impl Fold for Checker<'_> {
+    fn fold_call(&mut self, name: String, args: Vec<ASTNode>, line: Line) -> ASTNode {
+        let args = walk_fold_body(self, args);
+
+        let checked = match (name.as_str(), args.len()) {
+            ("+", 2) => "checked_add",
+            ("-", 2) => "checked_sub",
+            ("*", 2) => "checked_mul",
+            ("-", 1) => "checked_neg",
+            _ => return ASTNode::Call(name, args, line),
+        };
+
+        // BigInts and user overloads of the operators are left alone
+        let on_ints = args.iter().all(|arg| self.symbols.get_node_type(arg).unwrap_fn() == NodeType::Int);
+        if on_ints && compiler::is_inline_call(&name, &args, self.symbols) {
+            ASTNode::Call(checked.to_string(), args, line)
+        } else {
+            ASTNode::Call(name, args, line)
+        }
+    }
+}
+
+// Gives the checked operations back their symbols
+struct Operators;
+
+impl Fold for Operators {
+    fn fold_call(&mut self, name: String, args: Vec<ASTNode>, line: Line) -> ASTNode {
+        let name = match name.as_str() {
+            "checked_add" => "+",
+            "checked_sub" | "checked_neg" => "-",
+            "checked_mul" => "*",
+            _ => &name,
+        };
+        ASTNode::Call(name.to_string(), walk_fold_body(self, args), line)
+    }
+}
@@ -1,29 +1,91 @@
-use crate::ast::{ASTNode, NodeType};
+use crate::ast::{self, ASTNode, Bind, Definition, Line, NodeType, Program, Test};
+use crate::bigint::BigInt;
 use crate::error::{throw, throw_at};
+use crate::lint;
 use crate::symbols::{Symbol, SymbolTable};
 use crate::tokens::{Token, TokenType, TokensList};
 
 pub fn build_ast(token_vec: Vec<Token>, symbols: &mut SymbolTable) -> Vec<ASTNode> {
+    build_program(token_vec, symbols).tree
+}
+
+// Also returns the "test" blocks, which "build_ast" checks but leaves out,
+// and where each function was defined
+pub fn build_program(token_vec: Vec<Token>, symbols: &mut SymbolTable) -> Program {
     let mut tokens = TokensList::from(token_vec);
-    let mut tree: Vec<ASTNode> = vec![];
+    let mut program = Program { tree: Vec::new(), definitions: Vec::new(), tests: Vec::new() };
 
     while tokens.peek().is_some() {
-        tree.push(consume_fn(&mut tokens, symbols));
+        let allow = consume_attributes(&mut tokens);
+        let name_token = tokens.expect(TokenType::Literal);
+
+        if name_token.content == "test" && tokens.peek().is_some_and(|t| t.token_type == TokenType::String) {
+            program.tests.push(consume_test(name_token, &mut tokens, symbols, allow));
+        } else if name_token.content == "struct" && tokens.peek().is_some_and(|t| t.token_type == TokenType::Literal) {
+            program.tree.push(consume_struct(&mut tokens, symbols));
+            program.definitions.push(Definition { line: name_token.line, lines: Vec::new(), allow });
+        } else {
+            let line = name_token.line;
+            let (function, lines) = consume_fn(name_token, &mut tokens, symbols);
+            program.tree.push(function);
+            program.definitions.push(Definition { line, lines, allow });
+        }
+    }
+
+    program
+}
+
+// "#[allow(name, ...)]" before a function turns off the lints with those names in it
+fn consume_attributes(tokens: &mut TokensList) -> Vec<String> {
+    let mut allow = Vec::new();
+
+    while tokens.peek().is_some_and(|t| t.token_type == TokenType::Attribute) {
+        let token = tokens.consume();
+        let Some(names) = token.content.strip_prefix("#[allow(").and_then(|c| c.strip_suffix(")]")) else {
+            throw_at(&format!("Unknown attribute {}, expected #[allow(...)]", token.content), token.line);
+        };
+
+        for name in names.split(',').map(str::trim) {
+            if !lint::LINTS.contains(&name) {
+                throw_at(&format!("Unknown lint \"{name}\""), token.line);
+            }
+            allow.push(name.to_string());
+        }
+    }
+
+    allow
+}
+
+// Parses a single let-binding or expression, as found in a block. Bindings
+// which destructure a tuple give several nodes, see "consume_let".
+pub fn build_statement(token_vec: Vec<Token>, symbols: &mut SymbolTable) -> Vec<ASTNode> {
+    let mut tokens = TokensList::from(token_vec);
+
+    let nodes = if let Some(Token { token_type: TokenType::Let, .. }) = tokens.peek() {
+        tokens.consume();
+        consume_let(&mut tokens, symbols)
+    } else {
+        vec![parse_node(&mut tokens, symbols)]
+    };
+
+    if let Some(token) = tokens.peek() {
+        throw_at(&format!("Unexpected token {} after the end of the expression", token.content), token.line);
     }
 
-    tree
+    nodes
 }
 
-fn consume_fn(tokens: &mut TokensList, symbols: &mut SymbolTable) -> ASTNode {
-    let name = tokens.expect(TokenType::Literal).content;
+// Also returns the line each statement starts on
+fn consume_fn(name_token: Token, tokens: &mut TokensList, symbols: &mut SymbolTable) -> (ASTNode, Vec<usize>) {
+    let name = name_token.content;
 
     tokens.expect(TokenType::LeftParen);
 
-    let (arg_symbols, arg_types) = consume_fn_args(tokens);
+    let (arg_symbols, arg_types) = consume_fn_args(tokens, symbols);
 
     tokens.expect(TokenType::RightParen);
 
-    let return_type = consume_fn_return(tokens);
+    let return_type = consume_fn_return(tokens, symbols);
 
     symbols.insert(Symbol::new_fn(
         &name,
@@ -31,22 +93,85 @@ fn consume_fn(tokens: &mut TokensList, symbols: &mut SymbolTable) -> ASTNode {
         NodeType::Fn(Box::new(return_type.clone())),
     ));
 
-    let mut scoped_symbols = symbols.clone();
-    scoped_symbols.insert_vec(&arg_symbols);
-
-    let body = consume_block(tokens, symbols, &arg_symbols);
+    let (body, lines) = consume_block(tokens, symbols, &arg_symbols);
     let body_last_type = symbols.get_node_type(body.last().unwrap());
 
-    if body_last_type.unwrap_fn() != return_type.unwrap_fn() {
-        if !(name == "main" && body_last_type.unwrap_fn() == NodeType::Int) {
-            throw(&format!("Expected function \"{}\" to return \"{return_type}\", got \"{body_last_type}\" instead", name));
+    if body_last_type.unwrap_fn() != return_type.unwrap_fn()
+        && !(name == "main" && printable(&body_last_type.unwrap_fn()))
+    {
+        throw_at(
+            &format!("Expected function \"{name}\" to return \"{return_type}\", got \"{body_last_type}\" instead"),
+            name_token.line,
+        );
+    }
+
+    (ASTNode::Fn(name, arg_symbols, return_type, body), lines)
+}
+
+// The types "main" can return without declaring it, which the compiled program prints
+fn printable(t: &NodeType) -> bool {
+    match t {
+        NodeType::Int | NodeType::BigInt | NodeType::Float | NodeType::Char => true,
+        NodeType::List(item) => **item == NodeType::Char,
+        _ => false,
+    }
+}
+
+// test "name" { ... }
+fn consume_test(keyword: Token, tokens: &mut TokensList, symbols: &mut SymbolTable, allow: Vec<String>) -> Test {
+    let name = unquote(&tokens.expect(TokenType::String), '"');
+    let (body, lines) = consume_block(tokens, symbols, &[]);
+
+    Test {
+        name,
+        line: keyword.line,
+        body,
+        lines,
+        allow,
+    }
+}
+
+// struct Name { field: Type, ... }
+fn consume_struct(tokens: &mut TokensList, symbols: &mut SymbolTable) -> ASTNode {
+    let name_token = tokens.expect(TokenType::Literal);
+    let name = name_token.content;
+    tokens.expect(TokenType::LeftBrace);
+
+    let mut fields: Vec<(String, NodeType)> = Vec::new();
+    while tokens.peek().is_some_and(|t| t.token_type != TokenType::RightBrace) {
+        if !fields.is_empty() {
+            tokens.expect(TokenType::Comma);
         }
+
+        let field = tokens.expect(TokenType::Literal);
+        tokens.expect(TokenType::Colon);
+        let field_type = consume_type(tokens, symbols);
+
+        // Fields can hold records declared before, but not generics
+        let mut generics = Vec::new();
+        field_type.generics(&mut generics);
+        if let Some(generic) = generics.first() {
+            throw_at(&format!("Unknown type {generic} for field {} of {name}", field.content), field.line);
+        }
+        if fields.iter().any(|(f, _)| *f == field.content) {
+            throw_at(&format!("Field {} of {name} is declared twice", field.content), field.line);
+        }
+        fields.push((field.content, field_type));
     }
+    tokens.expect(TokenType::RightBrace);
 
-    ASTNode::Fn(name, arg_symbols, return_type, body)
+    if fields.is_empty() {
+        throw_at(&format!("Struct {name} needs at least one field"), name_token.line);
+    }
+    if symbols.record(&name).is_some() {
+        throw_at(&format!("Struct {name} is already defined"), name_token.line);
+    }
+
+    symbols.insert_record(&name, fields.clone());
+    ASTNode::Struct(name, fields)
 }
 
-fn consume_fn_args(tokens: &mut TokensList) -> (Vec<Symbol>, Vec<NodeType>) {
+fn consume_fn_args(tokens: &mut TokensList, symbols: &SymbolTable) -> (Vec<Symbol>, Vec<NodeType>) {
     let mut args = Vec::new();
     let mut arg_types = Vec::new();
 
@@ -60,7 +185,7 @@ fn consume_fn_args(tokens: &mut TokensList) -> (Vec<Symbol>, Vec<NodeType>) {
 
             tokens.expect(TokenType::Colon);
 
-            let arg_type = parse_type(tokens.expect(TokenType::Literal).content);
+            let arg_type = consume_type(tokens, symbols);
             let symbol = Symbol::new_var(&arg_name, arg_type.clone());
 
             args.push(symbol);
@@ -73,12 +198,11 @@ fn consume_fn_args(tokens: &mut TokensList) -> (Vec<Symbol>, Vec<NodeType>) {
     (args, arg_types)
 }
 
-fn consume_fn_return(tokens: &mut TokensList) -> NodeType {
+fn consume_fn_return(tokens: &mut TokensList, symbols: &SymbolTable) -> NodeType {
     if let Some(token) = tokens.peek() {
         if token.token_type == TokenType::Colon {
             tokens.consume();
-            let type_name = tokens.expect(TokenType::Literal).content;
-            parse_type(type_name)
+            consume_type(tokens, symbols)
         } else {
             NodeType::None
         }
@@ -87,29 +211,38 @@ fn consume_fn_return(tokens: &mut TokensList) -> NodeType {
     }
 }
 
-// Statements surrounded by braces: { ... }
-// "symbols" should be a SymbolTable created for ONLY this block
+// Statements surrounded by braces: { ... }, and the line each one starts on
+// The block gets a scope of its own, starting with "env_symbols"
 fn consume_block(
     tokens: &mut TokensList,
     symbols: &mut SymbolTable,
-    env_symbols: &Vec<Symbol>,
-) -> Vec<ASTNode> {
+    env_symbols: &[Symbol],
+) -> (Vec<ASTNode>, Vec<usize>) {
     tokens.expect(TokenType::LeftBrace);
 
-    let symbols = &mut symbols.clone();
+    symbols.push_scope();
     symbols.insert_vec(env_symbols);
 
     let mut calls = Vec::new();
+    let mut lines = Vec::new();
 
     while let Some(token) = tokens.peek() {
+        if token.token_type != TokenType::RightBrace {
+            lines.push(token.line);
+        }
+
         match token.token_type {
             TokenType::RightBrace => {
                 tokens.consume();
                 break;
             }
             TokenType::Let => {
-                tokens.consume();
-                calls.push(consume_let(tokens, symbols));
+                let line = tokens.consume().line;
+                let lets = consume_let(tokens, symbols);
+
+                // Every node of a destructuring binding starts on its line
+                lines.resize(lines.len() + lets.len() - 1, line);
+                calls.extend(lets);
             }
             _ => {
                 calls.push(parse_node(tokens, symbols));
@@ -117,45 +250,230 @@ fn consume_block(
         }
     }
 
-    calls
+    symbols.pop_scope();
+
+    (calls, lines)
 }
 
-fn consume_let(tokens: &mut TokensList, symbols: &mut SymbolTable) -> ASTNode {
-    tokens.expect(TokenType::LeftParen);
-    let name = tokens.expect(TokenType::Literal).content;
+// "=(name, value)", or "=((a, b), value)" to bind the elements of a tuple
+fn consume_let(tokens: &mut TokensList, symbols: &mut SymbolTable) -> Vec<ASTNode> {
+    let left_paren = tokens.expect(TokenType::LeftParen);
+    let pattern = consume_pattern(tokens);
     tokens.expect(TokenType::Comma);
     let value = parse_node(tokens, symbols);
     tokens.expect(TokenType::RightParen);
 
-    let symbol = Symbol::new_var(&name, symbols.get_node_type(&value));
+    let mut lets = Vec::new();
+    bind_pattern(&pattern, value, symbols, left_paren.line, false, &mut lets);
+    lets
+}
+
+// A name, or names in parentheses (which can be nested), like "(a, (b, c))"
+enum Pattern {
+    Name(String),
+    Tuple(Vec<Pattern>),
+}
+
+impl Pattern {
+    fn name(&self) -> String {
+        match self {
+            Pattern::Name(name) => name.clone(),
+            Pattern::Tuple(items) => ast::destructured_name(&items.iter().map(Pattern::name).collect::<Vec<_>>()),
+        }
+    }
+}
+
+fn consume_pattern(tokens: &mut TokensList) -> Pattern {
+    if tokens.peek().is_some_and(|t| t.token_type == TokenType::LeftParen) {
+        let left_paren = tokens.consume();
+        let mut items = vec![consume_pattern(tokens)];
+        while tokens.peek().is_some_and(|t| t.token_type == TokenType::Comma) {
+            tokens.consume();
+            items.push(consume_pattern(tokens));
+        }
+        tokens.expect(TokenType::RightParen);
+
+        if items.len() < 2 {
+            throw_at("A tuple needs at least two elements", left_paren.line);
+        }
+        Pattern::Tuple(items)
+    } else {
+        Pattern::Name(tokens.expect(TokenType::Literal).content)
+    }
+}
+
+// A tuple pattern binds the whole value to its own name, then each element
+fn bind_pattern(pattern: &Pattern, value: ASTNode, symbols: &mut SymbolTable, line: usize, element: bool, lets: &mut Vec<ASTNode>) {
+    // Tuples are typed as such even when a function returns them, so inlining
+    // the function does not change the type of the binding
+    let value_type = match pattern {
+        Pattern::Name(_) => symbols.get_node_type(&value),
+        Pattern::Tuple(_) => symbols.get_node_type(&value).unwrap_fn(),
+    };
+    let symbol = Symbol::new_var(&pattern.name(), value_type.clone());
     symbols.insert(symbol.clone());
+    let bind = Bind { tuple: matches!(pattern, Pattern::Tuple(_)), element };
+    lets.push(ASTNode::Let(symbol.clone(), Box::new(value), bind));
 
-    ASTNode::Let(symbol, Box::new(value))
+    if let Pattern::Tuple(items) = pattern {
+        match value_type.unwrap_fn() {
+            NodeType::Tuple(types) if types.len() == items.len() => {}
+            t => throw_at(&format!("Can not destructure {} into {}", t.source(), pattern.name()), line),
+        }
+
+        for (i, item) in items.iter().enumerate() {
+            let value = ASTNode::Index(Box::new(ASTNode::Var(symbol.clone())), i);
+            bind_pattern(item, value, symbols, line, true, lets);
+        }
+    }
 }
 
 fn parse_node(tokens: &mut TokensList, symbols: &mut SymbolTable) -> ASTNode {
+    let mut node = parse_operand(tokens, symbols);
+
+    // Elements of tuples, like "pair.0", fields of records, like "point.x",
+    // and updated records, like "point with { x: 3 }"
+    loop {
+        if tokens.peek().is_some_and(|t| t.token_type == TokenType::Dot) {
+            tokens.consume();
+            node = consume_element(node, tokens, symbols);
+        } else if at_with(tokens) {
+            let with = tokens.consume();
+            let name = match symbols.get_node_type(&node).unwrap_fn() {
+                NodeType::Record(name) => name,
+                t => throw_at(&format!("{} is not a struct, so it can not be updated", t.source()), with.line),
+            };
+
+            let fields = consume_field_values(tokens, symbols);
+            if let Some(error) = symbols.fields_error(&name, &fields, false) {
+                throw_at(&error, with.line);
+            }
+            node = ASTNode::With(Box::new(node), fields);
+        } else {
+            return node;
+        }
+    }
+}
+
+// "with" is only a keyword when it is followed by "{", so it can still be a name
+fn at_with(tokens: &TokensList) -> bool {
+    tokens.peek().is_some_and(|t| t.token_type == TokenType::Literal && t.content == "with")
+        && tokens.peek_second().is_some_and(|t| t.token_type == TokenType::LeftBrace)
+}
+
+// What follows the "." in "pair.0" or "point.x"
+fn consume_element(node: ASTNode, tokens: &mut TokensList, symbols: &SymbolTable) -> ASTNode {
     let token = tokens.consume();
+    let node_type = symbols.get_node_type(&node).unwrap_fn();
 
     match token.token_type {
-        TokenType::Integer => ASTNode::Int(token.content.parse::<i64>().unwrap()),
-        TokenType::True => ASTNode::Bool(true),
-        TokenType::False => ASTNode::Bool(false),
+        TokenType::Integer => {
+            let index = token.content.parse::<usize>().unwrap_or_else(|_| {
+                throw_at(&format!("Invalid tuple index {}", token.content), token.line)
+            });
+
+            match node_type {
+                NodeType::Tuple(items) if index < items.len() => {}
+                t => throw_at(&format!("{} has no element {index}", t.source()), token.line),
+            }
+            ASTNode::Index(Box::new(node), index)
+        }
         TokenType::Literal => {
-            let mut symbol = None;
+            if symbols.field(&node_type, &token.content).is_none() {
+                throw_at(&format!("{} has no field {}", node_type.source(), token.content), token.line);
+            }
+            ASTNode::Field(Box::new(node), token.content)
+        }
+        _ => throw_at(&format!("Unexpected token {}, expected an index or a field", token.content), token.line),
+    }
+}
 
-            for s in symbols.iter() {
-                if s.name == token.content {
-                    symbol = Some(s.clone());
-                    break;
-                }
+// "{ name: value, ... }", after the name of a record or "with"
+fn consume_field_values(tokens: &mut TokensList, symbols: &mut SymbolTable) -> Vec<(String, ASTNode)> {
+    tokens.expect(TokenType::LeftBrace);
+
+    let mut fields = Vec::new();
+    while tokens.peek().is_some_and(|t| t.token_type != TokenType::RightBrace) {
+        if !fields.is_empty() {
+            tokens.expect(TokenType::Comma);
+        }
+
+        let field = tokens.expect(TokenType::Literal).content;
+        tokens.expect(TokenType::Colon);
+        fields.push((field, parse_node(tokens, symbols)));
+    }
+    tokens.expect(TokenType::RightBrace);
+
+    fields
+}
+
+// The fields of a record are kept in the order they were declared in
+fn consume_record(name_token: Token, tokens: &mut TokensList, symbols: &mut SymbolTable) -> ASTNode {
+    let name = name_token.content;
+    let mut fields = consume_field_values(tokens, symbols);
+    if let Some(error) = symbols.fields_error(&name, &fields, true) {
+        throw_at(&error, name_token.line);
+    }
+
+    let declared = symbols.record(&name).unwrap();
+    fields.sort_by_key(|(field, _)| declared.iter().position(|(f, _)| f == field));
+    ASTNode::Record(name, fields)
+}
+
+fn parse_operand(tokens: &mut TokensList, symbols: &mut SymbolTable) -> ASTNode {
+    let token = tokens.consume();
+
+    match token.token_type {
+        TokenType::Integer => match token.content.strip_suffix('n') {
+            Some(digits) => ASTNode::BigInt(BigInt::parse(digits).unwrap()),
+            None => match token.content.parse::<i64>() {
+                Ok(v) => ASTNode::Int(v),
+                Err(_) => throw_at(
+                    &format!("{0} is too large for an Int, write {0}n for a BigInt", token.content),
+                    token.line,
+                ),
+            },
+        },
+        TokenType::Float => match token.content.parse::<f64>() {
+            Ok(v) if v.is_finite() => ASTNode::Float(v),
+            _ => throw_at(&format!("{} is too large for a Float", token.content), token.line),
+        },
+        TokenType::Char => {
+            let text = unquote(&token, '\'');
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => ASTNode::Char(c),
+                _ => throw_at(&format!("{} should hold exactly one character", token.content), token.line),
             }
+        }
+        TokenType::String => ASTNode::String(unquote(&token, '"')),
+        TokenType::True => ASTNode::Bool(true),
+        TokenType::False => ASTNode::Bool(false),
+        TokenType::LeftParen => {
+            let mut items = vec![parse_node(tokens, symbols)];
+            while tokens.peek().is_some_and(|t| t.token_type == TokenType::Comma) {
+                tokens.consume();
+                items.push(parse_node(tokens, symbols));
+            }
+            tokens.expect(TokenType::RightParen);
 
-            if let Some(s) = symbol {
-                if let Some(_) = s.arg_types {
-                    consume_call(token.content, tokens, symbols)
-                } else {
-                    ASTNode::Var(Symbol::new_var(&token.content, s.symbol_type))
-                }
+            if items.len() < 2 {
+                throw_at("A tuple needs at least two elements", token.line);
+            }
+            ASTNode::Tuple(items)
+        }
+        TokenType::Literal => {
+            let var_type = symbols.lookup_var(&token.content).map(|s| s.symbol_type.clone());
+            let is_call = tokens.peek().is_some_and(|t| t.token_type == TokenType::LeftParen);
+
+            if symbols.record(&token.content).is_some() && tokens.peek().is_some_and(|t| t.token_type == TokenType::LeftBrace) {
+                consume_record(token, tokens, symbols)
+            // Variables shadow functions with the same name, built-ins
+            // included, unless the name is called
+            } else if symbols.overloads(&token.content).next().is_some() && (is_call || var_type.is_none()) {
+                consume_call(token, tokens, symbols)
+            } else if let Some(var_type) = var_type {
+                ASTNode::Var(Symbol::new_var(&token.content, var_type))
             } else {
                 throw_at(&format!("Unknown symbol {}", token.content), token.line);
             }
@@ -164,19 +482,20 @@ fn parse_node(tokens: &mut TokensList, symbols: &mut SymbolTable) -> ASTNode {
     }
 }
 
-fn consume_call(name: String, tokens: &mut TokensList, symbols: &mut SymbolTable) -> ASTNode {
+fn consume_call(name_token: Token, tokens: &mut TokensList, symbols: &mut SymbolTable) -> ASTNode {
+    let name = name_token.content;
     let left_paren = tokens.expect(TokenType::LeftParen);
 
-    if let Some(token) = tokens.peek() {
+    let call = if let Some(token) = tokens.peek() {
         if token.token_type == TokenType::RightParen {
             tokens.consume();
-            ASTNode::Call(name, Vec::new())
+            ASTNode::Call(name, Vec::new(), Line(name_token.line))
         } else {
             let mut args = vec![parse_node(tokens, symbols)];
 
             while let Some(token) = tokens.peek() {
                 if token.token_type == TokenType::RightParen {
-                    tokens.next();
+                    tokens.consume();
                     break;
                 } else {
                     tokens.expect(TokenType::Comma);
@@ -184,25 +503,102 @@ fn consume_call(name: String, tokens: &mut TokensList, symbols: &mut SymbolTable
                 }
             }
 
-            ASTNode::Call(name, args)
+            ASTNode::Call(name, args, Line(name_token.line))
         }
     } else {
         throw_at("Expected closing paren", left_paren.line)
+    };
+
+    // Check the arguments here, where the line of the call is known
+    if let ASTNode::Call(name, args, _) = &call {
+        if !symbols.check_types(name, args) {
+            throw_at(&symbols.overload_error(name, args), name_token.line);
+        }
+    }
+
+    call
+}
+
+// The text between the quotes of a string or char literal, with escapes replaced
+fn unquote(token: &Token, quote: char) -> String {
+    let unterminated = || -> ! {
+        let kind = if quote == '"' { "string" } else { "char literal" };
+        throw_at(&format!("Unterminated {kind}"), token.line)
+    };
+
+    let mut text = String::new();
+    let mut chars = token.content.chars().skip(1);
+    loop {
+        match chars.next() {
+            Some(c) if c == quote => return text,
+            Some('\\') => text.push(match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some(c @ ('\\' | '\'' | '"')) => c,
+                Some(c) => throw_at(&format!("Unknown escape \\{c}"), token.line),
+                None => unterminated(),
+            }),
+            Some(c) => text.push(c),
+            None => unterminated(),
+        }
+    }
+}
+
+// Types are single tokens like "Int" or "[Int]", except for tuples like
+// "(Int, [Bool])", which also split lists of tuples into "[", "(...)" and "]"
+fn consume_type(tokens: &mut TokensList, symbols: &SymbolTable) -> NodeType {
+    let token = tokens.consume();
+
+    match token.token_type {
+        TokenType::LeftParen => {
+            let mut items = vec![consume_type(tokens, symbols)];
+            while tokens.peek().is_some_and(|t| t.token_type == TokenType::Comma) {
+                tokens.consume();
+                items.push(consume_type(tokens, symbols));
+            }
+            tokens.expect(TokenType::RightParen);
+
+            if items.len() < 2 {
+                throw_at("A tuple needs at least two elements", token.line);
+            }
+            NodeType::Tuple(items)
+        }
+        TokenType::Literal if token.content.chars().all(|c| c == '[') => {
+            let mut item = consume_type(tokens, symbols);
+            let close = tokens.expect(TokenType::Literal);
+            if close.content != "]".repeat(token.content.len()) {
+                throw_at(&format!("Unexpected token {}, expected {}", close.content, "]".repeat(token.content.len())), close.line);
+            }
+            for _ in 0..token.content.len() {
+                item = NodeType::List(Box::new(item));
+            }
+            item
+        }
+        TokenType::Literal => parse_type(token.content, symbols),
+        _ => throw_at(&format!("Unexpected token {}, expected a type", token.content), token.line),
     }
 }
 
-fn parse_type(type_name: String) -> NodeType {
+fn parse_type(type_name: String, symbols: &SymbolTable) -> NodeType {
     match type_name.as_ref() {
         "Int" => NodeType::Int,
+        "BigInt" => NodeType::BigInt,
+        "Float" => NodeType::Float,
+        "Char" => NodeType::Char,
         "Bool" => NodeType::Bool,
         // TODO: "Fn" should be extended to allow more return types (and also arg types)
         "Fn" => NodeType::Fn(Box::new(NodeType::Int)),
         "None" => NodeType::None,
         _ => {
-            if type_name.chars().nth(0).unwrap() == '[' {
+            if type_name.starts_with('[') {
                 NodeType::List(Box::new(parse_type(
-                    (&type_name[1..type_name.len() - 1]).to_string(),
+                    type_name[1..type_name.len() - 1].to_string(),
+                    symbols,
                 )))
+            } else if symbols.record(&type_name).is_some() {
+                NodeType::Record(type_name)
             } else {
                 NodeType::Generic(type_name)
             }
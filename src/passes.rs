@@ -0,0 +1,41 @@
+use crate::ast::ASTNode;
+use crate::error::throw;
+use crate::symbols::SymbolTable;
+
+// A transformation of the whole program, run between parsing and code generation
+pub trait Pass {
+    // Picks the pass in "dump ast --after <name>"
+    fn name(&self) -> &str;
+    fn run(&mut self, tree: Vec<ASTNode>, symbols: &SymbolTable) -> Vec<ASTNode>;
+}
+
+// Runs passes in the order they were added
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    pub fn new() -> PassManager {
+        PassManager::default()
+    }
+
+    pub fn with(mut self, pass: impl Pass + 'static) -> PassManager {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn run(&mut self, tree: Vec<ASTNode>, symbols: &SymbolTable) -> Vec<ASTNode> {
+        self.passes.iter_mut().fold(tree, |tree, pass| pass.run(tree, symbols))
+    }
+
+    // Runs the passes up to and including the one with the given name
+    pub fn run_until(&mut self, tree: Vec<ASTNode>, symbols: &SymbolTable, last: &str) -> Vec<ASTNode> {
+        let Some(end) = self.passes.iter().position(|pass| pass.name() == last) else {
+            let names: Vec<&str> = self.passes.iter().map(|pass| pass.name()).collect();
+            throw(&format!("No pass named \"{last}\" runs, the passes are {}", names.join(", ")));
+        };
+
+        self.passes[..=end].iter_mut().fold(tree, |tree, pass| pass.run(tree, symbols))
+    }
+}
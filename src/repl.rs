@@ -0,0 +1,258 @@
+use std::fs;
+use std::io::{self, prelude::*};
+
+use crate::ast::{self, ASTNode, NodeType};
+use crate::error::{self, throw, Error};
+use crate::symbols::SymbolTable;
+use crate::tokens::{Token, TokenType};
+use crate::{bytecode, compiler, parser, scanner, vm};
+
+const HELP: &str = "\
+Enter function definitions, let-bindings (=(name, value)) or expressions.
+    :type <expr>  Print the type of an expression
+    :ast <expr>   Print the syntax tree of an expression
+    :load <path>  Load the functions in a file
+    :reset        Forget all definitions
+    :quit         Exit";
+
+// Name of the function expressions are wrapped in to be run
+const INPUT_FN: &str = "<input>";
+
+pub struct Repl {
+    symbols: SymbolTable,
+    functions: Vec<ASTNode>,
+    lets: Vec<ASTNode>,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl {
+            symbols: compiler::table_from_inlines(),
+            functions: Vec::new(),
+            lets: Vec::new(),
+        }
+    }
+
+    // Handles one complete input, returning what to print
+    pub fn eval(&mut self, input: &str) -> Result<String, Error> {
+        let input = input.trim();
+
+        if let Some(command) = input.strip_prefix(':') {
+            let (command, arg) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+            let arg = arg.trim();
+
+            return match command {
+                "type" => self.expr(arg).map(|(_, node_type)| node_type.source()),
+                "ast" => self.expr(arg).map(|(node, _)| node.to_string()),
+                "load" => self.load(arg),
+                "reset" => {
+                    *self = Repl::new();
+                    Ok(String::new())
+                }
+                "help" => Ok(HELP.to_string()),
+                _ => Err(Error::new(&format!("Unknown command :{command}, try :help"))),
+            };
+        }
+
+        let tokens = scanner::get_tokens(input);
+
+        if tokens.is_empty() {
+            Ok(String::new())
+        } else if is_definition(&tokens) {
+            self.define(tokens)
+        } else if tokens[0].token_type == TokenType::Let {
+            let mut symbols = self.symbols.clone();
+            let nodes = error::catch(|| parser::build_statement(tokens, &mut symbols))?;
+            self.symbols = symbols;
+            self.lets.extend(nodes);
+            Ok(String::new())
+        } else {
+            let (node, node_type) = self.expr(input)?;
+            let value = self.run(node)?;
+
+            // Chars and strings are shown as literals
+            let shown = match (&value, node_type.unwrap_fn()) {
+                (vm::Value::Char(c), _) => format!("'{}'", ast::escape(*c)),
+                (_, NodeType::List(item)) if *item == NodeType::Char => {
+                    let text = value.as_string().unwrap_or_default();
+                    format!("\"{}\"", text.chars().map(ast::escape).collect::<String>())
+                }
+                // Records are tuples in the VM, so their field names come from the declaration
+                (vm::Value::Tuple(items), NodeType::Record(name)) => {
+                    let declared = self.symbols.record(&name).unwrap_or_default();
+                    let fields: Vec<String> =
+                        declared.iter().zip(items.iter()).map(|((field, _), item)| format!("{field}: {item}")).collect();
+                    format!("{name} {{ {} }}", fields.join(", "))
+                }
+                _ => value.to_string(),
+            };
+            Ok(format!("{shown} : {}", node_type.source()))
+        }
+    }
+
+    fn expr(&self, input: &str) -> Result<(ASTNode, NodeType), Error> {
+        let mut symbols = self.symbols.clone();
+
+        error::catch(|| {
+            let node = match parser::build_statement(scanner::get_tokens(input), &mut symbols).pop() {
+                Some(ASTNode::Let(..)) | None => throw("Expected an expression, got a let-binding"),
+                Some(node) => node,
+            };
+            let node_type = symbols.get_node_type(&node).unwrap_fn();
+            (node, node_type)
+        })
+    }
+
+    // Expressions are run as the body of a function, after the let-bindings
+    fn run(&self, node: ASTNode) -> Result<vm::Value, Error> {
+        let mut body = self.lets.clone();
+        body.push(node);
+
+        let mut tree = self.functions.clone();
+        tree.push(ASTNode::Fn(INPUT_FN.to_string(), Vec::new(), NodeType::None, body));
+
+        // Structs get no chunk, so the input is the last of the functions
+        let input = tree.iter().filter(|node| matches!(node, ASTNode::Fn(..))).count() - 1;
+
+        error::catch(|| {
+            let program = bytecode::compile_program(&tree, &self.symbols);
+            vm::call(&program, input, Vec::new())
+        })
+    }
+
+    fn define(&mut self, tokens: Vec<Token>) -> Result<String, Error> {
+        let mut symbols = self.symbols.clone();
+
+        // Redefining a function replaces it
+        let name = tokens[0].content.clone();
+        self.forget(&name, &mut symbols);
+
+        let functions = error::catch(|| parser::build_ast(tokens, &mut symbols))?;
+
+        self.symbols = symbols;
+        self.functions.retain(|f| !matches!(f, ASTNode::Fn(n, _, _, _) if *n == name));
+        self.functions.extend(functions);
+
+        Ok(String::new())
+    }
+
+    fn load(&mut self, path: &str) -> Result<String, Error> {
+        let code = match fs::read_to_string(path) {
+            Ok(code) => code,
+            Err(e) => return Err(Error::new(&format!("Could not read {path}: {e}"))),
+        };
+
+        let mut symbols = self.symbols.clone();
+        let functions = error::catch(|| parser::build_ast(scanner::get_tokens(&code), &mut symbols))?;
+
+        let mut names = Vec::new();
+        for f in functions {
+            if let ASTNode::Fn(name, _, _, _) = &f {
+                names.push(name.clone());
+            }
+            self.functions.push(f);
+        }
+        self.symbols = symbols;
+
+        Ok(format!("Loaded {}", names.join(", ")))
+    }
+
+    // Removes the symbols of a user function, leaving built-ins alone
+    fn forget(&self, name: &str, symbols: &mut SymbolTable) {
+        for f in self.functions.iter() {
+            if let ASTNode::Fn(n, args, _, _) = f {
+                if n == name {
+                    let arg_types: Vec<NodeType> = args.iter().map(|arg| arg.symbol_type.clone()).collect();
+                    symbols.remove_fn(name, &arg_types);
+                }
+            }
+        }
+    }
+}
+
+// A definition starts with "name(...)" followed by a return type or a block,
+// or declares a struct
+fn is_definition(tokens: &[Token]) -> bool {
+    if tokens.len() > 1 && tokens[0].content == "struct" && tokens[1].token_type == TokenType::Literal {
+        return true;
+    }
+    if tokens.len() < 2 || tokens[0].token_type != TokenType::Literal || tokens[1].token_type != TokenType::LeftParen {
+        return false;
+    }
+
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(1) {
+        match token.token_type {
+            TokenType::LeftParen => depth += 1,
+            TokenType::RightParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return matches!(
+                        tokens.get(i + 1).map(|t| &t.token_type),
+                        Some(TokenType::Colon) | Some(TokenType::LeftBrace)
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
+
+// How many more braces and parentheses were opened than closed
+fn depth(input: &str) -> i64 {
+    scanner::get_tokens(input)
+        .iter()
+        .map(|token| match token.token_type {
+            TokenType::LeftBrace | TokenType::LeftParen => 1,
+            TokenType::RightBrace | TokenType::RightParen => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+// Reads from stdin until it is closed, printing the result of each input
+pub fn run() {
+    let mut repl = Repl::new();
+    let stdin = io::stdin();
+    let mut input = String::new();
+
+    println!("Flip REPL, type :help for help");
+
+    loop {
+        print!("{}", if input.is_empty() { "> " } else { ". " });
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            println!();
+            break;
+        }
+
+        input.push_str(&line);
+
+        // Keep reading until every brace is closed
+        if !input.trim_start().starts_with(':') && depth(&input) > 0 {
+            continue;
+        }
+
+        if input.trim() == ":quit" {
+            break;
+        }
+
+        match repl.eval(&input) {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => println!("{output}"),
+            Err(e) => println!("{e}"),
+        }
+
+        input.clear();
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashSet;
+
+// The C runtime, which is copied into every generated program
+pub const RUNTIME: &str = include_str!("flip.h");
+
+struct Section<'a> {
+    name: &'a str,
+    provides: Vec<&'a str>,
+    requires: Vec<&'a str>,
+    code: String,
+}
+
+// Returns the parts of the runtime used by some generated C
+pub fn runtime_for(code: &str) -> String {
+    let (preamble, sections) = sections();
+
+    let used = identifiers(code);
+    let mut needed: Vec<&str> = sections
+        .iter()
+        .filter(|section| section.provides.iter().any(|name| used.contains(name)))
+        .map(|section| section.name)
+        .collect();
+
+    let mut i = 0;
+    while i < needed.len() {
+        let section = sections.iter().find(|s| s.name == needed[i]).unwrap();
+        for required in section.requires.iter() {
+            if !needed.contains(required) {
+                needed.push(required);
+            }
+        }
+        i += 1;
+    }
+
+    let mut runtime = preamble;
+    for section in sections.iter().filter(|s| needed.contains(&s.name)) {
+        runtime.push_str(&section.code);
+    }
+
+    runtime
+}
+
+fn sections<'a>() -> (String, Vec<Section<'a>>) {
+    let mut preamble = String::new();
+    let mut sections: Vec<Section> = Vec::new();
+
+    for line in RUNTIME.lines() {
+        if let Some(name) = line.strip_prefix("// @section ") {
+            sections.push(Section { name, provides: Vec::new(), requires: Vec::new(), code: String::new() });
+        } else if let (Some(names), Some(section)) = (line.strip_prefix("// @provides "), sections.last_mut()) {
+            section.provides.extend(names.split_whitespace());
+        } else if let (Some(names), Some(section)) = (line.strip_prefix("// @requires "), sections.last_mut()) {
+            section.requires.extend(names.split_whitespace());
+        } else if let Some(section) = sections.last_mut() {
+            section.code.push_str(line);
+            section.code.push('\n');
+        } else if line != "#pragma once" {
+            // The generated C is not a header
+            preamble.push_str(line);
+            preamble.push('\n');
+        }
+    }
+
+    (preamble, sections)
+}
+
+// Every identifier in the C, ignoring comments
+fn identifiers(code: &str) -> HashSet<&str> {
+    code.lines()
+        .map(|line| line.split("//").next().unwrap())
+        .flat_map(|line| line.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_')))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
@@ -0,0 +1,511 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{ASTNode, Line, NodeType};
+use crate::compiler;
+use crate::error::throw;
+use crate::overflow;
+use crate::symbols::{Symbol, SymbolTable};
+
+// Emits a self-contained Rust module with one public function per Flip
+// function. Let-bindings become shared thunks, lists are reference counted.
+pub fn compile_program(tree: &[ASTNode], symbols: &SymbolTable) -> String {
+    let mut out = String::from(PRELUDE);
+
+    for branch in tree {
+        match branch {
+            ASTNode::Fn(name, args, return_type, body) => {
+                let mut emitter = Emitter { symbols, function: name, scope: HashMap::new() };
+                out.push_str(&emitter.compile_fn(args, return_type, body));
+            }
+            ASTNode::Struct(name, fields) => out.push_str(&compile_struct(name, fields)),
+            _ => {}
+        }
+    }
+
+    // BigInts use the compiler's own implementation
+    if out.contains("flip_big::") {
+        out.push_str(&format!("pub mod flip_big {{\n{}}}\n", include_str!("bigint.rs")));
+    }
+
+    out
+}
+
+// Records are plain structs, which generic functions can take like any value
+fn compile_struct(name: &str, fields: &[(String, NodeType)]) -> String {
+    let mut out = format!("// {name}\n#[derive(Clone, PartialEq, PartialOrd)]\npub struct {} {{\n", rust_ident(name));
+    for (field, field_type) in fields {
+        out.push_str(&format!("    pub {}: {},\n", rust_ident(field), rust_type(field_type)));
+    }
+    out.push_str("}\n\n");
+    out
+}
+
+#[derive(Clone, Copy)]
+enum Binding {
+    Arg,
+    Let,
+}
+
+struct Emitter<'a> {
+    symbols: &'a SymbolTable,
+    function: &'a str,
+    scope: HashMap<String, Binding>,
+}
+
+impl Emitter<'_> {
+    fn compile_fn(&mut self, args: &[Symbol], return_type: &NodeType, body: &[ASTNode]) -> String {
+        let (last, init) = body.split_last().unwrap();
+
+        let return_type = match return_type.unwrap_fn() {
+            NodeType::None => self.symbols.get_node_type(last).unwrap_fn(),
+            t => t,
+        };
+
+        let mut generics = HashSet::new();
+        for t in args.iter().map(|arg| &arg.symbol_type).chain([&return_type]) {
+            collect_generics(t, &mut generics);
+        }
+        let mut generics: Vec<String> = generics.into_iter().collect();
+        generics.sort();
+
+        let generics = if generics.is_empty() {
+            String::new()
+        } else {
+            let bounds: Vec<String> = generics
+                .iter()
+                .map(|g| format!("{}: Clone + PartialEq + PartialOrd + 'static", rust_ident(g)))
+                .collect();
+            format!("<{}>", bounds.join(", "))
+        };
+
+        let params: Vec<String> = args
+            .iter()
+            .map(|arg| format!("{}: {}", rust_ident(&arg.name), rust_type(&arg.symbol_type)))
+            .collect();
+
+        let mut out = format!(
+            "// {}\npub fn {}{generics}({}) -> {} {{\n",
+            self.function,
+            rust_ident(self.function),
+            params.join(", "),
+            rust_type(&return_type),
+        );
+
+        for arg in args {
+            self.scope.insert(arg.name.clone(), Binding::Arg);
+        }
+
+        for node in init {
+            if let ASTNode::Let(s, v, _) = node {
+                let mut captures = Vec::new();
+                collect_vars(v, &mut captures);
+                captures.retain(|name| self.scope.contains_key(name));
+
+                let mut thunk = String::from("{ ");
+                for name in captures {
+                    let ident = rust_ident(&name);
+                    thunk.push_str(&format!("let {ident} = {ident}.clone(); "));
+                }
+                thunk.push_str(&format!("flip_rt::Thunk::new(move || {}) }}", self.compile_expr(v)));
+
+                out.push_str(&format!("    let {} = {thunk};\n", rust_ident(&s.name)));
+                self.scope.insert(s.name.clone(), Binding::Let);
+            }
+        }
+
+        out.push_str(&format!("    {}\n}}\n\n", self.compile_expr(last)));
+        out
+    }
+
+    fn compile_expr(&self, node: &ASTNode) -> String {
+        match node {
+            ASTNode::Int(v) => format!("{v}i64"),
+            ASTNode::BigInt(v) => format!("flip_big::BigInt::parse(\"{v}\").unwrap()"),
+            ASTNode::Float(v) => format!("{v:?}f64"),
+            ASTNode::Char(v) => format!("{v:?}"),
+            ASTNode::String(v) => format!("flip_rt::List::string({v:?})"),
+            ASTNode::Bool(v) => format!("{v}"),
+            ASTNode::Var(s) => {
+                let ident = rust_ident(&s.name);
+                match self.scope.get(&s.name) {
+                    Some(Binding::Let) => format!("{ident}.force()"),
+                    Some(Binding::Arg) => format!("{ident}.clone()"),
+                    None => throw(&format!("Unknown variable \"{}\"", s.name)),
+                }
+            }
+            ASTNode::Call(name, args, line) => {
+                if compiler::is_inline_call(name, args, self.symbols) {
+                    self.compile_builtin(name, args, *line)
+                } else {
+                    let args: Vec<String> = args.iter().map(|arg| self.compile_expr(arg)).collect();
+                    format!("{}({})", rust_ident(name), args.join(", "))
+                }
+            }
+            ASTNode::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|item| self.compile_expr(item)).collect();
+                format!("({})", items.join(", "))
+            }
+            ASTNode::Index(tuple, index) => format!("{}.{index}", self.compile_expr(tuple)),
+            ASTNode::Record(name, fields) => format!("{} {{ {} }}", rust_ident(name), self.compile_fields(fields)),
+            ASTNode::Field(record, field) => format!("{}.{}", self.compile_expr(record), rust_ident(field)),
+            ASTNode::With(record, fields) => format!(
+                "{} {{ {}, ..{} }}",
+                rust_type(&self.symbols.get_node_type(record)),
+                self.compile_fields(fields),
+                self.compile_expr(record)
+            ),
+            ASTNode::Let(..) => throw("Let-bindings can only appear in a function body"),
+            ASTNode::Fn(name, _, _, _) => throw(&format!("Function \"{name}\" must be defined at the top level")),
+            ASTNode::Struct(name, _) => throw(&format!("Struct \"{name}\" must be declared at the top level")),
+        }
+    }
+
+    fn compile_fields(&self, fields: &[(String, ASTNode)]) -> String {
+        let fields: Vec<String> =
+            fields.iter().map(|(field, value)| format!("{}: {}", rust_ident(field), self.compile_expr(value))).collect();
+        fields.join(", ")
+    }
+
+    fn compile_builtin(&self, name: &str, args: &[ASTNode], line: Line) -> String {
+        let a: Vec<String> = args.iter().map(|arg| self.compile_expr(arg)).collect();
+        let function = format!("{:?}", self.function);
+
+        let first_type = args.first().map(|arg| self.symbols.get_node_type(arg).unwrap_fn());
+        if first_type == Some(NodeType::Float) {
+            match (name, a.len()) {
+                ("+" | "-" | "*" | "/", 2) => return format!("({} {name} {})", a[0], a[1]),
+                ("-", 1) => return format!("(-{})", a[0]),
+                ("floor" | "round", 1) => return format!("flip_rt::float_to_int({}.{name}(), {function})", a[0]),
+                ("sqrt", 1) => return format!("{}.sqrt()", a[0]),
+                _ => {}
+            }
+        }
+        if first_type == Some(NodeType::BigInt) {
+            match (name, a.len()) {
+                ("+" | "-" | "*", 2) => return format!("(&{} {name} &{})", a[0], a[1]),
+                ("/" | "mod", 2) => {
+                    let (pattern, error) = if name == "/" { ("(v, _)", "division by zero") } else { ("(_, v)", "modulo by zero") };
+                    return format!(
+                        "(match {}.div_rem(&{}) {{ Some({pattern}) => v, None => flip_rt::error({error:?}, {function}) }})",
+                        a[0], a[1]
+                    );
+                }
+                ("-", 1) => return format!("(-&{})", a[0]),
+                ("int", 1) => {
+                    return format!(
+                        "(match {}.to_i64() {{ Some(v) => v, None => flip_rt::error(\"BigInt is too large for an Int\", {function}) }})",
+                        a[0]
+                    )
+                }
+                _ => {}
+            }
+        }
+        if first_type == Some(NodeType::Char) {
+            match (name, a.len()) {
+                ("int", 1) => return format!("({} as i64)", a[0]),
+                ("is_digit", 1) => return format!("{}.is_ascii_digit()", a[0]),
+                ("is_alpha", 1) => return format!("{}.is_ascii_alphabetic()", a[0]),
+                _ => {}
+            }
+        }
+
+        match (name, a.len()) {
+            ("+" | "wrapping_add", 2) => format!("{}.wrapping_add({})", a[0], a[1]),
+            ("-" | "wrapping_sub", 2) => format!("{}.wrapping_sub({})", a[0], a[1]),
+            ("*" | "wrapping_mul", 2) => format!("{}.wrapping_mul({})", a[0], a[1]),
+            ("/", 2) => format!("flip_rt::divide({}, {}, {function})", a[0], a[1]),
+            ("mod", 2) => format!("flip_rt::modulo({}, {}, {function})", a[0], a[1]),
+            ("-" | "wrapping_neg", 1) => format!("{}.wrapping_neg()", a[0]),
+            ("checked_add" | "checked_sub" | "checked_mul" | "checked_neg", _) => {
+                let error = overflow::error_message(&ASTNode::Call(name.to_string(), args.to_vec(), line));
+                format!("flip_rt::checked({}.{name}({}), {error:?}, {}, {function})", a[0], a[1..].join(", "), line.0)
+            }
+            ("big", 1) => format!("flip_big::BigInt::from_i64({})", a[0]),
+            ("to_float", 1) => format!("({} as f64)", a[0]),
+            ("to_char", 1) => format!("flip_rt::to_char({}, {function})", a[0]),
+
+            ("==" | "!=" | ">" | "<" | ">=" | "<=", 2) => format!("({} {name} {})", a[0], a[1]),
+            ("and", 2) => format!("({} && {})", a[0], a[1]),
+            ("or", 2) => format!("({} || {})", a[0], a[1]),
+            ("not", 1) => format!("(!{})", a[0]),
+
+            ("fst", 1) => format!("{}.0", a[0]),
+            ("snd", 1) => format!("{}.1", a[0]),
+
+            ("if", 3) => format!("(if {} {{ {} }} else {{ {} }})", a[0], a[1], a[2]),
+
+            ("[Int]", 0) => String::from("flip_rt::List::<i64>::empty()"),
+            ("[Char]", 0) => String::from("flip_rt::List::<char>::empty()"),
+            ("[Float]", 0) => String::from("flip_rt::List::<f64>::empty()"),
+            ("[BigInt]", 0) => String::from("flip_rt::List::<flip_big::BigInt>::empty()"),
+            ("len", 1) => format!("{}.len()", a[0]),
+            ("head", 1) => format!("{}.head({function})", a[0]),
+            ("tail", 1) => format!("{}.tail({function})", a[0]),
+            ("push", 2) => format!("{}.push({})", a[0], a[1]),
+            ("is_null", 1) => format!("{}.is_null()", a[0]),
+
+            ("assert", 1) => format!("flip_rt::assert({}, {function})", a[0]),
+            ("assert_eq", 2) => format!("flip_rt::assert_eq({}, {}, {function})", a[0], a[1]),
+
+            _ => throw(&format!("Built-in \"{name}\" is not supported by the Rust back end")),
+        }
+    }
+}
+
+fn rust_type(t: &NodeType) -> String {
+    match t.unwrap_fn() {
+        NodeType::Int => String::from("i64"),
+        NodeType::BigInt => String::from("flip_big::BigInt"),
+        NodeType::Float => String::from("f64"),
+        NodeType::Char => String::from("char"),
+        NodeType::Bool => String::from("bool"),
+        NodeType::List(inner) => format!("flip_rt::List<{}>", rust_type(&inner)),
+        NodeType::Tuple(items) => {
+            let items: Vec<String> = items.iter().map(rust_type).collect();
+            format!("({})", items.join(", "))
+        }
+        NodeType::Record(name) => rust_ident(&name),
+        NodeType::None => String::from("()"),
+        NodeType::Generic(g) => rust_ident(&g),
+        NodeType::Fn(_) => unreachable!(),
+    }
+}
+
+fn collect_generics(t: &NodeType, generics: &mut HashSet<String>) {
+    match t.unwrap_fn() {
+        NodeType::Generic(g) => {
+            generics.insert(g);
+        }
+        NodeType::List(inner) => collect_generics(&inner, generics),
+        NodeType::Tuple(items) => items.iter().for_each(|item| collect_generics(item, generics)),
+        _ => {}
+    }
+}
+
+fn collect_vars(node: &ASTNode, vars: &mut Vec<String>) {
+    match node {
+        ASTNode::Var(s) if !vars.contains(&s.name) => vars.push(s.name.clone()),
+        ASTNode::Call(_, args, _) | ASTNode::Tuple(args) => {
+            for arg in args {
+                collect_vars(arg, vars);
+            }
+        }
+        ASTNode::Index(record, _) | ASTNode::Field(record, _) => collect_vars(record, vars),
+        ASTNode::Record(_, fields) => fields.iter().for_each(|(_, value)| collect_vars(value, vars)),
+        ASTNode::With(record, fields) => {
+            collect_vars(record, vars);
+            fields.iter().for_each(|(_, value)| collect_vars(value, vars));
+        }
+        _ => {}
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn", "for", "if",
+    "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct", "trait",
+    "true", "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+// Valid Rust identifiers are kept so that the generated functions can be
+// called by name, anything else goes through the same mangling as the C back
+// end. The "flip_" prefix is reserved for the runtime and mangled names.
+fn rust_ident(name: &str) -> String {
+    let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name.chars().any(|c| c != '_')
+        && !name.starts_with("flip_");
+
+    if !valid || ["self", "Self", "super", "crate"].contains(&name) {
+        format!("flip_{}", compiler::mangle(name))
+    } else if KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+const PRELUDE: &str = r#"// Generated by flip
+#![allow(dead_code, non_snake_case, unused_parens, unused_variables, clippy::all)]
+
+pub mod flip_rt {
+    use std::cell::RefCell;
+    use std::fmt;
+    use std::rc::Rc;
+
+    enum State<T> {
+        Pending(Box<dyn FnOnce() -> T>),
+        Running,
+        Done(T),
+    }
+
+    // A let-binding, evaluated at most once, the first time it is forced
+    pub struct Thunk<T> {
+        state: RefCell<State<T>>,
+    }
+
+    impl<T: Clone> Thunk<T> {
+        pub fn new(f: impl FnOnce() -> T + 'static) -> Rc<Thunk<T>> {
+            Rc::new(Thunk { state: RefCell::new(State::Pending(Box::new(f))) })
+        }
+
+        pub fn force(&self) -> T {
+            let state = std::mem::replace(&mut *self.state.borrow_mut(), State::Running);
+            let value = match state {
+                State::Pending(f) => f(),
+                State::Done(value) => value,
+                State::Running => panic!("Error: let-binding depends on itself"),
+            };
+            *self.state.borrow_mut() = State::Done(value.clone());
+            value
+        }
+    }
+
+    #[derive(PartialEq, PartialOrd)]
+    struct Node<T> {
+        head: T,
+        tail: List<T>,
+    }
+
+    // Dropping a long list recursively would overflow the stack
+    impl<T> Drop for Node<T> {
+        fn drop(&mut self) {
+            let mut tail = self.tail.0.take();
+            while let Some(node) = tail {
+                match Rc::try_unwrap(node) {
+                    Ok(mut node) => tail = node.tail.0.take(),
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+
+    #[derive(PartialEq, PartialOrd)]
+    pub struct List<T>(Option<Rc<Node<T>>>);
+
+    impl<T> Clone for List<T> {
+        fn clone(&self) -> Self {
+            List(self.0.clone())
+        }
+    }
+
+    impl<T: Clone> List<T> {
+        pub fn empty() -> List<T> {
+            List(None)
+        }
+
+        pub fn push(&self, head: T) -> List<T> {
+            List(Some(Rc::new(Node { head, tail: self.clone() })))
+        }
+
+        pub fn head(&self, function: &str) -> T {
+            match &self.0 {
+                Some(node) => node.head.clone(),
+                None => error("head of an empty list", function),
+            }
+        }
+
+        pub fn tail(&self, function: &str) -> List<T> {
+            match &self.0 {
+                Some(node) => node.tail.clone(),
+                None => error("tail of an empty list", function),
+            }
+        }
+
+        pub fn len(&self) -> i64 {
+            self.iter().count() as i64
+        }
+
+        pub fn is_null(&self) -> bool {
+            self.0.is_none()
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+            let mut node = &self.0;
+            std::iter::from_fn(move || {
+                let n = node.as_ref()?;
+                node = &n.tail.0;
+                Some(n.head.clone())
+            })
+        }
+    }
+
+    impl List<char> {
+        pub fn string(text: &str) -> List<char> {
+            text.chars().rev().fold(List::empty(), |list, c| list.push(c))
+        }
+    }
+
+    impl<T: Clone + fmt::Display> fmt::Display for List<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let items: Vec<String> = self.iter().map(|item| item.to_string()).collect();
+            write!(f, "[{}]", items.join(", "))
+        }
+    }
+
+    pub fn error(error: &str, function: &str) -> ! {
+        panic!("Error in {}: {}", function, error)
+    }
+
+    // For errors in code written on a known line, with 0 when it is not known
+    pub fn error_at(error: &str, line: usize, function: &str) -> ! {
+        if line == 0 {
+            self::error(error, function)
+        }
+        panic!("Error, line {}: {}", line, error)
+    }
+
+    pub fn assert(ok: bool, function: &str) -> bool {
+        if !ok {
+            error("assertion failed", function)
+        }
+        true
+    }
+
+    pub fn assert_eq<T: PartialEq>(a: T, b: T, function: &str) -> bool {
+        if a != b {
+            error("assert_eq failed", function)
+        }
+        true
+    }
+
+    pub fn checked(result: Option<i64>, error: &str, line: usize, function: &str) -> i64 {
+        match result {
+            Some(value) => value,
+            None => error_at(error, line, function),
+        }
+    }
+
+    pub fn float_to_int(value: f64, function: &str) -> i64 {
+        // Also false for NaN
+        if !(value >= -9223372036854775808.0 && value < 9223372036854775808.0) {
+            error("Float does not fit in an Int", function)
+        }
+        value as i64
+    }
+
+    pub fn to_char(value: i64, function: &str) -> char {
+        if value < 0 || value > u32::max_value() as i64 {
+            error("Int is not a valid Char", function)
+        }
+        match std::char::from_u32(value as u32) {
+            Some(c) => c,
+            None => error("Int is not a valid Char", function),
+        }
+    }
+
+    pub fn divide(a: i64, b: i64, function: &str) -> i64 {
+        if b == 0 {
+            error("division by zero", function)
+        }
+        a.wrapping_div(b)
+    }
+
+    pub fn modulo(a: i64, b: i64, function: &str) -> i64 {
+        if b == 0 {
+            error("modulo by zero", function)
+        }
+        a.wrapping_rem(b)
+    }
+}
+
+"#;
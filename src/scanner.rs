@@ -1,25 +1,46 @@
-use std::iter::{Enumerate, Peekable};
-use std::str::Chars;
+use std::iter::Peekable;
+use std::str::CharIndices;
 
 use crate::tokens::{Token, TokenType};
 
 struct Scanner<'a> {
     code: &'a str,
-    chars: Peekable<Enumerate<Chars<'a>>>,
+    chars: Peekable<CharIndices<'a>>,
     line: usize,
+    // Byte index of the first character of the current line
+    line_start: usize,
 }
 
 impl<'a> Scanner<'a> {
+    fn token(&self, token_type: TokenType, start: usize, end: usize) -> Token {
+        Token::new(
+            token_type,
+            String::from(&self.code[start..end]),
+            self.line,
+            self.code[self.line_start..start].chars().count() + 1,
+        )
+    }
+
     fn new(code: &'a str) -> Self {
         Scanner {
             code,
-            chars: code.chars().enumerate().peekable(),
+            chars: code.char_indices().peekable(),
             line: 1,
+            line_start: 0,
         }
     }
 }
 
 pub fn get_tokens(code: &str) -> Vec<Token> {
+    scan(code, false)
+}
+
+// Also returns comments, as Comment tokens, for tools that rewrite the code
+pub fn get_tokens_with_comments(code: &str) -> Vec<Token> {
+    scan(code, true)
+}
+
+fn scan(code: &str, keep_comments: bool) -> Vec<Token> {
     let mut tokens: Vec<Token> = Vec::new();
     let mut scanner = Scanner::new(code);
 
@@ -35,11 +56,12 @@ pub fn get_tokens(code: &str) -> Vec<Token> {
             ':' => one_char_token(TokenType::Colon, &mut scanner, i),
 
             // Whitespace
-            '\n' | '\r' => {
+            '\n' => {
                 scanner.line += 1;
+                scanner.line_start = i + 1;
                 continue;
             }
-            ' ' | '\t' => {
+            ' ' | '\t' | '\r' => {
                 continue;
             }
 
@@ -48,11 +70,19 @@ pub fn get_tokens(code: &str) -> Vec<Token> {
                 let mut token = None;
 
                 if let Some((_, '/')) = scanner.chars.peek() {
-                    while let Some(&(_, c)) = scanner.chars.peek() {
-                        scanner.chars.next();
+                    let mut end = scanner.code.len();
+                    while let Some(&(j, c)) = scanner.chars.peek() {
                         if c == '\n' {
+                            end = j;
                             break;
                         }
+                        scanner.chars.next();
+                    }
+
+                    if keep_comments {
+                        let mut comment = scanner.token(TokenType::Comment, i, end);
+                        comment.content = comment.content.trim_end().to_string();
+                        token = Some(comment);
                     }
                 } else {
                     token = scan_literal(&mut scanner, i)
@@ -62,7 +92,12 @@ pub fn get_tokens(code: &str) -> Vec<Token> {
             }
 
             // Cool stuff
+            // Elements of tuples, so "pair.0.1" is not read as a Float
+            '0'..='9' if tokens.last().is_some_and(|t| t.token_type == TokenType::Dot) => scan_index(&mut scanner, i),
             '0'..='9' => scan_int(&mut scanner, i),
+            '"' => scan_quoted(&mut scanner, i, '"', TokenType::String),
+            '\'' => scan_quoted(&mut scanner, i, '\'', TokenType::Char),
+            '#' if matches!(scanner.chars.peek(), Some((_, '['))) => scan_attribute(&mut scanner, i),
             _ => scan_literal(&mut scanner, i),
         };
 
@@ -79,15 +114,12 @@ fn one_char_token<'a>(
     scanner: &mut Scanner<'a>,
     start: usize,
 ) -> Option<Token> {
-    Some(Token::new(
-        token_type,
-        String::from(&scanner.code[start..start + 1]),
-        scanner.line,
-    ))
+    Some(scanner.token(token_type, start, start + 1))
 }
 
 fn scan_literal<'a>(scanner: &mut Scanner<'a>, start: usize) -> Option<Token> {
-    let mut end = 0;
+    // Tokens at the end of the code run to its end
+    let mut end = scanner.code.len();
 
     while let Some(&(j, c)) = scanner.chars.peek() {
         match c {
@@ -115,20 +147,50 @@ fn scan_literal<'a>(scanner: &mut Scanner<'a>, start: usize) -> Option<Token> {
         }
     }
 
-    let content = &scanner.code[start..end];
-
-    let token_type = match content {
+    let token_type = match &scanner.code[start..end] {
         "=" => TokenType::Let,
         "true" => TokenType::True,
         "false" => TokenType::False,
         _ => TokenType::Literal,
     };
 
-    Some(Token::new(token_type, String::from(content), scanner.line))
+    Some(scanner.token(token_type, start, end))
 }
 
+// BigInt literals end with an "n", and Float literals have a fraction, an
+// exponent or both, like 1.5, 2e-3 or 6.02e23
 fn scan_int<'a>(scanner: &mut Scanner<'a>, start: usize) -> Option<Token> {
-    let mut end = 0;
+    let mut end = scanner.code.len();
+    let (mut fraction, mut exponent) = (false, false);
+
+    while let Some(&(j, c)) = scanner.chars.peek() {
+        if c.is_ascii_digit() {
+            scanner.chars.next();
+        } else if c == 'n' && !fraction && !exponent {
+            scanner.chars.next();
+            end = j + 1;
+            break;
+        } else if c == '.' && !fraction && !exponent && digits_follow(scanner, false) {
+            scanner.chars.next();
+            fraction = true;
+        } else if matches!(c, 'e' | 'E') && !exponent && digits_follow(scanner, true) {
+            scanner.chars.next();
+            if matches!(scanner.chars.peek(), Some((_, '+' | '-'))) {
+                scanner.chars.next();
+            }
+            exponent = true;
+        } else {
+            end = j;
+            break;
+        }
+    }
+
+    let token_type = if fraction || exponent { TokenType::Float } else { TokenType::Integer };
+    Some(scanner.token(token_type, start, end))
+}
+
+fn scan_index<'a>(scanner: &mut Scanner<'a>, start: usize) -> Option<Token> {
+    let mut end = scanner.code.len();
 
     while let Some(&(j, c)) = scanner.chars.peek() {
         if c.is_ascii_digit() {
@@ -139,9 +201,71 @@ fn scan_int<'a>(scanner: &mut Scanner<'a>, start: usize) -> Option<Token> {
         }
     }
 
-    Some(Token::new(
-        TokenType::Integer,
-        String::from(&scanner.code[start..end]),
-        scanner.line,
-    ))
+    Some(scanner.token(TokenType::Integer, start, end))
+}
+
+// If a digit comes after the next character, and an optional sign
+fn digits_follow(scanner: &Scanner, signed: bool) -> bool {
+    let mut ahead = scanner.chars.clone().skip(1).map(|(_, c)| c);
+    match ahead.next() {
+        Some('+' | '-') if signed => ahead.next().is_some_and(|c| c.is_ascii_digit()),
+        c => c.is_some_and(|c| c.is_ascii_digit()),
+    }
+}
+
+// Attributes like "#[allow(unused_param)]" run to the next "]" on the same line
+fn scan_attribute<'a>(scanner: &mut Scanner<'a>, start: usize) -> Option<Token> {
+    let mut end = scanner.code.len();
+
+    while let Some(&(j, c)) = scanner.chars.peek() {
+        match c {
+            ']' => {
+                scanner.chars.next();
+                end = j + 1;
+                break;
+            }
+            '\n' | '\r' => {
+                end = j;
+                break;
+            }
+            _ => {
+                scanner.chars.next();
+            }
+        }
+    }
+
+    Some(scanner.token(TokenType::Attribute, start, end))
+}
+
+// Strings and char literals end at the next quote on the same line which is
+// not escaped, like \", and keep their quotes, so the parser can tell if one
+// was left open
+fn scan_quoted<'a>(scanner: &mut Scanner<'a>, start: usize, quote: char, token_type: TokenType) -> Option<Token> {
+    let mut end = scanner.code.len();
+
+    while let Some(&(j, c)) = scanner.chars.peek() {
+        match c {
+            _ if c == quote => {
+                scanner.chars.next();
+                end = j + 1;
+                break;
+            }
+            '\\' => {
+                scanner.chars.next();
+                if scanner.chars.next_if(|&(_, c)| c != '\n' && c != '\r').is_none() {
+                    end = j + 1;
+                    break;
+                }
+            }
+            '\n' | '\r' => {
+                end = j;
+                break;
+            }
+            _ => {
+                scanner.chars.next();
+            }
+        }
+    }
+
+    Some(scanner.token(token_type, start, end))
 }
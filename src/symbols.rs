@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use crate::error::throw;
 use crate::ast::{ASTNode, NodeType};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Symbol {
     pub name: String,
     pub symbol_type: NodeType,
@@ -20,81 +20,306 @@ impl<'a> Symbol {
     }
 }
 
+// Symbols are kept in a chain of scopes, the first holding built-ins and
+// functions and the others the parameters and let-bindings of each block.
+// Records declared with "struct" are global, with their fields in order.
 #[derive(Clone, Debug)]
 pub struct SymbolTable {
-    pub table: Vec<Symbol>
+    scopes: Vec<Scope>,
+    records: HashMap<String, Vec<(String, NodeType)>>,
+    // The symbols the table was made "from", which come first in the global scope
+    builtins: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Scope {
+    symbols: Vec<Symbol>,
+    // Where the symbols with each name are in "symbols", in the order they were declared
+    names: HashMap<String, Vec<usize>>,
+}
+
+impl Scope {
+    fn insert(&mut self, s: Symbol) {
+        self.names.entry(s.name.clone()).or_default().push(self.symbols.len());
+        self.symbols.push(s);
+    }
+
+    fn named<'a>(&'a self, name: &str) -> impl DoubleEndedIterator<Item = &'a Symbol> {
+        self.names.get(name).into_iter().flatten().map(|&i| &self.symbols[i])
+    }
 }
 
 impl SymbolTable {
     pub fn from(table: Vec<Symbol>) -> SymbolTable {
-        SymbolTable { table }
+        let builtins = table.len();
+        let mut global = Scope::default();
+        for s in table {
+            global.insert(s);
+        }
+
+        SymbolTable { scopes: vec![global], records: HashMap::new(), builtins }
+    }
+
+    // Every symbol in scope, outermost first
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.scopes.iter().flat_map(|scope| scope.symbols.iter())
     }
 
-    pub fn iter(&self) -> std::slice::Iter<'_, Symbol> {
-        self.table.iter()
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
     }
 
     pub fn insert(&mut self, s: Symbol) {
-        self.table.push(s);
+        self.scopes.last_mut().unwrap().insert(s);
+    }
+
+    pub fn insert_vec(&mut self, v: &[Symbol]) {
+        for s in v {
+            self.insert(s.clone());
+        }
+    }
+
+    // Removes one overload of a function
+    pub fn remove_fn(&mut self, name: &str, arg_types: &[NodeType]) {
+        let removed = |s: &Symbol| s.name == name && s.arg_types.as_deref() == Some(arg_types);
+        self.builtins -= self.scopes[0].symbols[..self.builtins].iter().filter(|s| removed(s)).count();
+
+        for scope in self.scopes.iter_mut() {
+            if scope.named(name).any(removed) {
+                let symbols = std::mem::take(&mut scope.symbols);
+                *scope = Scope::default();
+                for s in symbols {
+                    if !removed(&s) {
+                        scope.insert(s);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn insert_record(&mut self, name: &str, fields: Vec<(String, NodeType)>) {
+        self.records.insert(name.to_string(), fields);
+    }
+
+    pub fn record(&self, name: &str) -> Option<&[(String, NodeType)]> {
+        self.records.get(name).map(Vec::as_slice)
+    }
+
+    // The position and type of a field of a record
+    pub fn field(&self, record_type: &NodeType, field: &str) -> Option<(usize, NodeType)> {
+        let NodeType::Record(name) = record_type.unwrap_fn() else {
+            return None;
+        };
+        self.record(&name)?.iter().enumerate().find(|(_, (f, _))| f == field).map(|(i, (_, t))| (i, t.clone()))
+    }
+
+    // The types of the elements of a tuple, or of the fields of a record
+    pub fn elements(&self, t: &NodeType) -> Option<Vec<NodeType>> {
+        match t.unwrap_fn() {
+            NodeType::Tuple(items) => Some(items),
+            NodeType::Record(name) => Some(self.record(&name)?.iter().map(|(_, t)| t.clone()).collect()),
+            _ => None,
+        }
     }
 
-    pub fn insert_vec(&mut self, v: &Vec<Symbol>) {
-        self.table.extend_from_slice(v);
+    // Why the fields given to a record, as in "Point { x: 1, y: 2 }" or
+    // "point with { x: 3 }", don't fit its declaration, if they don't.
+    // Building a record takes every field, updating one takes any of them.
+    pub fn fields_error(&self, name: &str, fields: &[(String, ASTNode)], all: bool) -> Option<String> {
+        let Some(declared) = self.record(name) else {
+            return Some(format!("Unknown struct {name}"));
+        };
+
+        for (i, (field, value)) in fields.iter().enumerate() {
+            let Some((_, field_type)) = declared.iter().find(|(f, _)| f == field) else {
+                return Some(format!("{name} has no field {field}"));
+            };
+            if fields[..i].iter().any(|(f, _)| f == field) {
+                return Some(format!("Field {field} of {name} is given twice"));
+            }
+
+            let value_type = self.get_node_type(value);
+            if !Self::compare(&value_type, field_type, &mut HashMap::new()) {
+                return Some(format!(
+                    "Field {field} of {name} is {}, got {}",
+                    field_type.source(),
+                    value_type.unwrap_fn().source()
+                ));
+            }
+        }
+
+        let missing: Vec<&str> = declared
+            .iter()
+            .filter(|(f, _)| !fields.iter().any(|(given, _)| given == f))
+            .map(|(f, _)| f.as_str())
+            .collect();
+        if all && !missing.is_empty() {
+            let plural = if missing.len() == 1 { "" } else { "s" };
+            return Some(format!("{name} is missing the field{plural} {}", missing.join(", ")));
+        }
+
+        None
     }
 
-    pub fn check_types(&self, name: &String, args: &Vec<ASTNode>) -> bool {
+    // Every overload of a function, in the order they were declared, except
+    // that built-ins come after the program's functions. A function written
+    // with the name of a built-in then wins over it, so adding built-ins does
+    // not change what programs already call.
+    pub fn overloads<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Symbol> {
+        let (builtins, declared): (Vec<&Symbol>, Vec<&Symbol>) = self
+            .scopes
+            .iter()
+            .flat_map(move |scope| scope.named(name))
+            .filter(|s| s.arg_types.is_some())
+            .partition(|s| self.is_builtin(s));
+
+        declared.into_iter().chain(builtins)
+    }
+
+    fn is_builtin(&self, s: &Symbol) -> bool {
+        self.scopes[0].symbols[..self.builtins].iter().any(|builtin| std::ptr::eq(builtin, s))
+    }
+
+    // True if a call resolves to a built-in rather than a function of the program
+    pub fn calls_builtin(&self, name: &str, args: &[ASTNode]) -> bool {
+        self.find_fn(name, args).is_some_and(|s| self.is_builtin(s))
+    }
+
+    // The innermost variable with a name
+    pub fn lookup_var(&self, name: &str) -> Option<&Symbol> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.named(name).rev().find(|s| s.arg_types.is_none()))
+    }
+
+    pub fn check_types(&self, name: &str, args: &[ASTNode]) -> bool {
         self.find_fn(name, args).is_some()
     }
 
-    pub fn get_arg_types(&self, name: &String, args: &Vec<ASTNode>) -> Vec<NodeType> {
+    pub fn get_arg_types(&self, name: &str, args: &[ASTNode]) -> Vec<NodeType> {
         if let Some(s) = self.find_fn(name, args) {
-            return s.arg_types.clone().unwrap();
+            s.arg_types.clone().unwrap()
         } else {
-            panic!("Could not find symbol {name}");
+            throw(&self.overload_error(name, args));
         }
     }
 
 
-    pub fn get_return_type(&self, name: &String, args: &Vec<ASTNode>) -> NodeType {
+    pub fn get_return_type(&self, name: &str, args: &[ASTNode]) -> NodeType {
         if let Some(s) = self.find_fn(name, args) {
             s.symbol_type.clone()
         } else {
-            panic!("Could not find symbol {name}");
+            throw(&self.overload_error(name, args));
+        }
+    }
+
+    fn find_fn<'a>(&'a self, name: &'a str, args: &[ASTNode]) -> Option<&'a Symbol> {
+        let arg_types: Vec<NodeType> = args.iter().map(|arg| self.get_node_type(arg)).collect();
+        self.find_fn_for_types(name, &arg_types)
+    }
+
+    // Typing each argument once keeps nested calls, like long strings, linear
+    fn find_fn_for_types<'a>(&'a self, name: &'a str, arg_types: &[NodeType]) -> Option<&'a Symbol> {
+        self.overloads(name).find(|s| Self::compare_node_types(arg_types, s.arg_types.as_ref().unwrap()))
+    }
+
+    // Lists the argument types of a call which matches no overload of its
+    // function, and why each overload doesn't match
+    pub fn overload_error(&self, name: &str, args: &[ASTNode]) -> String {
+        let arg_types: Vec<String> = args.iter().map(|arg| self.get_node_type(arg).unwrap_fn().source()).collect();
+        let mut message = format!("No overload of \"{name}\" matches the arguments ({})", arg_types.join(", "));
+
+        let overloads: Vec<(String, String)> = self
+            .overloads(name)
+            .map(|s| {
+                let goal_types = s.arg_types.as_ref().unwrap();
+                let params: Vec<String> = goal_types.iter().map(|t| t.unwrap_fn().source()).collect();
+                let signature = format!("{name}({}): {}", params.join(", "), s.symbol_type.unwrap_fn().source());
+                (signature, self.mismatch(args, goal_types).unwrap_or_default())
+            })
+            .collect();
+
+        let width = overloads.iter().map(|(signature, _)| signature.len()).max().unwrap_or(0);
+        for (signature, reason) in overloads {
+            message.push_str(&format!("\n    {signature:width$}  {reason}"));
         }
+
+        message
     }
 
-    fn find_fn(&self, name: &String, args: &Vec<ASTNode>) -> Option<&Symbol> {
-        for s in self.table.iter() {
-            if let Some(arg_types) = &s.arg_types {
-                if *name == s.name && self.compare_types(&args, arg_types) {
-                    return Some(s);
+    // Why arguments don't fit the types of a function's parameters, if they don't
+    fn mismatch(&self, args: &[ASTNode], goal_types: &[NodeType]) -> Option<String> {
+        if args.len() != goal_types.len() {
+            let plural = if goal_types.len() == 1 { "" } else { "s" };
+            return Some(format!("takes {} argument{plural}, got {}", goal_types.len(), args.len()));
+        }
+
+        let mut generics = HashMap::new();
+
+        for (i, (arg, goal_type)) in args.iter().zip(goal_types).enumerate() {
+            let arg_type = self.get_node_type(arg);
+            let bound = generics.clone();
+
+            if !Self::compare(&arg_type, goal_type, &mut generics) {
+                let mut reason = format!(
+                    "argument {} is {}, expected {}",
+                    i + 1,
+                    arg_type.unwrap_fn().source(),
+                    goal_type.unwrap_fn().source()
+                );
+
+                // Generics can be fixed by earlier arguments
+                let mut names = Vec::new();
+                goal_type.generics(&mut names);
+                let bindings: Vec<String> = names
+                    .iter()
+                    .filter_map(|g| bound.get(g).map(|t| format!("{g} is {}", t.unwrap_fn().source())))
+                    .collect();
+                if !bindings.is_empty() {
+                    reason.push_str(&format!(", where {}", bindings.join(", ")));
                 }
+
+                return Some(reason);
             }
         }
 
         None
     }
 
-    pub fn compare_types(&self, args: &Vec<ASTNode>, goal_types: &Vec<NodeType>) -> bool {
-        if  args.len() != goal_types.len() {
+    pub fn compare_types(&self, args: &[ASTNode], goal_types: &[NodeType]) -> bool {
+        let arg_types: Vec<NodeType> = args.iter().map(|arg| self.get_node_type(arg)).collect();
+        Self::compare_node_types(&arg_types, goal_types)
+    }
+
+    fn compare_node_types(arg_types: &[NodeType], goal_types: &[NodeType]) -> bool {
+        if arg_types.len() != goal_types.len() {
             return false;
         }
-    
+
         let mut generics = HashMap::new();
-    
-        for (arg, goal_type) in args.into_iter().zip(goal_types.into_iter()) {
-            let arg_type = self.get_node_type(arg);
-            if !Self::compare(&arg_type, goal_type, &mut generics) {
+
+        for (arg_type, goal_type) in arg_types.iter().zip(goal_types) {
+            if !Self::compare(arg_type, goal_type, &mut generics) {
                 return false;
             }
         }
-    
+
         true
     }
 
     fn compare<'a>(a: &'a NodeType, b: &'a NodeType, generics: &mut HashMap<String, NodeType>) -> bool {
         match (a, b) {
             (NodeType::Int, NodeType::Int) => true,
+            (NodeType::BigInt, NodeType::BigInt) => true,
+            (NodeType::Float, NodeType::Float) => true,
+            (NodeType::Char, NodeType::Char) => true,
             (NodeType::Bool, NodeType::Bool) => true,
             (NodeType::None, NodeType::None) => true,
             (NodeType::Generic(a), NodeType::Generic(b)) => a == b,
@@ -112,6 +337,10 @@ impl SymbolTable {
                 true
             }
             (NodeType::List(a), NodeType::List(b)) => Self::compare(a, b, generics),
+            (NodeType::Record(a), NodeType::Record(b)) => a == b,
+            (NodeType::Tuple(a), NodeType::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| Self::compare(a, b, generics))
+            }
             (NodeType::Fn(_), _) |
             (_, NodeType::Fn(_)) => {
                 Self::compare(&a.unwrap_fn(), &b.unwrap_fn(), generics)
@@ -120,41 +349,46 @@ impl SymbolTable {
         }
     }
 
-    pub fn get_node_type<'a>(&self, node: &ASTNode) -> NodeType {
+    pub fn get_node_type(&self, node: &ASTNode) -> NodeType {
         match node {
             ASTNode::Fn(_, _, return_type, _) => NodeType::Fn(Box::new(return_type.clone())),
-            ASTNode::Let(_, _) => throw("Cannot pass a let-binding as an argument"),
-            ASTNode::Call(name, args) => {
-                let return_type = self.get_return_type(name, args);
-                let arg_types = self.get_arg_types(name, args);
+            ASTNode::Let(..) => throw("Cannot pass a let-binding as an argument"),
+            ASTNode::Call(name, args, _) => {
+                let node_types: Vec<NodeType> = args.iter().map(|arg| self.get_node_type(arg)).collect();
+                let Some(s) = self.find_fn_for_types(name, &node_types) else {
+                    throw(&self.overload_error(name, args));
+                };
+                let return_type = s.symbol_type.clone();
                 let mut generics = HashMap::new();
 
-                for (arg_type, arg) in arg_types.iter().zip(args.iter()) {
-                    let mut left_type = arg_type.unwrap_fn();
-                    let mut right_type = self.get_node_type(arg).unwrap_fn();
-
-                    loop {
-                        match (left_type, &right_type) {
-                            (NodeType::List(a), NodeType::List(b)) => {
-                                left_type = *a.clone();
-                                right_type = *b.clone();
-                            }
-                            (NodeType::Generic(g), _) => {
-                                if !generics.contains_key(&g) {
-                                    generics.insert(g, right_type);
-                                }
-                                break;
+                // Finds what each generic stands for, inside lists and tuples too
+                fn bind(param_type: NodeType, arg_type: NodeType, generics: &mut HashMap<String, NodeType>) {
+                    match (param_type, arg_type) {
+                        (NodeType::List(a), NodeType::List(b)) => bind(*a, *b, generics),
+                        (NodeType::Tuple(a), NodeType::Tuple(b)) => {
+                            for (a, b) in a.into_iter().zip(b) {
+                                bind(a.unwrap_fn(), b.unwrap_fn(), generics);
                             }
-                            _ => break
                         }
+                        (NodeType::Generic(g), t) => {
+                            generics.entry(g).or_insert(t);
+                        }
+                        _ => {}
                     }
                 }
 
-                fn wrap(return_type: NodeType, generics: HashMap<String, NodeType>) -> NodeType {
+                for (arg_type, node_type) in s.arg_types.as_ref().unwrap().iter().zip(node_types) {
+                    bind(arg_type.unwrap_fn(), node_type.unwrap_fn(), &mut generics);
+                }
+
+                fn wrap(return_type: NodeType, generics: &HashMap<String, NodeType>) -> NodeType {
                     match return_type {
                         NodeType::List(inner) => {
                             NodeType::List(Box::new(wrap(*inner, generics)))
                         }
+                        NodeType::Tuple(items) => {
+                            NodeType::Tuple(items.into_iter().map(|item| wrap(item, generics)).collect())
+                        }
                         NodeType::Generic(g) => {
                             generics.get(&g).unwrap().clone()
                         }
@@ -162,11 +396,30 @@ impl SymbolTable {
                     }
                 }
 
-                wrap(return_type, generics)
+                wrap(return_type, &generics)
             },
             ASTNode::Var(s) => s.symbol_type.clone(),
             ASTNode::Int(_) => NodeType::Int,
+            ASTNode::BigInt(_) => NodeType::BigInt,
+            ASTNode::Float(_) => NodeType::Float,
+            ASTNode::Char(_) => NodeType::Char,
+            ASTNode::String(_) => NodeType::List(Box::new(NodeType::Char)),
             ASTNode::Bool(_) => NodeType::Bool,
+            ASTNode::Tuple(items) => NodeType::Tuple(items.iter().map(|item| self.get_node_type(item).unwrap_fn()).collect()),
+            ASTNode::Index(tuple, index) => match self.get_node_type(tuple).unwrap_fn() {
+                NodeType::Tuple(items) if *index < items.len() => items[*index].clone(),
+                t => throw(&format!("{} has no element {index}", t.source())),
+            },
+            ASTNode::Struct(name, _) |
+            ASTNode::Record(name, _) => NodeType::Record(name.clone()),
+            ASTNode::Field(record, field) => {
+                let record_type = self.get_node_type(record);
+                match self.field(&record_type, field) {
+                    Some((_, field_type)) => field_type,
+                    None => throw(&format!("{} has no field {field}", record_type.unwrap_fn().source())),
+                }
+            }
+            ASTNode::With(record, _) => self.get_node_type(record).unwrap_fn(),
         }
     }
 }
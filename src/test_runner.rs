@@ -0,0 +1,65 @@
+use crate::ast::{ASTNode, NodeType, Test};
+use crate::error::{self, Error};
+use crate::symbols::SymbolTable;
+use crate::{bytecode, vm};
+
+pub struct TestResult {
+    pub name: String,
+    // The first failure, with the line of the statement it happened in
+    pub error: Option<Error>,
+}
+
+// Every statement in a test, other than let-bindings, is run in order with
+// the VM. A test fails at the first statement which throws an error (like a
+// failed assertion) or evaluates to false.
+pub fn run_tests(tree: &[ASTNode], tests: &[Test], symbols: &SymbolTable) -> Vec<TestResult> {
+    // Each statement becomes a function of its own, taking the let-bindings
+    // before it, as only the last statement of a body is evaluated. Structs
+    // get no chunk, so they are left out to keep the indexes of the chunks.
+    let mut program_tree: Vec<ASTNode> = tree.iter().filter(|node| matches!(node, ASTNode::Fn(..))).cloned().collect();
+    let mut statements = Vec::new();
+
+    for (t, test) in tests.iter().enumerate() {
+        for (i, (node, line)) in test.body.iter().zip(&test.lines).enumerate() {
+            if let ASTNode::Let(..) = node {
+                continue;
+            }
+
+            let mut body: Vec<ASTNode> =
+                test.body[..i].iter().filter(|n| matches!(n, ASTNode::Let(..))).cloned().collect();
+            body.push(node.clone());
+
+            statements.push((t, *line, program_tree.len()));
+            program_tree.push(ASTNode::Fn(test.name.clone(), Vec::new(), NodeType::None, body));
+        }
+    }
+
+    let mut results: Vec<TestResult> =
+        tests.iter().map(|test| TestResult { name: test.name.clone(), error: None }).collect();
+
+    let program = match error::catch(|| bytecode::compile_program(&program_tree, symbols)) {
+        Ok(program) => program,
+        Err(e) => {
+            for result in results.iter_mut() {
+                result.error = Some(e.clone());
+            }
+            return results;
+        }
+    };
+
+    for (t, line, chunk) in statements {
+        if results[t].error.is_some() {
+            continue;
+        }
+
+        let error = match error::catch(|| vm::call(&program, chunk, Vec::new())) {
+            Ok(vm::Value::Bool(false)) => Some(Error::new("expression was false")),
+            Ok(_) => None,
+            Err(e) => Some(e),
+        };
+
+        results[t].error = error.map(|e| Error { line: Some(line), ..e });
+    }
+
+    results
+}
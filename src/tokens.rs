@@ -1,5 +1,4 @@
 use std::fmt;
-use std::iter::Peekable;
 use std::vec::IntoIter;
 
 use crate::error::{throw, throw_at};
@@ -19,7 +18,12 @@ pub enum TokenType {
     True,
     False,
     Integer,
+    Float,
+    Char,
     Literal,
+    String,
+    Comment,
+    Attribute,
 }
 
 impl fmt::Display for TokenType {
@@ -38,7 +42,12 @@ impl fmt::Display for TokenType {
             TokenType::True => write!(f, "True"),
             TokenType::False => write!(f, "False"),
             TokenType::Integer => write!(f, "Integer"),
+            TokenType::Float => write!(f, "Float"),
+            TokenType::Char => write!(f, "Char"),
             TokenType::Literal => write!(f, "Literal"),
+            TokenType::String => write!(f, "String"),
+            TokenType::Comment => write!(f, "Comment"),
+            TokenType::Attribute => write!(f, "Attribute"),
         }
     }
 }
@@ -48,35 +57,38 @@ pub struct Token {
     pub token_type: TokenType,
     pub content: String,
     pub line: usize,
+    pub column: usize,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, content: String, line: usize) -> Token {
+    pub fn new(token_type: TokenType, content: String, line: usize, column: usize) -> Token {
         Token {
             token_type,
             content,
             line,
+            column,
         }
     }
 }
 
 pub struct TokensList {
-    tokens: Peekable<IntoIter<Token>>,
+    tokens: IntoIter<Token>,
 }
 
 impl TokensList {
     pub fn from(tokens: Vec<Token>) -> TokensList {
         TokensList {
-            tokens: tokens.into_iter().peekable(),
+            tokens: tokens.into_iter(),
         }
     }
 
-    pub fn peek(&mut self) -> Option<&Token> {
-        self.tokens.peek()
+    pub fn peek(&self) -> Option<&Token> {
+        self.tokens.as_slice().first()
     }
 
-    pub fn next(&mut self) -> Option<Token> {
-        self.tokens.next()
+    // The token after the next one
+    pub fn peek_second(&self) -> Option<&Token> {
+        self.tokens.as_slice().get(1)
     }
 
     pub fn consume(&mut self) -> Token {
@@ -91,7 +103,7 @@ impl TokensList {
         let token = self.consume();
 
         if token.token_type == expected {
-            return token;
+            token
         } else {
             throw_at(
                 &format!("Unexpected token {}, expected {expected:?}", token.content),
@@ -0,0 +1,434 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use crate::bigint::BigInt;
+use crate::bytecode::{Builtin, Instr, Program};
+use crate::error::{throw, throw_at_in, throw_in};
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    Float(f64),
+    Char(char),
+    Bool(bool),
+    List(List),
+    Tuple(Rc<Vec<Value>>),
+    Thunk(Rc<RefCell<Thunk>>),
+}
+
+pub type List = Option<Rc<ListNode>>;
+
+#[derive(Debug)]
+pub struct ListNode {
+    pub head: Value,
+    pub tail: List,
+}
+
+// Dropping a long list recursively would overflow the stack
+impl Drop for ListNode {
+    fn drop(&mut self) {
+        let mut tail = self.tail.take();
+        while let Some(node) = tail {
+            match Rc::try_unwrap(node) {
+                Ok(mut node) => tail = node.tail.take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+type Slots = Rc<RefCell<Vec<Value>>>;
+
+// Thunks only live in the frame they were created in, so they hold a weak
+// reference to it to avoid a cycle
+#[derive(Debug)]
+pub enum Thunk {
+    Pending(usize, Weak<RefCell<Vec<Value>>>),
+    Running,
+    Done(Value),
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::BigInt(a), Value::BigInt(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::List(a), Value::List(b)) => {
+                let (mut a, mut b) = (a, b);
+                loop {
+                    match (a, b) {
+                        (None, None) => return true,
+                        (Some(x), Some(y)) => {
+                            if x.head != y.head {
+                                return false;
+                            }
+                            a = &x.tail;
+                            b = &y.tail;
+                        }
+                        _ => return false,
+                    }
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{v}"),
+            Value::BigInt(v) => write!(f, "{v}"),
+            Value::Float(v) => write!(f, "{}", format_float(*v)),
+            Value::Char(v) => write!(f, "{v}"),
+            Value::Bool(v) => write!(f, "{v}"),
+            Value::List(list) => {
+                write!(f, "[")?;
+                let mut node = list;
+                while let Some(n) = node {
+                    write!(f, "{}", n.head)?;
+                    if n.tail.is_some() {
+                        write!(f, ", ")?;
+                    }
+                    node = &n.tail;
+                }
+                write!(f, "]")
+            }
+            Value::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "({})", items.join(", "))
+            }
+            Value::Thunk(_) => write!(f, "<thunk>"),
+        }
+    }
+}
+
+// The fewest digits which read back as the same number, in scientific
+// notation only for very large and small numbers, like the C runtime prints
+fn format_float(v: f64) -> String {
+    if v.is_nan() {
+        return String::from("NaN");
+    } else if v.is_infinite() {
+        return String::from(if v > 0.0 { "inf" } else { "-inf" });
+    }
+
+    let scientific = format!("{v:e}");
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let digits = mantissa.chars().filter(char::is_ascii_digit).count() as i32;
+
+    if !(-4..16).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{mantissa}e{sign}{:02}", exponent.abs())
+    } else if digits - 1 - exponent > 0 {
+        format!("{v:.*}", (digits - 1 - exponent) as usize)
+    } else {
+        format!("{v:.0}.0")
+    }
+}
+
+impl Value {
+    // The text of a list of chars, which Flip uses as strings
+    pub fn as_string(&self) -> Option<String> {
+        let Value::List(list) = self else {
+            return None;
+        };
+
+        let mut text = String::new();
+        let mut node = list;
+        while let Some(n) = node {
+            let Value::Char(c) = n.head else {
+                return None;
+            };
+            text.push(c);
+            node = &n.tail;
+        }
+        Some(text)
+    }
+}
+
+struct Frame {
+    chunk: usize,
+    pc: usize,
+    slots: Slots,
+    // Height of the stack when the frame was entered
+    base: usize,
+    // The thunk to update with the result, if the frame computes a let-binding
+    thunk: Option<Rc<RefCell<Thunk>>>,
+}
+
+pub fn run(program: &Program) -> Value {
+    if let Some(main) = program.main {
+        call(program, main, Vec::new())
+    } else {
+        throw("Expected a \"main\" function")
+    }
+}
+
+// Runs a function chunk to completion
+pub fn call(program: &Program, chunk: usize, args: Vec<Value>) -> Value {
+    let mut stack: Vec<Value> = Vec::new();
+    let mut frames = vec![Frame {
+        chunk,
+        pc: 0,
+        slots: Rc::new(RefCell::new(args)),
+        base: 0,
+        thunk: None,
+    }];
+
+    loop {
+        let frame = frames.last_mut().unwrap();
+        let chunk = &program.chunks[frame.chunk];
+        let instr = chunk.code[frame.pc];
+        frame.pc += 1;
+
+        match instr {
+            Instr::Int(v) => stack.push(Value::Int(v)),
+            Instr::BigInt(index) => stack.push(Value::BigInt(program.big_ints[index].clone())),
+            Instr::Float(v) => stack.push(Value::Float(v)),
+            Instr::Char(v) => stack.push(Value::Char(v)),
+            Instr::String(index) => {
+                let list = program.strings[index].chars().rev().fold(None, |tail, c| Some(Rc::new(ListNode { head: Value::Char(c), tail })));
+                stack.push(Value::List(list));
+            }
+            Instr::Bool(v) => stack.push(Value::Bool(v)),
+            Instr::EmptyList => stack.push(Value::List(None)),
+            Instr::Tuple(size) => {
+                let items = stack.split_off(stack.len() - size);
+                stack.push(Value::Tuple(Rc::new(items)));
+            }
+            Instr::Index(index) => match stack.pop().unwrap() {
+                Value::Tuple(items) => stack.push(items[index].clone()),
+                value => throw_in(&format!("cannot take element {index} of {value}"), &chunk.name),
+            },
+            Instr::With(index) => {
+                let value = stack.pop().unwrap();
+                match stack.pop().unwrap() {
+                    Value::Tuple(items) => {
+                        let mut items = items.as_ref().clone();
+                        items[index] = value;
+                        stack.push(Value::Tuple(Rc::new(items)));
+                    }
+                    record => throw_in(&format!("cannot change element {index} of {record}"), &chunk.name),
+                }
+            }
+            Instr::Load(slot) => stack.push(frame.slots.borrow()[slot].clone()),
+            Instr::Store(slot) => {
+                let value = stack.pop().unwrap();
+                let mut slots = frame.slots.borrow_mut();
+                if slot < slots.len() {
+                    slots[slot] = value;
+                } else {
+                    slots.push(value);
+                }
+            }
+            Instr::Thunk(thunk_chunk) => {
+                let thunk = Thunk::Pending(thunk_chunk, Rc::downgrade(&frame.slots));
+                stack.push(Value::Thunk(Rc::new(RefCell::new(thunk))));
+            }
+            Instr::Force => match stack.pop().unwrap() {
+                Value::Thunk(thunk) => {
+                    let state = mem::replace(&mut *thunk.borrow_mut(), Thunk::Running);
+                    match state {
+                        Thunk::Done(value) => {
+                            *thunk.borrow_mut() = Thunk::Done(value.clone());
+                            stack.push(value);
+                        }
+                        Thunk::Pending(thunk_chunk, slots) => {
+                            let slots = slots.upgrade().unwrap();
+                            let base = stack.len();
+                            frames.push(Frame { chunk: thunk_chunk, pc: 0, slots, base, thunk: Some(thunk) });
+                        }
+                        Thunk::Running => throw_in("let-binding depends on itself", &chunk.name),
+                    }
+                }
+                // Only let-bindings are lazy, anything else is already a value
+                value => stack.push(value),
+            },
+            Instr::Call(callee, arg_count) => {
+                let args = stack.split_off(stack.len() - arg_count);
+                let base = stack.len();
+                frames.push(Frame {
+                    chunk: callee,
+                    pc: 0,
+                    slots: Rc::new(RefCell::new(args)),
+                    base,
+                    thunk: None,
+                });
+            }
+            Instr::Builtin(builtin) => {
+                let args = stack.split_off(stack.len() - builtin.arity());
+                stack.push(apply(builtin, args, &chunk.name));
+            }
+            Instr::Checked(builtin, error) => {
+                let args = stack.split_off(stack.len() - builtin.arity());
+                stack.push(apply_checked(builtin, args, &program.errors[error], &chunk.name));
+            }
+            Instr::Jump(target) => frame.pc = target,
+            Instr::JumpIfFalse(target) => {
+                if let Some(Value::Bool(false)) = stack.pop() {
+                    frame.pc = target;
+                }
+            }
+            Instr::Return => {
+                let value = stack.pop().unwrap();
+                let frame = frames.pop().unwrap();
+                stack.truncate(frame.base);
+
+                if let Some(thunk) = frame.thunk {
+                    *thunk.borrow_mut() = Thunk::Done(value.clone());
+                }
+
+                if frames.is_empty() {
+                    return value;
+                }
+                stack.push(value);
+            }
+        }
+    }
+}
+
+fn apply_checked(builtin: Builtin, args: Vec<Value>, error: &(String, Option<usize>), function: &str) -> Value {
+    let result = match (builtin, args.as_slice()) {
+        (Builtin::Add, [Value::Int(a), Value::Int(b)]) => a.checked_add(*b),
+        (Builtin::Sub, [Value::Int(a), Value::Int(b)]) => a.checked_sub(*b),
+        (Builtin::Mul, [Value::Int(a), Value::Int(b)]) => a.checked_mul(*b),
+        (Builtin::Neg, [Value::Int(a)]) => a.checked_neg(),
+        _ => return apply(builtin, args, function),
+    };
+
+    match result {
+        Some(v) => Value::Int(v),
+        None => match error {
+            (error, Some(line)) => throw_at_in(error, *line, function),
+            (error, None) => throw_in(error, function),
+        },
+    }
+}
+
+fn apply(builtin: Builtin, args: Vec<Value>, function: &str) -> Value {
+    let mut args = args.into_iter();
+    let a = args.next().unwrap();
+    let b = args.next();
+
+    match (builtin, a, b) {
+        (Builtin::Add, Value::Int(a), Some(Value::Int(b))) => Value::Int(a.wrapping_add(b)),
+        (Builtin::Sub, Value::Int(a), Some(Value::Int(b))) => Value::Int(a.wrapping_sub(b)),
+        (Builtin::Mul, Value::Int(a), Some(Value::Int(b))) => Value::Int(a.wrapping_mul(b)),
+        (Builtin::Div, Value::Int(_), Some(Value::Int(0))) => throw_in("division by zero", function),
+        (Builtin::Div, Value::Int(a), Some(Value::Int(b))) => Value::Int(a.wrapping_div(b)),
+        (Builtin::Mod, Value::Int(_), Some(Value::Int(0))) => throw_in("modulo by zero", function),
+        (Builtin::Mod, Value::Int(a), Some(Value::Int(b))) => Value::Int(a.wrapping_rem(b)),
+        (Builtin::Neg, Value::Int(a), None) => Value::Int(a.wrapping_neg()),
+        (Builtin::Not, Value::Bool(a), None) => Value::Bool(!a),
+
+        (Builtin::Add, Value::BigInt(a), Some(Value::BigInt(b))) => Value::BigInt(Rc::new(&*a + &*b)),
+        (Builtin::Sub, Value::BigInt(a), Some(Value::BigInt(b))) => Value::BigInt(Rc::new(&*a - &*b)),
+        (Builtin::Mul, Value::BigInt(a), Some(Value::BigInt(b))) => Value::BigInt(Rc::new(&*a * &*b)),
+        (Builtin::Div, Value::BigInt(a), Some(Value::BigInt(b))) => match a.div_rem(&b) {
+            Some((quotient, _)) => Value::BigInt(Rc::new(quotient)),
+            None => throw_in("division by zero", function),
+        },
+        (Builtin::Mod, Value::BigInt(a), Some(Value::BigInt(b))) => match a.div_rem(&b) {
+            Some((_, remainder)) => Value::BigInt(Rc::new(remainder)),
+            None => throw_in("modulo by zero", function),
+        },
+        (Builtin::Neg, Value::BigInt(a), None) => Value::BigInt(Rc::new(-&*a)),
+
+        (Builtin::Add, Value::Float(a), Some(Value::Float(b))) => Value::Float(a + b),
+        (Builtin::Sub, Value::Float(a), Some(Value::Float(b))) => Value::Float(a - b),
+        (Builtin::Mul, Value::Float(a), Some(Value::Float(b))) => Value::Float(a * b),
+        (Builtin::Div, Value::Float(a), Some(Value::Float(b))) => Value::Float(a / b),
+        (Builtin::Neg, Value::Float(a), None) => Value::Float(-a),
+        (Builtin::ToFloat, Value::Int(a), None) => Value::Float(a as f64),
+        (Builtin::Floor, Value::Float(a), None) => Value::Int(float_to_int(a.floor(), function)),
+        (Builtin::Round, Value::Float(a), None) => Value::Int(float_to_int(a.round(), function)),
+        (Builtin::Sqrt, Value::Float(a), None) => Value::Float(a.sqrt()),
+
+        (Builtin::ToChar, Value::Int(a), None) => match u32::try_from(a).ok().and_then(char::from_u32) {
+            Some(c) => Value::Char(c),
+            None => throw_in("Int is not a valid Char", function),
+        },
+        (Builtin::ToInt, Value::Char(a), None) => Value::Int(a as i64),
+        (Builtin::IsDigit, Value::Char(a), None) => Value::Bool(a.is_ascii_digit()),
+        (Builtin::IsAlpha, Value::Char(a), None) => Value::Bool(a.is_ascii_alphabetic()),
+
+        (Builtin::ToBigInt, Value::Int(a), None) => Value::BigInt(Rc::new(BigInt::from_i64(a))),
+        (Builtin::ToInt, Value::BigInt(a), None) => match a.to_i64() {
+            Some(v) => Value::Int(v),
+            None => throw_in("BigInt is too large for an Int", function),
+        },
+
+        (Builtin::Eq, a, Some(b)) => Value::Bool(a == b),
+        (Builtin::Ne, a, Some(b)) => Value::Bool(a != b),
+        (Builtin::Gt, Value::Int(a), Some(Value::Int(b))) => Value::Bool(a > b),
+        (Builtin::Lt, Value::Int(a), Some(Value::Int(b))) => Value::Bool(a < b),
+        (Builtin::Ge, Value::Int(a), Some(Value::Int(b))) => Value::Bool(a >= b),
+        (Builtin::Le, Value::Int(a), Some(Value::Int(b))) => Value::Bool(a <= b),
+        (Builtin::Gt, Value::Char(a), Some(Value::Char(b))) => Value::Bool(a > b),
+        (Builtin::Lt, Value::Char(a), Some(Value::Char(b))) => Value::Bool(a < b),
+        (Builtin::Ge, Value::Char(a), Some(Value::Char(b))) => Value::Bool(a >= b),
+        (Builtin::Le, Value::Char(a), Some(Value::Char(b))) => Value::Bool(a <= b),
+        (Builtin::Gt, Value::Float(a), Some(Value::Float(b))) => Value::Bool(a > b),
+        (Builtin::Lt, Value::Float(a), Some(Value::Float(b))) => Value::Bool(a < b),
+        (Builtin::Ge, Value::Float(a), Some(Value::Float(b))) => Value::Bool(a >= b),
+        (Builtin::Le, Value::Float(a), Some(Value::Float(b))) => Value::Bool(a <= b),
+        (Builtin::Gt, Value::BigInt(a), Some(Value::BigInt(b))) => Value::Bool(a > b),
+        (Builtin::Lt, Value::BigInt(a), Some(Value::BigInt(b))) => Value::Bool(a < b),
+        (Builtin::Ge, Value::BigInt(a), Some(Value::BigInt(b))) => Value::Bool(a >= b),
+        (Builtin::Le, Value::BigInt(a), Some(Value::BigInt(b))) => Value::Bool(a <= b),
+        (Builtin::Gt, Value::Bool(a), Some(Value::Bool(b))) => Value::Bool(a & !b),
+        (Builtin::Lt, Value::Bool(a), Some(Value::Bool(b))) => Value::Bool(!a & b),
+        (Builtin::Ge, Value::Bool(a), Some(Value::Bool(b))) => Value::Bool(a >= b),
+        (Builtin::Le, Value::Bool(a), Some(Value::Bool(b))) => Value::Bool(a <= b),
+
+        (Builtin::Len, Value::List(list), None) => {
+            let mut length = 0;
+            let mut node = &list;
+            while let Some(n) = node {
+                length += 1;
+                node = &n.tail;
+            }
+            Value::Int(length)
+        }
+        (Builtin::Head, Value::List(list), None) => match list {
+            Some(node) => node.head.clone(),
+            None => throw_in("head of an empty list", function),
+        },
+        (Builtin::Tail, Value::List(list), None) => match list {
+            Some(node) => Value::List(node.tail.clone()),
+            None => throw_in("tail of an empty list", function),
+        },
+        (Builtin::Push, Value::List(tail), Some(head)) => Value::List(Some(Rc::new(ListNode { head, tail }))),
+        (Builtin::IsNull, Value::List(list), None) => Value::Bool(list.is_none()),
+
+        (Builtin::Assert, Value::Bool(true), None) => Value::Bool(true),
+        (Builtin::Assert, Value::Bool(false), None) => throw_in("assertion failed", function),
+        (Builtin::AssertEq, a, Some(b)) => {
+            if a != b {
+                throw_in(&format!("assert_eq failed: {a} != {b}"), function);
+            }
+            Value::Bool(true)
+        }
+
+        // Generic built-ins like ">" type check for values they don't support
+        (builtin, a, Some(b)) => throw_in(&format!("cannot apply {} to {a} and {b}", builtin.name()), function),
+        (builtin, a, None) => throw_in(&format!("cannot apply {} to {a}", builtin.name()), function),
+    }
+}
+
+fn float_to_int(v: f64, function: &str) -> i64 {
+    // Also false for NaN
+    if (-9223372036854775808.0..9223372036854775808.0).contains(&v) {
+        v as i64
+    } else {
+        throw_in("Float does not fit in an Int", function)
+    }
+}
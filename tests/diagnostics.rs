@@ -0,0 +1,131 @@
+use flip::error::{self, Error};
+use flip::{compiler, parser, scanner};
+
+fn parse_error(code: &str) -> Error {
+    let mut symbols = compiler::table_from_inlines();
+    error::catch(|| parser::build_ast(scanner::get_tokens(code), &mut symbols)).unwrap_err()
+}
+
+#[test]
+fn overload_errors_list_every_candidate() {
+    let error = parse_error(
+        "main() {
+            -(true, 1)
+        }",
+    );
+
+    assert_eq!(error.line, Some(2));
+    assert_eq!(
+        error.message,
+        "No overload of \"-\" matches the arguments (Bool, Int)
+    -(Int, Int): Int           argument 1 is Bool, expected Int
+    -(Int): Int                takes 1 argument, got 2
+    -(BigInt, BigInt): BigInt  argument 1 is Bool, expected BigInt
+    -(BigInt): BigInt          takes 1 argument, got 2
+    -(Float, Float): Float     argument 1 is Bool, expected Float
+    -(Float): Float            takes 1 argument, got 2"
+    );
+}
+
+#[test]
+fn overload_errors_name_bound_generics() {
+    let error = parse_error(
+        "main() {
+            len(push([Int](), true))
+        }",
+    );
+
+    assert_eq!(
+        error.message,
+        "No overload of \"push\" matches the arguments ([Int], Bool)
+    push([T], T): [T]  argument 2 is Bool, expected T, where T is Int"
+    );
+}
+
+#[test]
+fn user_function_overloads_are_listed() {
+    let error = parse_error(
+        "double(x: Int): Int {
+            *(x, 2)
+        }
+
+        double(xs: [Int]): [Int] {
+            xs
+        }
+
+        main() {
+            double(true)
+        }",
+    );
+
+    assert_eq!(
+        error.message,
+        "No overload of \"double\" matches the arguments (Bool)
+    double(Int): Int      argument 1 is Bool, expected Int
+    double([Int]): [Int]  argument 1 is Bool, expected [Int]"
+    );
+}
+
+#[test]
+fn tuple_errors_name_the_tuple_type() {
+    let error = parse_error(
+        "main() {
+            =(pair, (1, true))
+            pair.2
+        }",
+    );
+
+    assert_eq!(error.line, Some(3));
+    assert_eq!(error.message, "(Int, Bool) has no element 2");
+
+    let error = parse_error(
+        "main() {
+            =((a, b, c), (1, true))
+            a
+        }",
+    );
+
+    assert_eq!(error.line, Some(2));
+    assert_eq!(error.message, "Can not destructure (Int, Bool) into (a, b, c)");
+}
+
+#[test]
+fn record_errors_name_the_field() {
+    let declaration = "struct Point { x: Int, y: Int }\n";
+    let cases = [
+        ("main() {\n Point { x: 1 }.x\n}", 3, "Point is missing the field y"),
+        ("main() {\n Point { x: 1, y: true }.x\n}", 3, "Field y of Point is Int, got Bool"),
+        ("main() {\n Point { x: 1, y: 2, x: 3 }.x\n}", 3, "Field x of Point is given twice"),
+        ("main() {\n =(p, Point { x: 1, y: 2 })\n p.z\n}", 4, "Point has no field z"),
+        ("main() {\n =(p, Point { x: 1, y: 2 })\n p with { z: 3 }.x\n}", 4, "Point has no field z"),
+        ("main() {\n (1, 2) with { x: 3 }.0\n}", 3, "(Int, Int) is not a struct, so it can not be updated"),
+        ("struct Line { from: Point, to: Pt }\nmain() {\n 1\n}", 2, "Unknown type Pt for field to of Line"),
+        ("struct Point { z: Int }\nmain() {\n 1\n}", 2, "Struct Point is already defined"),
+    ];
+
+    for (code, line, message) in cases {
+        let error = parse_error(&format!("{declaration}{code}"));
+        assert_eq!((error.line, error.message.as_str()), (Some(line), message), "{code}");
+    }
+}
+
+// Lists, tuples and records have no order every back end agrees on
+#[test]
+fn only_scalars_can_be_ordered() {
+    for (compared, shown) in [
+        ("(1, 2), (1, 3)", "((Int, Int), (Int, Int))"),
+        ("P { x: 1 }, P { x: 2 }", "(P, P)"),
+        ("push([Int](), 1), [Int]()", "([Int], [Int])"),
+    ] {
+        let error = parse_error(&format!(
+            "struct P {{ x: Int }}
+
+            main() {{
+                if(<({compared}), 1, 0)
+            }}"
+        ));
+
+        assert_eq!(error.line, Some(4));
+        assert!(error.message.starts_with(&format!("No overload of \"<\" matches the arguments {shown}\n")), "{}", error.message);
+    }
+}
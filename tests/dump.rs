@@ -0,0 +1,37 @@
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+const FLIP: &str = env!("CARGO_BIN_EXE_flip");
+
+#[test]
+fn ast_is_dumped_before_the_passes() {
+    let path = env::temp_dir().join(format!("flip-dump-{}.flip", process::id()));
+    fs::write(
+        &path,
+        "twice(n: Int): Int {
+            *(n, 2)
+        }
+
+        main() {
+            twice(3)
+        }",
+    )
+    .unwrap();
+
+    let output = Command::new(FLIP).args(["dump", "ast", "--checked"]).arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Fn twice(n: Int): Int
+  Call * : Int
+    Var n : Int
+    Int 2
+Fn main(): None
+  Call twice : Fn(Int)
+    Int 3
+"
+    );
+}
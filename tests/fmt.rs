@@ -0,0 +1,146 @@
+use std::fs;
+
+use flip::ast::ASTNode;
+use flip::{compiler, formatter, parser, scanner};
+
+fn ast(code: &str) -> Vec<ASTNode> {
+    let mut symbols = compiler::table_from_inlines();
+    parser::build_ast(scanner::get_tokens(code), &mut symbols)
+}
+
+fn examples() -> Vec<(String, String)> {
+    let mut examples = Vec::new();
+
+    for entry in fs::read_dir("test").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "flip") {
+            examples.push((path.display().to_string(), fs::read_to_string(&path).unwrap()));
+        }
+    }
+
+    assert!(!examples.is_empty());
+    examples
+}
+
+#[test]
+fn examples_are_formatted() {
+    for (path, code) in examples() {
+        assert_eq!(formatter::format(&code), code, "{path} is not formatted");
+    }
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    for (path, code) in examples() {
+        // Squash each example onto as few lines as possible first
+        let squashed: String = code
+            .lines()
+            .map(|line| if line.trim_start().starts_with("//") { format!("\n{line}\n") } else { line.to_string() })
+            .collect();
+
+        let once = formatter::format(&squashed);
+        assert_eq!(formatter::format(&once), once, "formatting {path} twice changed it");
+        assert_eq!(ast(&once), ast(&code), "formatting {path} changed its meaning");
+    }
+}
+
+#[test]
+fn comments_are_kept() {
+    let code = "\
+// About f
+
+f(x: Int,y:Int):Int{ // opens
+  =(a, +(x,y)) // a
+
+
+  // Before the if
+  if(>(a, 10), // cond
+     a,
+     // else
+     *(a, 2)
+     // dangling
+  )
+}
+main(){f(1,2)}
+// The end
+";
+
+    let expected = "\
+// About f
+
+f(x: Int, y: Int): Int { // opens
+    =(a, +(x, y)) // a
+
+    // Before the if
+    if(>(a, 10), // cond
+        a,
+        // else
+        *(a, 2)
+        // dangling
+    )
+}
+
+main() {
+    f(1, 2)
+}
+
+// The end
+";
+
+    assert_eq!(formatter::format(code), expected);
+    assert_eq!(formatter::format(expected), expected);
+}
+
+#[test]
+fn tuples_are_spaced_like_calls() {
+    let code = "\
+first(pairs:[(Int,[Bool])]):(Int,[Bool]){head(pairs)}
+main(){=((a,b),((1,'x'),true))a.0}
+";
+
+    let expected = "\
+first(pairs: [(Int, [Bool])]): (Int, [Bool]) {
+    head(pairs)
+}
+
+main() {
+    =((a, b), ((1, 'x'), true))
+    a.0
+}
+";
+
+    assert_eq!(formatter::format(code), expected);
+}
+
+#[test]
+fn records_are_split_one_field_per_line() {
+    let code = "\
+struct Point{x:Int,// across
+y:Int}
+struct Tag{name:[Char]}
+main(){=(p,Point{x:1,y:2})=(far,p with{x:+(p.x,1000000000000000000),y:+(p.y,2000000000000000000)}.x)Tag{name:\"point\"}.name}
+";
+
+    let expected = "\
+struct Point {
+    x: Int, // across
+    y: Int
+}
+
+struct Tag { name: [Char] }
+
+main() {
+    =(p, Point { x: 1, y: 2 })
+    =(far,
+        p with {
+            x: +(p.x, 1000000000000000000),
+            y: +(p.y, 2000000000000000000)
+        }.x
+    )
+    Tag { name: \"point\" }.name
+}
+";
+
+    assert_eq!(formatter::format(code), expected);
+    assert_eq!(ast(&formatter::format(code)), ast(code));
+}
@@ -0,0 +1,125 @@
+// Runs every program in test/ and compares what it prints with the sidecar
+// file next to it, e.g. test/e1.flip and test/e1.out. Each program is built
+// with the C back end, run with the bytecode VM and built with the Rust back
+// end and rustc, and all three must match.
+//
+//     cargo test --test golden              Check every program
+//     cargo test --test golden -- --bless   Write the output of the C build
+//                                           to the sidecar files instead
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use flip::ast::NodeType;
+use flip::{compiler, parser, scanner};
+
+const FLIP: &str = env!("CARGO_BIN_EXE_flip");
+
+fn main() {
+    let bless = env::args().any(|arg| arg == "--bless");
+    let build_dir = env::temp_dir().join(format!("flip-golden-{}", process::id()));
+    fs::create_dir_all(&build_dir).unwrap();
+
+    let mut programs: Vec<PathBuf> = fs::read_dir("test")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "flip"))
+        .collect();
+    programs.sort();
+
+    let width = programs.iter().map(|p| p.display().to_string().len()).max().unwrap_or(0);
+    let mut failures = 0;
+
+    for program in programs.iter() {
+        let expected_path = program.with_extension("out");
+
+        let results = [
+            ("c", run_c(program, &build_dir)),
+            ("vm", run_vm(program)),
+            ("rust", run_rust(program, &build_dir)),
+        ];
+
+        if bless {
+            if let Ok(output) = &results[0].1 {
+                fs::write(&expected_path, output).unwrap();
+            }
+        }
+
+        let expected = fs::read_to_string(&expected_path).map_err(|_| {
+            format!("no expected output in {}, run with --bless", expected_path.display())
+        });
+
+        for (back_end, result) in results.iter() {
+            let name = format!("{}:", program.display());
+            let outcome = match (&expected, &result) {
+                (Err(e), _) | (_, Err(e)) => Err(e.clone()),
+                (Ok(expected), Ok(actual)) if expected == actual => Ok(()),
+                (Ok(expected), Ok(actual)) => Err(format!("expected {expected:?}, got {actual:?}")),
+            };
+
+            match outcome {
+                Ok(()) => println!("{name:width$} {back_end:4} ok", width = width + 1),
+                Err(e) => {
+                    failures += 1;
+                    println!("{name:width$} {back_end:4} FAILED\n    {e}", width = width + 1);
+                }
+            }
+        }
+    }
+
+    fs::remove_dir_all(&build_dir).unwrap();
+
+    if failures > 0 {
+        println!("\n{failures} failed");
+        process::exit(1);
+    }
+}
+
+fn run_c(program: &Path, build_dir: &Path) -> Result<String, String> {
+    let exe = build_dir.join(program.file_stem().unwrap());
+    output(Command::new(FLIP).arg("build").arg(program).arg("-o").arg(&exe))?;
+    output(&mut Command::new(&exe))
+}
+
+fn run_vm(program: &Path) -> Result<String, String> {
+    output(Command::new(FLIP).arg("run").arg(program))
+}
+
+// The Rust back end writes a module, so the program is built with a main
+// printing the result of the Flip "main" the way the C runtime does
+fn run_rust(program: &Path, build_dir: &Path) -> Result<String, String> {
+    let dir = build_dir.join(format!("{}-rust", program.file_stem().unwrap().to_string_lossy()));
+    fs::create_dir_all(&dir).unwrap();
+    output(Command::new(FLIP).args(["build", "--target", "rust"]).arg(program).arg("-o").arg(dir.join("gen.rs")))?;
+
+    let mut symbols = compiler::table_from_inlines();
+    let tree = parser::build_ast(scanner::get_tokens(&fs::read_to_string(program).unwrap()), &mut symbols);
+    let print_main = match compiler::main_type(&tree, &symbols) {
+        // Debug is the shortest text which reads back as the same number,
+        // like float_print for all but very large and small numbers
+        Some(NodeType::Float) => "println!(\"{:?}\", gen::main());",
+        Some(NodeType::Bool) => "println!(\"{}\", gen::main() as i64);",
+        Some(NodeType::List(item)) if *item == NodeType::Char => {
+            "println!(\"{}\", gen::main().iter().collect::<String>());"
+        }
+        _ => "println!(\"{}\", gen::main());",
+    };
+    fs::write(dir.join("main.rs"), format!("mod gen;\n\nfn main() {{\n    {print_main}\n}}\n")).unwrap();
+
+    let exe = dir.join("main");
+    output(Command::new("rustc").args(["--edition", "2015", "-O", "-A", "warnings"]).arg(dir.join("main.rs")).arg("-o").arg(&exe))?;
+    output(&mut Command::new(&exe))
+}
+
+// What a command prints, if it succeeds
+fn output(command: &mut Command) -> Result<String, String> {
+    let output = command.output().map_err(|e| format!("could not run {command:?}: {e}"))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(format!("{command:?} failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
@@ -0,0 +1,88 @@
+use flip::error;
+use flip::inline::{Inline, DEFAULT_THRESHOLD};
+use flip::passes::{Pass, PassManager};
+use flip::{bytecode, compiler, parser, scanner, vm};
+
+// What the VM prints for "main", or the error it stops with
+fn run(code: &str, pass: impl Pass + 'static) -> String {
+    let mut symbols = compiler::table_from_inlines();
+    let tree = parser::build_ast(scanner::get_tokens(code), &mut symbols);
+    let tree = PassManager::new().with(pass).run(tree, &symbols);
+    let program = bytecode::compile_program(&tree, &symbols);
+
+    match error::catch(|| vm::run(&program).to_string()) {
+        Ok(value) => value,
+        Err(error) => error.to_string(),
+    }
+}
+
+fn inlined(code: &str) -> String {
+    run(code, Inline { threshold: DEFAULT_THRESHOLD })
+}
+
+fn not_inlined(code: &str) -> String {
+    run(code, Inline { threshold: 0 })
+}
+
+#[test]
+fn arguments_which_can_fail_are_still_evaluated() {
+    let programs = [
+        // Not used
+        "zero(x: Int): Int {
+            0
+        }
+
+        main(): Int {
+            zero(head([Int]()))
+        }",
+        // Used in one branch
+        "pick(c: Bool, x: Int): Int {
+            if(c, x, 1)
+        }
+
+        main(): Int {
+            pick(false, head([Int]()))
+        }",
+        // Used after a short-circuit
+        "both(a: Bool, b: Bool): Bool {
+            and(a, b)
+        }
+
+        main(): Int {
+            if(both(false, ==(head([Int]()), 1)), 1, 0)
+        }",
+        // A let-binding, which is only forced when the call happens
+        "zero(x: Int): Int {
+            0
+        }
+
+        main(): Int {
+            =(x, head([Int]()))
+            zero(x)
+        }",
+    ];
+
+    for code in programs {
+        assert_eq!(not_inlined(code), "Error in main: head of an empty list", "{code}");
+        assert_eq!(inlined(code), not_inlined(code), "{code}");
+    }
+}
+
+#[test]
+fn inlining_keeps_results() {
+    let code = "pick(c: Bool, x: Int, y: Int): Int {
+        if(c, x, y)
+    }
+
+    twice(x: Int): Int {
+        *(x, 2)
+    }
+
+    main(): Int {
+        =(n, 4)
+        +(pick(true, n, 2), twice(+(n, 1)))
+    }";
+
+    assert_eq!(not_inlined(code), "14");
+    assert_eq!(inlined(code), not_inlined(code));
+}
@@ -0,0 +1,183 @@
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+use flip::lint::{self, Warning};
+use flip::{compiler, formatter, parser, scanner};
+
+const FLIP: &str = env!("CARGO_BIN_EXE_flip");
+
+fn check(code: &str) -> Vec<Warning> {
+    let mut symbols = compiler::table_from_inlines();
+    let program = parser::build_program(scanner::get_tokens(code), &mut symbols);
+    lint::check(&program, &symbols)
+}
+
+fn lints(warnings: &[Warning]) -> Vec<(&str, usize)> {
+    warnings.iter().map(|w| (w.lint, w.line)).collect()
+}
+
+#[test]
+fn every_lint_is_reported() {
+    let warnings = check(
+        "twice(x: Int, y: Int): Int {
+            =(a, *(x, 2))
+            =(b, 3)
+            if(==(x, x), a, a)
+        }
+
+        never(): Int {
+            5
+        }
+
+        main() {
+            twice(2, 3)
+        }",
+    );
+
+    assert_eq!(
+        lints(&warnings),
+        [
+            ("unused_param", 1),
+            ("unused_binding", 3),
+            ("identical_branches", 4),
+            ("self_comparison", 4),
+            ("unused_function", 7),
+        ]
+    );
+    assert_eq!(warnings[3].message, "\"==(x, x)\" compares a value with itself, so it is always true");
+}
+
+#[test]
+fn shadowed_bindings_are_unused() {
+    let warnings = check(
+        "main() {
+            =(x, 1)
+            =(x, 2)
+            =(y, 3)
+            =(y, +(y, 1))
+            +(x, y)
+        }",
+    );
+
+    assert_eq!(lints(&warnings), [("unused_binding", 2)]);
+}
+
+#[test]
+fn functions_called_from_tests_are_used() {
+    let warnings = check(
+        "helper(): Int {
+            1
+        }
+
+        main() {
+            0
+        }
+
+        test \"helper\" {
+            assert_eq(helper(), 1)
+        }",
+    );
+
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn lints_can_be_allowed() {
+    let code = "\
+#[allow(unused_param, identical_branches)]
+ignore(x: Int): Int {
+    if(true,
+        1,
+        1
+    )
+}
+
+main() {
+    ignore(1)
+}
+";
+
+    assert!(check(code).is_empty());
+    assert_eq!(formatter::format(code), code);
+}
+
+// NaN is not equal to itself
+#[test]
+fn floats_compared_with_themselves_are_allowed() {
+    let warnings = check(
+        "main() {
+            =(x, sqrt(-(1.0)))
+            if(==(x, x), 1, 0)
+        }",
+    );
+
+    assert!(warnings.is_empty());
+}
+
+#[test]
+fn values_holding_floats_compared_with_themselves_are_allowed() {
+    let warnings = check(
+        "struct Reading { id: Int, value: Float }
+
+        main() {
+            =(r, Reading { id: 1, value: sqrt(-(1.0)) })
+            =(a, if(==((1, sqrt(-(1.0))), (1, sqrt(-(1.0)))), 1, 0))
+            =(b, if(==(r, r), 1, 0))
+            =(xs, push([Float](), sqrt(-(1.0))))
+            =(c, if(==(xs, xs), 1, 0))
+            +(a, +(b, c))
+        }",
+    );
+
+    assert!(warnings.is_empty());
+}
+
+// What "main" prints when built with C and when run by the VM
+fn outputs(name: &str, code: &str) -> (String, String) {
+    let dir = env::temp_dir().join(format!("flip-lint-{}-{name}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.flip");
+    let exe = dir.join("main");
+    fs::write(&source, code).unwrap();
+
+    let build = Command::new(FLIP).arg("build").arg(&source).arg("-o").arg(&exe).output().unwrap();
+    assert!(build.status.success(), "{}", String::from_utf8_lossy(&build.stderr));
+    let c = Command::new(&exe).output().unwrap();
+    let vm = Command::new(FLIP).arg("run").arg(&source).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    (String::from_utf8_lossy(&c.stdout).into_owned(), String::from_utf8_lossy(&vm.stdout).into_owned())
+}
+
+// The lint is only right if both back ends agree with it
+#[test]
+fn self_comparisons_are_what_the_program_computes() {
+    let cases = [
+        ("list", "==(push([Int](), 1), push([Int](), 1))", "1\n"),
+        ("string", "!=(\"ab\", \"ab\")", "0\n"),
+        ("tuple", "==((1, push([Char](), 'a')), (1, push([Char](), 'a')))", "1\n"),
+        ("int", ">(+(2, 3), +(2, 3))", "0\n"),
+    ];
+
+    for (name, comparison, expected) in cases {
+        let code = format!("main(): Int {{\n    if({comparison}, 1, 0)\n}}\n");
+        let always = if expected == "1\n" { "true" } else { "false" };
+
+        let warnings = check(&code);
+        assert_eq!(lints(&warnings), [("self_comparison", 2)], "{comparison}");
+        assert!(warnings[0].message.ends_with(&format!("so it is always {always}")), "{comparison}");
+        assert_eq!(outputs(name, &code), (expected.to_string(), expected.to_string()), "{comparison}");
+    }
+}
+
+#[test]
+fn examples_have_no_warnings() {
+    for entry in std::fs::read_dir("test").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "flip") {
+            let warnings = check(&std::fs::read_to_string(&path).unwrap());
+            assert!(warnings.is_empty(), "{}: {warnings:?}", path.display());
+        }
+    }
+}
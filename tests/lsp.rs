@@ -0,0 +1,156 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use flip::json::Json;
+
+const FLIP: &str = env!("CARGO_BIN_EXE_flip");
+
+const DOCUMENT: &str = "file:///main.flip";
+
+// Sends the messages to "flip lsp", ending the session, and returns every
+// message it sent back
+fn session(messages: &[Json]) -> Vec<Json> {
+    let mut server = Command::new(FLIP).arg("lsp").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+
+    let mut input = String::new();
+    let end = [request(99, "shutdown", Json::Null), notification("exit", Json::Null)];
+    for message in messages.iter().chain(end.iter()) {
+        let body = message.to_string();
+        input.push_str(&format!("Content-Length: {}\r\n\r\n{body}", body.len()));
+    }
+    server.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+    let output = server.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let mut out = String::from_utf8(output.stdout).unwrap();
+    let mut replies = Vec::new();
+    while let Some((header, rest)) = out.split_once("\r\n\r\n") {
+        let length: usize = header.trim_start_matches("Content-Length: ").parse().unwrap();
+        replies.push(Json::parse(&rest[..length]).unwrap());
+        out = rest[length..].to_string();
+    }
+    replies
+}
+
+fn request(id: i64, method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", "2.0".into()),
+        ("id", Json::Number(id as f64)),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![("jsonrpc", "2.0".into()), ("method", method.into()), ("params", params)])
+}
+
+fn open(text: &str) -> Json {
+    let document = Json::object(vec![("uri", DOCUMENT.into()), ("languageId", "flip".into()), ("text", text.into())]);
+    notification("textDocument/didOpen", Json::object(vec![("textDocument", document)]))
+}
+
+// LSP positions start at 0
+fn at(id: i64, method: &str, line: usize, character: usize) -> Json {
+    let position = Json::object(vec![("line", line.into()), ("character", character.into())]);
+    let document = Json::object(vec![("uri", DOCUMENT.into())]);
+    request(id, method, Json::object(vec![("textDocument", document), ("position", position)]))
+}
+
+fn reply(replies: &[Json], id: i64) -> &Json {
+    let id = Json::Number(id as f64);
+    let reply = replies.iter().find(|reply| reply.get("id") == Some(&id)).unwrap();
+    reply.get("result").unwrap()
+}
+
+fn diagnostics(replies: &[Json]) -> Vec<&Json> {
+    let published = replies
+        .iter()
+        .find(|reply| reply.get("method").and_then(Json::as_str) == Some("textDocument/publishDiagnostics"))
+        .unwrap();
+    match published.path(&["params", "diagnostics"]) {
+        Some(Json::Array(diagnostics)) => diagnostics.iter().collect(),
+        _ => panic!("diagnostics are not a list"),
+    }
+}
+
+fn hover_text(replies: &[Json], id: i64) -> &str {
+    reply(replies, id).path(&["contents", "value"]).and_then(Json::as_str).unwrap()
+}
+
+const CODE: &str = "\
+greet(name: [Char]): [Char] {
+    =(pair, (name, 1.5))
+    pair.0
+}
+
+main() {
+    greet(\"flip\")
+}
+";
+
+#[test]
+fn initialize_lists_the_capabilities() {
+    let replies = session(&[request(1, "initialize", Json::object(Vec::new()))]);
+
+    let capabilities = reply(&replies, 1).get("capabilities").unwrap();
+    assert_eq!(capabilities.get("hoverProvider"), Some(&Json::Bool(true)));
+    assert_eq!(capabilities.get("definitionProvider"), Some(&Json::Bool(true)));
+}
+
+#[test]
+fn hover_shows_flip_types() {
+    let replies = session(&[
+        open(CODE),
+        at(1, "textDocument/hover", 1, 7),
+        at(2, "textDocument/hover", 6, 6),
+        at(3, "textDocument/hover", 2, 5),
+    ]);
+
+    assert_eq!(hover_text(&replies, 1), "```flip\npair: ([Char], Float)\n```");
+    assert_eq!(hover_text(&replies, 2), "```flip\ngreet(name: [Char]): [Char]\n```");
+    assert_eq!(hover_text(&replies, 3), "```flip\npair: ([Char], Float)\n```");
+}
+
+#[test]
+fn hover_shows_the_types_of_destructured_bindings() {
+    let code = "main(): Int {\n    =((a, (b, c)), (1, ('x', 2.5)))\n    a\n}\n";
+    let replies = session(&[open(code), at(1, "textDocument/hover", 1, 12), at(2, "textDocument/hover", 1, 15)]);
+
+    assert_eq!(hover_text(&replies, 1), "```flip\nb: Char\n```");
+    assert_eq!(hover_text(&replies, 2), "```flip\nc: Float\n```");
+}
+
+#[test]
+fn hover_shows_built_in_signatures() {
+    let replies = session(&[open("main(): Int {\n    len(\"ab\")\n}\n"), at(1, "textDocument/hover", 1, 5)]);
+
+    assert_eq!(hover_text(&replies, 1), "```flip\nlen([T]): Int\n```");
+}
+
+#[test]
+fn definition_finds_functions_and_bindings() {
+    let replies = session(&[open(CODE), at(1, "textDocument/definition", 6, 5), at(2, "textDocument/definition", 2, 6)]);
+
+    let Json::Array(functions) = reply(&replies, 1) else { panic!("expected a list of locations") };
+    assert_eq!(functions.len(), 1);
+    assert_eq!(functions[0].path(&["range", "start", "line"]), Some(&Json::Number(0.0)));
+    assert_eq!(reply(&replies, 2).path(&["range", "start", "line"]), Some(&Json::Number(1.0)));
+    assert_eq!(reply(&replies, 2).path(&["range", "start", "character"]), Some(&Json::Number(6.0)));
+}
+
+#[test]
+fn diagnostics_report_errors_and_warnings() {
+    let errors = session(&[open("main(): Int {\n    +(1, true)\n}\n")]);
+    let errors = diagnostics(&errors);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].get("severity"), Some(&Json::Number(1.0)));
+    assert_eq!(errors[0].path(&["range", "start", "line"]), Some(&Json::Number(1.0)));
+
+    let warnings = session(&[open("main(): Int {\n    =(unused, 1)\n    2\n}\n")]);
+    let warnings = diagnostics(&warnings);
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].get("severity"), Some(&Json::Number(2.0)));
+    assert_eq!(warnings[0].get("code").and_then(Json::as_str), Some("unused_binding"));
+}
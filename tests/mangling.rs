@@ -0,0 +1,56 @@
+use std::env;
+use std::fs;
+use std::process::{self, Command, Output};
+
+const FLIP: &str = env!("CARGO_BIN_EXE_flip");
+
+// Builds the program with the C back end and runs it
+fn run_c(name: &str, code: &str) -> Output {
+    let dir = env::temp_dir().join(format!("flip-mangling-{}-{name}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.flip");
+    let exe = dir.join("main");
+    fs::write(&source, code).unwrap();
+
+    let build = Command::new(FLIP).arg("build").arg(&source).arg("-o").arg(&exe).output().unwrap();
+    assert!(build.status.success(), "{}", String::from_utf8_lossy(&build.stderr));
+
+    let output = Command::new(&exe).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+#[test]
+fn identifiers_used_by_c_and_the_runtime_can_be_names() {
+    let output = run_c(
+        "names",
+        "sum(list: [Int], args: Int, size: Int): Int {
+            =(long, if(==(len(list), 0), 0, +(head(list), sum(tail(list), args, size))))
+            +(long, *(args, size))
+        }
+
+        main() {
+            sum(push(push([Int](), 1), 2), 3, 4)
+        }",
+    );
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "39\n");
+}
+
+#[test]
+fn runtime_errors_name_the_flip_function() {
+    let output = run_c(
+        "errors",
+        "nth(list: [Int], size: Int): Int {
+            if(==(size, 0), head(list), nth(tail(list), -(size, 1)))
+        }
+
+        main() {
+            nth([Int](), 2)
+        }",
+    );
+
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Error in nth: tail of an empty list\n");
+}
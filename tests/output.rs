@@ -0,0 +1,98 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+
+use flip::{compiler, parser, scanner};
+
+const FLIP: &str = env!("CARGO_BIN_EXE_flip");
+
+const PROGRAM: &str = "\
+double(n: Int): Int {
+    *(n, 2)
+}
+
+main() {
+    double(21)
+}
+";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("flip-output-{name}-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Builds C source with the C compiler and runs it
+fn run_c(dir: &Path, code: &str) -> Output {
+    fs::write(dir.join("out.c"), code).unwrap();
+    let cc = env::var("CC").unwrap_or(String::from("cc"));
+    let status = Command::new(cc).arg(dir.join("out.c")).arg("-o").arg(dir.join("out")).arg("-lm").status().unwrap();
+    assert!(status.success());
+    Command::new(dir.join("out")).output().unwrap()
+}
+
+fn flip(dir: &Path, args: &[&str]) -> Output {
+    fs::write(dir.join("main.flip"), PROGRAM).unwrap();
+    // Run in "dir", so a build/ directory would be counted as a file there
+    Command::new(FLIP).current_dir(dir).args(args).arg("main.flip").output().unwrap()
+}
+
+#[test]
+fn compile_program_returns_standalone_c() {
+    let mut symbols = compiler::table_from_inlines();
+    let tree = parser::build_ast(scanner::get_tokens(PROGRAM), &mut symbols);
+    let code = compiler::compile_program(&tree, &mut symbols);
+
+    let dir = temp_dir("library");
+    let run = run_c(&dir, &code);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "42\n");
+}
+
+#[test]
+fn dash_writes_c_to_stdout() {
+    let dir = temp_dir("c");
+    let output = flip(&dir, &["-o", "-"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let run = run_c(&dir, &String::from_utf8_lossy(&output.stdout));
+    let files = fs::read_dir(&dir).unwrap().count();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "42\n");
+    // Only main.flip and what the test built from stdout, so no build/out.c
+    assert_eq!(files, 3);
+}
+
+#[test]
+fn dash_writes_rust_to_stdout() {
+    let dir = temp_dir("rust");
+    let output = flip(&dir, &["build", "--target", "rust", "-o", "-"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    fs::write(dir.join("gen.rs"), &output.stdout).unwrap();
+    fs::write(dir.join("main.rs"), "mod gen;\n\nfn main() {\n    println!(\"{}\", gen::main());\n}\n").unwrap();
+    let status = Command::new("rustc")
+        .args(["--edition", "2015", "-A", "warnings"])
+        .arg(dir.join("main.rs"))
+        .arg("-o")
+        .arg(dir.join("main"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let run = Command::new(dir.join("main")).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "42\n");
+}
+
+#[test]
+fn executables_cannot_go_to_stdout() {
+    let dir = temp_dir("exe");
+    let output = flip(&dir, &["build", "-o", "-"]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+}
@@ -0,0 +1,127 @@
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+use flip::ast::{self, ASTNode};
+use flip::error;
+use flip::overflow::CheckOverflow;
+use flip::passes::PassManager;
+use flip::symbols::SymbolTable;
+use flip::{bytecode, compiler, parser, scanner, vm};
+
+const FLIP: &str = env!("CARGO_BIN_EXE_flip");
+
+fn checked(code: &str) -> (Vec<ASTNode>, SymbolTable) {
+    let mut symbols = compiler::table_from_inlines();
+    let tree = parser::build_ast(scanner::get_tokens(code), &mut symbols);
+    let tree = PassManager::new().with(CheckOverflow).run(tree, &symbols);
+    (tree, symbols)
+}
+
+#[test]
+fn only_int_operators_are_checked() {
+    let (tree, _) = checked(
+        "main(): Int {
+            =(big, -(*(2n, 3n), 1n))
+            +(-(2), wrapping_mul(3, 4))
+        }",
+    );
+
+    assert_eq!(
+        ast::to_source(&tree),
+        "main(): Int {
+    =(big, -(*(2n, 3n), 1n))
+    checked_add(checked_neg(2), wrapping_mul(3, 4))
+}
+"
+    );
+}
+
+#[test]
+fn overflow_names_the_operation() {
+    let (tree, symbols) = checked(
+        "twice(n: Int): Int {
+            *(n, 2)
+        }
+
+        main() {
+            twice(4611686018427387904)
+        }",
+    );
+    let program = bytecode::compile_program(&tree, &symbols);
+
+    let error = error::catch(|| vm::run(&program)).unwrap_err();
+    assert_eq!(error.message, "integer overflow in *(n, 2)");
+    assert_eq!(error.function.as_deref(), Some("twice"));
+}
+
+#[test]
+fn overflow_names_the_line() {
+    let (tree, symbols) = checked(
+        "twice(n: Int): Int {
+            =(m, +(n, 1))
+            *(m, 2)
+        }
+
+        main() {
+            twice(4611686018427387904)
+        }",
+    );
+    let program = bytecode::compile_program(&tree, &symbols);
+
+    let error = error::catch(|| vm::run(&program)).unwrap_err();
+    assert_eq!(error.line, Some(3));
+    assert_eq!(error.to_string(), "Error, line 3: integer overflow in *(m, 2)");
+}
+
+// An operation written over several lines is reported on the line of its name
+const MULTI_LINE: &str = "main() {
+    +(1,
+      *(9223372036854775807,
+        2))
+}
+";
+
+#[test]
+fn overflow_names_the_line_of_the_call() {
+    let (tree, symbols) = checked(MULTI_LINE);
+    let program = bytecode::compile_program(&tree, &symbols);
+
+    let error = error::catch(|| vm::run(&program)).unwrap_err();
+    assert_eq!(error.to_string(), "Error, line 3: integer overflow in *(9223372036854775807, 2)");
+}
+
+#[test]
+fn both_back_ends_name_the_line_of_the_call() {
+    let dir = env::temp_dir().join(format!("flip-overflow-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.flip");
+    let exe = dir.join("main");
+    fs::write(&source, MULTI_LINE).unwrap();
+
+    let build = Command::new(FLIP).args(["build", "--checked"]).arg(&source).arg("-o").arg(&exe).output().unwrap();
+    assert!(build.status.success(), "{}", String::from_utf8_lossy(&build.stderr));
+    let c = Command::new(&exe).output().unwrap();
+    let vm = Command::new(FLIP).args(["run", "--checked"]).arg(&source).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    for output in [c, vm] {
+        assert!(!output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "Error, line 3: integer overflow in *(9223372036854775807, 2)\n"
+        );
+    }
+}
+
+#[test]
+fn wrapping_operations_wrap() {
+    let (tree, symbols) = checked(
+        "main() {
+            wrapping_add(9223372036854775807, wrapping_neg(wrapping_sub(0, 1)))
+        }",
+    );
+    let program = bytecode::compile_program(&tree, &symbols);
+
+    assert_eq!(vm::run(&program).to_string(), "-9223372036854775808");
+}
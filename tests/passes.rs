@@ -0,0 +1,103 @@
+use flip::ast::{self, walk_node_mut, ASTNode, Fold, Line, VisitorMut};
+use flip::passes::{Pass, PassManager};
+use flip::symbols::SymbolTable;
+use flip::{compiler, parser, scanner};
+
+// Folds additions of two literals
+struct ConstantFold;
+
+impl VisitorMut for ConstantFold {
+    fn visit_node(&mut self, node: &mut ASTNode) {
+        walk_node_mut(self, node);
+        if let ASTNode::Call(name, args, _) = node {
+            if let [ASTNode::Int(a), ASTNode::Int(b)] = args[..] {
+                if name == "+" {
+                    *node = ASTNode::Int(a.wrapping_add(b));
+                }
+            }
+        }
+    }
+}
+
+impl Pass for ConstantFold {
+    fn name(&self) -> &str {
+        "constant-fold"
+    }
+
+    fn run(&mut self, mut tree: Vec<ASTNode>, _symbols: &SymbolTable) -> Vec<ASTNode> {
+        ast::walk_body_mut(self, &mut tree);
+        tree
+    }
+}
+
+// Swaps the branches of every if with a negated condition
+struct RemoveNot;
+
+impl Fold for RemoveNot {
+    fn fold_call(&mut self, name: String, args: Vec<ASTNode>, line: Line) -> ASTNode {
+        let mut args = ast::walk_fold_body(self, args);
+        if name == "if" {
+            if let ASTNode::Call(inner, cond, _) = &args[0] {
+                if inner == "not" {
+                    args[0] = cond[0].clone();
+                    args.swap(1, 2);
+                }
+            }
+        }
+        ASTNode::Call(name, args, line)
+    }
+}
+
+impl Pass for RemoveNot {
+    fn name(&self) -> &str {
+        "remove-not"
+    }
+
+    fn run(&mut self, tree: Vec<ASTNode>, _symbols: &SymbolTable) -> Vec<ASTNode> {
+        ast::walk_fold_body(self, tree)
+    }
+}
+
+fn parse(code: &str) -> (Vec<ASTNode>, SymbolTable) {
+    let mut symbols = compiler::table_from_inlines();
+    let tree = parser::build_ast(scanner::get_tokens(code), &mut symbols);
+    (tree, symbols)
+}
+
+#[test]
+fn passes_run_in_order() {
+    let (tree, symbols) = parse(
+        "main() {
+            =(x, +(1, 2))
+            if(not(==(x, +(x, 0))), +(x, +(3, 4)), 0)
+        }",
+    );
+
+    let tree = PassManager::new().with(ConstantFold).with(RemoveNot).run(tree, &symbols);
+    let (expected, _) = parse(
+        "main() {
+            =(x, 3)
+            if(==(x, +(x, 0)), 0, +(x, 7))
+        }",
+    );
+
+    assert_eq!(ast::to_source(&tree), ast::to_source(&expected));
+}
+
+#[test]
+fn passes_stop_after_the_named_one() {
+    let (tree, symbols) = parse(
+        "main() {
+            if(not(true), +(1, 2), 0)
+        }",
+    );
+
+    let tree = PassManager::new().with(ConstantFold).with(RemoveNot).run_until(tree, &symbols, "constant-fold");
+    let (expected, _) = parse(
+        "main() {
+            if(not(true), 3, 0)
+        }",
+    );
+
+    assert_eq!(ast::to_source(&tree), ast::to_source(&expected));
+}
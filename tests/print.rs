@@ -0,0 +1,159 @@
+use std::fs;
+
+use flip::ast::{self, ASTNode};
+use flip::bigint::BigInt;
+use flip::{bytecode, compiler, inline, parser, scanner, vm};
+
+fn parse(code: &str) -> Vec<ASTNode> {
+    let mut symbols = compiler::table_from_inlines();
+    parser::build_ast(scanner::get_tokens(code), &mut symbols)
+}
+
+// Printing a tree and parsing the output must give the same tree back
+fn assert_round_trips(tree: &[ASTNode]) {
+    let source = ast::to_source(tree);
+    assert_eq!(parse(&source), tree, "printed as:\n{source}");
+}
+
+fn examples() -> Vec<String> {
+    let mut examples = Vec::new();
+
+    for entry in fs::read_dir("test").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "flip") {
+            examples.push(fs::read_to_string(&path).unwrap());
+        }
+    }
+
+    assert!(!examples.is_empty());
+    examples
+}
+
+#[test]
+fn examples_round_trip() {
+    for code in examples() {
+        assert_round_trips(&parse(&code));
+    }
+}
+
+#[test]
+fn inlined_examples_round_trip() {
+    for code in examples() {
+        let mut symbols = compiler::table_from_inlines();
+        let tree = parser::build_ast(scanner::get_tokens(&code), &mut symbols);
+        assert_round_trips(&inline::inline_calls(tree, &symbols, inline::DEFAULT_THRESHOLD));
+    }
+}
+
+#[test]
+fn literals_round_trip() {
+    let tree = parse(
+        "first(nums: [Int], fallback: Int): Int {
+            if(is_null(nums), fallback, head(nums))
+        }
+
+        main() {
+            =(flag, and(true, not(false)))
+            if(flag, first([Int](), -(5)), 9223372036854775807)
+        }",
+    );
+
+    assert_round_trips(&tree);
+    assert!(ast::to_source(&tree).contains("9223372036854775807"));
+}
+
+// Passes can make negative numbers, which have no literals
+#[test]
+fn negative_numbers_print_as_calls() {
+    let cases = [
+        (ASTNode::Int(-5), "-(5)", "-5"),
+        (ASTNode::Int(i64::MIN), "-(-(9223372036854775807), 1)", "-9223372036854775808"),
+        (ASTNode::BigInt(BigInt::from_i64(-12)), "-(12n)", "-12"),
+        (ASTNode::Float(-0.5), "-(0.5)", "-0.5"),
+    ];
+
+    for (node, source, value) in cases {
+        assert_eq!(node.to_string(), source);
+
+        let mut symbols = compiler::table_from_inlines();
+        let tree = parser::build_ast(scanner::get_tokens(&format!("main() {{\n    {source}\n}}")), &mut symbols);
+        assert_eq!(vm::run(&bytecode::compile_program(&tree, &symbols)).to_string(), value);
+    }
+}
+
+#[test]
+fn float_literals_round_trip() {
+    let tree = parse(
+        "main() {
+            +(*(1.5, 2e-3), -(6.02E23, 1e16))
+        }",
+    );
+
+    assert_round_trips(&tree);
+    assert_eq!(ast::to_source(&tree), "main() {\n    +(*(1.5, 0.002), -(6.02e23, 1e16))\n}\n");
+}
+
+#[test]
+fn char_literals_round_trip() {
+    let tree = parse(
+        "main() {
+            push(push(\"ab\", '\\n'), '\\'')
+        }",
+    );
+
+    assert_round_trips(&tree);
+    assert_eq!(
+        ast::to_source(&tree),
+        "main() {\n    push(push(\"ab\", '\\n'), '\\'')\n}\n"
+    );
+}
+
+#[test]
+fn strings_round_trip() {
+    let tree = parse(
+        "main() {
+            \"say \\\"hi\\\"\\n\"
+        }",
+    );
+
+    assert_round_trips(&tree);
+    assert_eq!(ast::to_source(&tree), "main() {\n    \"say \\\"hi\\\"\\n\"\n}\n");
+}
+
+#[test]
+fn tuples_round_trip() {
+    let tree = parse(
+        "swap(pair: (Int, [Char])): ([Char], Int) {
+            (pair.1, fst(pair))
+        }
+
+        main() {
+            =((a, (b, c)), (swap((1, \"x\")), (2, 3)))
+            +(a.1, *(b, c))
+        }",
+    );
+
+    assert_round_trips(&tree);
+    assert_eq!(
+        ast::to_source(&tree[1..]),
+        "main() {\n    =((a, (b, c)), (swap((1, \"x\")), (2, 3)))\n    +(a.1, *(b, c))\n}\n"
+    );
+}
+
+#[test]
+fn records_round_trip() {
+    let tree = parse(
+        "struct Point { x: Int, y: Int }
+
+        main() {
+            =(p, Point { y: 2, x: 1 })
+            +(p with { y: *(p.x, 3) }.y, p.x)
+        }",
+    );
+
+    assert_round_trips(&tree);
+    assert_eq!(
+        ast::to_source(&tree),
+        "struct Point { x: Int, y: Int }\n\nmain() {\n    =(p, Point { x: 1, y: 2 })\n    +(p with { y: *(p.x, 3) }.y, p.x)\n}\n"
+    );
+}
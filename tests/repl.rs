@@ -0,0 +1,58 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const FLIP: &str = env!("CARGO_BIN_EXE_flip");
+
+// Types the lines into "flip repl" and returns what it printed
+fn session(lines: &[&str]) -> String {
+    let mut repl = Command::new(FLIP).arg("repl").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    repl.stdin.take().unwrap().write_all(format!("{}\n", lines.join("\n")).as_bytes()).unwrap();
+
+    let output = repl.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn results_are_shown_with_their_types() {
+    let output = session(&[
+        "double(x: Int): Int {",
+        "    *(x, 2)",
+        "}",
+        "=(name, \"flip\")",
+        "double(21)",
+        "name",
+        "(double(2), 1.5)",
+    ]);
+
+    assert_eq!(
+        output,
+        "Flip REPL, type :help for help\n> . . > > 42 : Int\n> \"flip\" : [Char]\n> (4, 1.5) : (Int, Float)\n> \n"
+    );
+}
+
+#[test]
+fn types_and_trees_are_shown_as_flip_code() {
+    let output = session(&[
+        "=(pair, (\"a\", [Int]()))",
+        ":type pair",
+        ":type push(pair.0, 'b')",
+        ":ast if(is_null(pair.1), -(3), head(pair.1))",
+        ":quit",
+    ]);
+
+    assert_eq!(
+        output,
+        "Flip REPL, type :help for help\n> > ([Char], [Int])\n> [Char]\n> if(is_null(pair.1), -(3), head(pair.1))\n> "
+    );
+}
+
+#[test]
+fn errors_do_not_end_the_session() {
+    let output = session(&["head([Int]())", ":nope", "+(1, 2)"]);
+
+    assert_eq!(
+        output,
+        "Flip REPL, type :help for help\n> Error in <input>: head of an empty list\n> Error: Unknown command :nope, try :help\n> 3 : Int\n> \n"
+    );
+}
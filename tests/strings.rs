@@ -0,0 +1,28 @@
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+const FLIP: &str = env!("CARGO_BIN_EXE_flip");
+
+// A string literal is one node however long it is, so nothing recurses once
+// for each of its characters
+#[test]
+fn long_string_literals_build_and_run() {
+    let dir = env::temp_dir().join(format!("flip-strings-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.flip");
+    let exe = dir.join("main");
+    let text = "ab\\\"".repeat(25000);
+    fs::write(&source, format!("main(): Int {{\n    len(\"{text}\")\n}}\n")).unwrap();
+
+    let build = Command::new(FLIP).arg("build").arg(&source).arg("-o").arg(&exe).output().unwrap();
+    assert!(build.status.success(), "{}", String::from_utf8_lossy(&build.stderr));
+    let c = Command::new(&exe).output().unwrap();
+    let vm = Command::new(FLIP).arg("run").arg(&source).output().unwrap();
+    let rust = Command::new(FLIP).args(["build", "--target", "rust"]).arg(&source).arg("-o").arg(dir.join("main.rs")).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&c.stdout), "75000\n");
+    assert_eq!(String::from_utf8_lossy(&vm.stdout), "75000\n");
+    assert!(rust.status.success(), "{}", String::from_utf8_lossy(&rust.stderr));
+}
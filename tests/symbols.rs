@@ -0,0 +1,33 @@
+use flip::ast::{ASTNode, NodeType};
+use flip::symbols::{Symbol, SymbolTable};
+
+#[test]
+fn inner_scopes_shadow_outer_ones() {
+    let mut symbols = SymbolTable::from(vec![Symbol::new_var("x", NodeType::Int)]);
+
+    symbols.push_scope();
+    symbols.insert(Symbol::new_var("x", NodeType::Bool));
+    assert_eq!(symbols.lookup_var("x").unwrap().symbol_type, NodeType::Bool);
+
+    symbols.pop_scope();
+    assert_eq!(symbols.lookup_var("x").unwrap().symbol_type, NodeType::Int);
+    assert!(symbols.lookup_var("y").is_none());
+}
+
+#[test]
+fn overloads_resolve_by_argument_types() {
+    let mut symbols = SymbolTable::from(vec![
+        Symbol::new_fn("f", vec![NodeType::Int], NodeType::Int),
+        Symbol::new_fn("f", vec![NodeType::Bool], NodeType::Bool),
+    ]);
+    symbols.push_scope();
+    symbols.insert(Symbol::new_var("f", NodeType::Int));
+
+    assert_eq!(symbols.overloads("f").count(), 2);
+    assert_eq!(symbols.get_return_type("f", &[ASTNode::Int(1)]), NodeType::Int);
+    assert_eq!(symbols.get_return_type("f", &[ASTNode::Bool(true)]), NodeType::Bool);
+    assert!(!symbols.check_types("f", &[]));
+
+    symbols.remove_fn("f", &[NodeType::Int]);
+    assert_eq!(symbols.overloads("f").count(), 1);
+}
@@ -0,0 +1,34 @@
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+const FLIP: &str = env!("CARGO_BIN_EXE_flip");
+
+#[test]
+fn failing_tests_are_named_and_exit_with_1() {
+    let output = Command::new(FLIP).args(["test", "test/failing_tests.flip"]).output().unwrap();
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "test squares ... ok
+test wrong square ... FAILED
+    line 15: assert_eq failed: 9 != 6
+test false statement ... FAILED
+    line 20: expression was false
+
+1 passed, 2 failed
+"
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn passing_tests_exit_with_0() {
+    let source = env::temp_dir().join(format!("flip-passing-tests-{}.flip", process::id()));
+    fs::write(&source, "test \"addition\" {\n    assert_eq(+(1, 2), 3)\n}\n").unwrap();
+    let output = Command::new(FLIP).arg("test").arg(&source).output().unwrap();
+    fs::remove_file(&source).unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "test addition ... ok\n\n1 passed, 0 failed\n");
+    assert!(output.status.success());
+}
@@ -0,0 +1,92 @@
+use flip::bytecode::{Chunk, Instr, Program};
+use flip::error;
+use flip::{bytecode, compiler, parser, scanner, vm};
+
+fn run(code: &str) -> String {
+    let mut symbols = compiler::table_from_inlines();
+    let tree = parser::build_ast(scanner::get_tokens(code), &mut symbols);
+    let program = bytecode::compile_program(&tree, &symbols);
+    vm::run(&program).to_string()
+}
+
+#[test]
+fn calls_run_the_overload_matching_their_arguments() {
+    let result = run(
+        "describe(x: Bool): Int {
+            if(x, 10, 20)
+        }
+
+        describe(x: Int): Int {
+            +(x, 1)
+        }
+
+        main() {
+            +(describe(1), describe(true))
+        }",
+    );
+
+    assert_eq!(result, "12");
+}
+
+#[test]
+fn unsupported_operands_are_shown_as_flip_values() {
+    let error = error::catch(|| {
+        run("bigger(a: T, b: T): Bool {
+            >(a, b)
+        }
+
+        main() {
+            if(bigger(push([Int](), 1), [Int]()), 1, 0)
+        }")
+    })
+    .unwrap_err();
+
+    assert_eq!(error.message, "cannot apply > to [1] and []");
+    assert_eq!(error.function.as_deref(), Some("bigger"));
+}
+
+// The type checker rules these out, so the program is built by hand
+#[test]
+fn element_errors_show_flip_values() {
+    let chunk = Chunk { name: String::from("main"), binding: None, slots: 0, code: vec![Instr::EmptyList, Instr::Index(1)] };
+    let program = Program { chunks: vec![chunk], main: Some(0), big_ints: Vec::new(), strings: Vec::new(), errors: Vec::new() };
+
+    let error = error::catch(|| vm::run(&program)).unwrap_err();
+    assert_eq!(error.message, "cannot take element 1 of []");
+    assert_eq!(error.function.as_deref(), Some("main"));
+}
+
+#[test]
+fn locals_shadow_built_ins_unless_called() {
+    let result = run(
+        "scale(int: Int, len: Int): Int {
+            =(head, *(int, len))
+            +(head, len(push([Int](), int)))
+        }
+
+        main() {
+            scale(3, 4)
+        }",
+    );
+
+    assert_eq!(result, "13");
+}
+
+#[test]
+fn with_is_only_a_keyword_before_a_brace() {
+    let result = run(
+        "struct P { x: Int, y: Int }
+
+        shift(with: Int, p: P): P {
+            p with { x: +(p.x, with) }
+        }
+
+        main() {
+            =(with, 2)
+            =(q, shift(with, P { x: 1, y: with }))
+            +(q.x, q.y)
+        }",
+    );
+
+    assert_eq!(result, "5");
+}